@@ -56,7 +56,7 @@ mod test {
     fn test_serialize_date() {
         let date = Utc.timestamp(150, 200);
 
-        let result = serialize(&date, ValueSerializer).unwrap();
+        let result = serialize(&date, ValueSerializer::new()).unwrap();
 
         assert_eq!(
             Value {
@@ -68,7 +68,7 @@ mod test {
             result
         );
 
-        assert_eq!(date, deserialize(&mut ValueDeserializer(&result)).unwrap());
+        assert_eq!(date, deserialize(&mut ValueDeserializer::new(&result)).unwrap());
     }
 
     #[test]
@@ -100,4 +100,25 @@ mod test {
 
         assert_eq!(st, from_grpc_value(&result).unwrap());
     }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct StructWithFlattenedDate {
+        name: String,
+        #[serde(flatten)]
+        inner: StructWithDate,
+    }
+
+    #[test]
+    fn test_serialize_in_flattened_struct() {
+        let st = StructWithFlattenedDate {
+            name: "blah".to_string(),
+            inner: StructWithDate {
+                date: Utc.timestamp(150, 200),
+            },
+        };
+
+        let result = to_grpc_value(&st).unwrap();
+
+        assert_eq!(st, from_grpc_value(&result).unwrap());
+    }
 }
@@ -0,0 +1,231 @@
+//! A canonical protobuf encoding of `Value`s and `Document`s, in which map
+//! fields are written in order of their keys rather than in the (random)
+//! iteration order of their `HashMap`. Equal values therefore always encode
+//! to identical bytes, which makes the encoding suitable for content hashes
+//! and snapshot tests. It decodes like any other encoding of the message.
+//!
+//! Doubles are encoded by their bits, so `0.0` and `-0.0` (which compare
+//! equal) encode differently, as do NaNs with different payloads.
+
+use crate::firestore::{value::ValueType, ArrayValue, Document, MapValue, Value};
+use crate::wire::{
+    ARRAY_VALUES, DOCUMENT_CREATE_TIME, DOCUMENT_FIELDS, DOCUMENT_NAME, DOCUMENT_UPDATE_TIME,
+    ENTRY_KEY, ENTRY_VALUE, MAP_FIELDS, VALUE_ARRAY, VALUE_MAP,
+};
+use bytes::BytesMut;
+use prost::encoding::{
+    encode_key, encode_varint, encoded_len_varint, key_len, message, string, WireType,
+};
+use prost::Message;
+use std::collections::HashMap;
+use std::hash::Hasher;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Writes the key and length of a message field.
+fn encode_header(tag: u32, len: usize, buf: &mut BytesMut) {
+    encode_key(tag, WireType::LengthDelimited, buf);
+    encode_varint(len as u64, buf);
+}
+
+/// The encoded length of a map entry, which leaves out an empty key and a
+/// value with no type like prost does.
+fn entry_len(key: &str, value: &Value) -> usize {
+    let key_part = if key.is_empty() {
+        0
+    } else {
+        key_len(ENTRY_KEY) + encoded_len_varint(key.len() as u64) + key.len()
+    };
+    let value_part = if value.value_type.is_none() {
+        0
+    } else {
+        message::encoded_len(ENTRY_VALUE, value)
+    };
+
+    key_part + value_part
+}
+
+fn encode_fields(tag: u32, fields: &HashMap<String, Value>, buf: &mut BytesMut) {
+    let mut entries: Vec<(&String, &Value)> = fields.iter().collect();
+    entries.sort_unstable_by_key(|(key, _)| *key);
+
+    for (key, value) in entries {
+        encode_header(tag, entry_len(key, value), buf);
+
+        if !key.is_empty() {
+            string::encode(ENTRY_KEY, key, buf);
+        }
+        if value.value_type.is_some() {
+            encode_header(ENTRY_VALUE, value.encoded_len(), buf);
+            encode_value(value, buf);
+        }
+    }
+}
+
+/// Appends the canonical encoding of `value` to `buf`.
+pub fn encode_value(value: &Value, buf: &mut BytesMut) {
+    match &value.value_type {
+        Some(ValueType::ArrayValue(array)) => {
+            encode_header(VALUE_ARRAY, array.encoded_len(), buf);
+
+            let ArrayValue { values } = array;
+            for value in values {
+                encode_header(ARRAY_VALUES, value.encoded_len(), buf);
+                encode_value(value, buf);
+            }
+        }
+        Some(ValueType::MapValue(map)) => {
+            encode_header(VALUE_MAP, map.encoded_len(), buf);
+
+            let MapValue { fields } = map;
+            encode_fields(MAP_FIELDS, fields, buf);
+        }
+        // Other values contain no maps, so they only have one encoding.
+        _ => value
+            .encode(buf)
+            .expect("A BytesMut grows to fit the message."),
+    }
+}
+
+/// Appends the canonical encoding of `document` to `buf`.
+pub fn encode_document(document: &Document, buf: &mut BytesMut) {
+    if !document.name.is_empty() {
+        string::encode(DOCUMENT_NAME, &document.name, buf);
+    }
+
+    encode_fields(DOCUMENT_FIELDS, &document.fields, buf);
+
+    if let Some(create_time) = &document.create_time {
+        message::encode(DOCUMENT_CREATE_TIME, create_time, buf);
+    }
+    if let Some(update_time) = &document.update_time {
+        message::encode(DOCUMENT_UPDATE_TIME, update_time, buf);
+    }
+}
+
+/// The canonical encoding of `value`.
+pub fn value_to_vec(value: &Value) -> Vec<u8> {
+    let mut buf = BytesMut::with_capacity(value.encoded_len());
+    encode_value(value, &mut buf);
+    buf.to_vec()
+}
+
+/// The canonical encoding of `document`.
+pub fn document_to_vec(document: &Document) -> Vec<u8> {
+    let mut buf = BytesMut::with_capacity(document.encoded_len());
+    encode_document(document, &mut buf);
+    buf.to_vec()
+}
+
+/// Feeds the canonical encoding of `value` to `state`, e.g. to implement
+/// `Hash` for a type containing a `Value`.
+pub fn hash_value<H: Hasher>(value: &Value, state: &mut H) {
+    state.write(&value_to_vec(value));
+}
+
+/// Feeds the canonical encoding of `document` to `state`.
+pub fn hash_document<H: Hasher>(document: &Document, state: &mut H) {
+    state.write(&document_to_vec(document));
+}
+
+/// The 64-bit FNV-1a hash of `bytes`. Unlike `DefaultHasher`, its output is
+/// the same on every platform and Rust release, so digests can be stored.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// A stable 64-bit digest of the canonical encoding of `value`, for use as
+/// a cache key. It is not a cryptographic hash; for one, hash the output of
+/// `value_to_vec` instead.
+pub fn value_digest(value: &Value) -> u64 {
+    fnv1a(&value_to_vec(value))
+}
+
+/// A stable 64-bit digest of the canonical encoding of `document`, like
+/// `value_digest`.
+pub fn document_digest(document: &Document) -> u64 {
+    fnv1a(&document_to_vec(document))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{to_document, to_grpc_value};
+    use prost_types::Timestamp;
+    use serde::Serialize;
+
+    fn document_with_keys(keys: impl Iterator<Item = usize>) -> Document {
+        #[derive(Serialize)]
+        struct Entry {
+            tags: HashMap<String, Vec<u8>>,
+            empty: Option<u8>,
+        }
+
+        let keys: Vec<String> = keys.map(|k| format!("key{}", k)).collect();
+        let fields: HashMap<String, Entry> = keys
+            .iter()
+            .map(|k| {
+                let entry = Entry {
+                    tags: keys.iter().map(|t| (t.clone(), vec![1, 2])).collect(),
+                    empty: None,
+                };
+                (k.clone(), entry)
+            })
+            .collect();
+
+        let mut document = to_document(&fields).unwrap();
+        document.name = "projects/p/databases/(default)/documents/things/a".to_string();
+        document.update_time = Some(Timestamp {
+            seconds: 10,
+            nanos: 20,
+        });
+        document
+    }
+
+    #[test]
+    fn test_equal_documents_encode_identically() {
+        let a = document_with_keys(0..20);
+        let b = document_with_keys((0..20).rev());
+        assert_eq!(a, b);
+
+        let bytes = document_to_vec(&a);
+        assert_eq!(bytes, document_to_vec(&b));
+        assert_eq!(document_digest(&a), document_digest(&b));
+
+        assert_eq!(a.encoded_len(), bytes.len());
+        assert_eq!(a, Document::decode(&bytes[..]).unwrap());
+    }
+
+    #[test]
+    fn test_keys_are_sorted() {
+        let map: HashMap<&str, u8> = vec![("b", 1), ("", 2), ("a", 3)].into_iter().collect();
+        let value = to_grpc_value(&vec![map]).unwrap();
+
+        let bytes = value_to_vec(&value);
+        let position = |needle: &[u8]| bytes.windows(needle.len()).position(|w| w == needle);
+
+        // Each key is encoded as field 1 of its entry, with a length of 1.
+        assert!(position(&[0x0a, 1, b'a']) < position(&[0x0a, 1, b'b']));
+        assert_eq!(value, Value::decode(&bytes[..]).unwrap());
+    }
+
+    #[test]
+    fn test_matches_prost_for_single_entries() {
+        // A map with at most one entry has only one encoding.
+        let single: HashMap<&str, Vec<&str>> = vec![("k", vec!["v"])].into_iter().collect();
+        let value = to_grpc_value(&single).unwrap();
+
+        assert_eq!(value.encode_to_vec(), value_to_vec(&value));
+    }
+
+    #[test]
+    fn test_digest_is_stable() {
+        assert_eq!(FNV_OFFSET_BASIS, document_digest(&Document::default()));
+        // Test vectors from the FNV reference implementation.
+        assert_eq!(0xaf63_dc4c_8601_ec8c, fnv1a(b"a"));
+        assert_eq!(0x8594_4171_f739_67e8, fnv1a(b"foobar"));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{EnumRepresentation, I128Fallback, U64Overflow};
+
+/// Options which control how Rust values are converted into `Value`s.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SerializerConfig {
+    pub(crate) enum_representation: EnumRepresentation,
+    pub(crate) unit_as_null: bool,
+    pub(crate) skip_none: bool,
+    pub(crate) u64_overflow: U64Overflow,
+    pub(crate) i128_fallback: I128Fallback,
+    pub(crate) validate: bool,
+}
+
+impl SerializerConfig {
+    pub fn new() -> Self {
+        SerializerConfig::default()
+    }
+
+    pub fn with_enum_representation(mut self, enum_representation: EnumRepresentation) -> Self {
+        self.enum_representation = enum_representation;
+        self
+    }
+
+    /// Store `()` and unit structs as `NullValue` instead of failing with
+    /// `SerializationError::Unrepresentable`.
+    pub fn with_unit_as_null(mut self, unit_as_null: bool) -> Self {
+        self.unit_as_null = unit_as_null;
+        self
+    }
+
+    /// Omit struct and map fields whose value is `None`, instead of storing
+    /// them as `NullValue`. `None` values in arrays are still stored as
+    /// `NullValue`, and missing fields are read back as `None`.
+    pub fn with_skip_none(mut self, skip_none: bool) -> Self {
+        self.skip_none = skip_none;
+        self
+    }
+
+    /// Choose how `u64` values above `i64::MAX` are stored, instead of
+    /// failing with `SerializationError::OutsideIntRange`.
+    pub fn with_u64_overflow(mut self, u64_overflow: U64Overflow) -> Self {
+        self.u64_overflow = u64_overflow;
+        self
+    }
+
+    /// Choose how `i128` and `u128` values outside the range of `i64` are
+    /// stored. By default, they are stored as decimal strings.
+    pub fn with_i128_fallback(mut self, i128_fallback: I128Fallback) -> Self {
+        self.i128_fallback = i128_fallback;
+        self
+    }
+
+    /// Check documents produced by `to_document_with` (and
+    /// `to_document_with_transforms`) against Firestore's limits, failing
+    /// with `SerializationError::InvalidDocument` if any are violated.
+    pub fn with_validation(mut self, validate: bool) -> Self {
+        self.validate = validate;
+        self
+    }
+}
+
+/// Options which control how `Value`s are converted into Rust values. These
+/// should mirror the `SerializerConfig` that the values were written with.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DeserializerConfig {
+    pub(crate) enum_representation: EnumRepresentation,
+    pub(crate) unit_as_null: bool,
+    pub(crate) u64_overflow: U64Overflow,
+    pub(crate) i128_fallback: I128Fallback,
+}
+
+impl DeserializerConfig {
+    pub fn new() -> Self {
+        DeserializerConfig::default()
+    }
+
+    pub fn with_enum_representation(mut self, enum_representation: EnumRepresentation) -> Self {
+        self.enum_representation = enum_representation;
+        self
+    }
+
+    /// Read `NullValue` as `()` and unit structs instead of failing with
+    /// `DeserializationError::Unrepresentable`.
+    pub fn with_unit_as_null(mut self, unit_as_null: bool) -> Self {
+        self.unit_as_null = unit_as_null;
+        self
+    }
+
+    /// Read `u64` values stored with the given policy, in addition to
+    /// non-negative integers.
+    pub fn with_u64_overflow(mut self, u64_overflow: U64Overflow) -> Self {
+        self.u64_overflow = u64_overflow;
+        self
+    }
+
+    /// Read `i128` and `u128` values stored with the given fallback, in
+    /// addition to integers.
+    pub fn with_i128_fallback(mut self, i128_fallback: I128Fallback) -> Self {
+        self.i128_fallback = i128_fallback;
+        self
+    }
+}
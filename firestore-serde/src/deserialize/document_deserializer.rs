@@ -0,0 +1,125 @@
+use super::{DeserializationError, Fields, MapValueSeq, Result, ValueRef};
+use crate::firestore::{Document, Value};
+use crate::metadata::DocumentMetadata;
+use crate::DeserializerConfig;
+use serde::{forward_to_deserialize_any, Deserializer};
+use std::borrow::Cow;
+
+/// Deserializes the fields of a `Document` as a map. Structs which ask for
+/// the metadata field names (see `crate::metadata`) receive the document's
+/// metadata under those names.
+pub(crate) struct DocumentDeserializer<'de> {
+    fields: Fields<'de, Value>,
+    metadata: DocumentMetadata<'de>,
+    config: DeserializerConfig,
+}
+
+impl<'de> DocumentDeserializer<'de> {
+    /// A deserializer which lends strings and bytes out of `document`.
+    pub fn borrowed(document: &'de Document, config: DeserializerConfig) -> Self {
+        DocumentDeserializer {
+            fields: Fields::Borrowed(
+                document
+                    .fields
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v))
+                    .collect(),
+            ),
+            metadata: DocumentMetadata::new(document),
+            config,
+        }
+    }
+
+    /// A deserializer which consumes `document`, moving its strings and
+    /// bytes into the result instead of cloning them.
+    pub fn owned(document: Document, config: DeserializerConfig) -> Self {
+        DocumentDeserializer {
+            metadata: DocumentMetadata {
+                name: Cow::Owned(document.name),
+                create_time: document.create_time,
+                update_time: document.update_time,
+            },
+            fields: Fields::Owned(document.fields),
+            config,
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for DocumentDeserializer<'de> {
+    type Error = DeserializationError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        visitor.visit_map(MapValueSeq::new(self.fields.into_entries(), self.config))
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        let document_fields = self.fields;
+        let metadata = self
+            .metadata
+            .into_fields(fields)
+            .into_iter()
+            .filter(|(k, _)| document_fields.get(k).is_none())
+            .map(|(k, v)| (Cow::Borrowed(k), ValueRef::Owned(v)))
+            .collect();
+
+        visitor.visit_map(
+            MapValueSeq::new(document_fields.into_entries(), self.config).with_extra(metadata),
+        )
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        self.fields.visit_enum(self.config, visitor)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct identifier
+    }
+}
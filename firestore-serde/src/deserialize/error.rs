@@ -1,14 +1,44 @@
-use crate::firestore::Value;
+use crate::value_path::{PathSegment, ValuePath};
 use serde::de;
 use std::fmt::Display;
 
 #[derive(Debug, PartialEq)]
 pub enum DeserializationError {
     Message(String),
-    WrongType(&'static str, Value),
-    IntRange(&'static str, i64),
+    /// A value of the wrong kind, with the type which was expected and a
+    /// description of the value's kind.
+    WrongType(&'static str, &'static str),
+    /// An integer which doesn't fit in the expected type, with the type and
+    /// the value's decimal digits.
+    IntRange(&'static str, String),
     MissingField(&'static str),
     Unrepresentable(&'static str),
+    /// Bytes which aren't a valid protobuf encoding of the message being
+    /// read.
+    Malformed(String),
+    /// An error which occurred at the given location inside the value.
+    AtPath(ValuePath, Box<DeserializationError>),
+}
+
+impl DeserializationError {
+    /// The location of the value which caused the error, if it was nested
+    /// inside the value being deserialized.
+    pub fn path(&self) -> Option<&ValuePath> {
+        match self {
+            DeserializationError::AtPath(path, _) => Some(path),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn at(self, segment: PathSegment) -> Self {
+        match self {
+            DeserializationError::AtPath(mut path, error) => {
+                path.prepend(segment);
+                DeserializationError::AtPath(path, error)
+            }
+            error => DeserializationError::AtPath(ValuePath::new(segment), Box::new(error)),
+        }
+    }
 }
 
 impl Display for DeserializationError {
@@ -18,11 +48,9 @@ impl Display for DeserializationError {
             DeserializationError::MissingField(field) => {
                 writeln!(f, "Expected field {} in map, but didn't find it.", field)
             }
-            DeserializationError::WrongType(expected, got) => writeln!(
-                f,
-                "Tried to deserialize into {}, but got {:?}.",
-                expected, got
-            ),
+            DeserializationError::WrongType(expected, got) => {
+                writeln!(f, "expected {}, got {}", expected, got)
+            }
             DeserializationError::IntRange(typ, val) => writeln!(
                 f,
                 "Tried to convert to {}, but value {} is out of range.",
@@ -31,6 +59,10 @@ impl Display for DeserializationError {
             DeserializationError::Unrepresentable(typ) => {
                 writeln!(f, "Tried to deserialize {}, which is unrepresentable.", typ)
             }
+            DeserializationError::Malformed(message) => {
+                writeln!(f, "Malformed protobuf encoding: {}", message)
+            }
+            DeserializationError::AtPath(path, error) => write!(f, "{}: {}", path, error),
         }
     }
 }
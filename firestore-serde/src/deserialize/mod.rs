@@ -1,44 +1,358 @@
-use crate::firestore::{value::ValueType, ArrayValue, MapValue, Value};
+#[cfg(feature = "googapis")]
+pub(crate) use document_deserializer::DocumentDeserializer;
 pub use error::{DeserializationError, Result};
 use prost::Message;
+use prost_types::Timestamp;
 use serde::{
-    de::{EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess},
+    de::{
+        value::MapAccessDeserializer, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+        VariantAccess,
+    },
     Deserializer,
 };
+use std::borrow::Cow;
+use std::collections::{hash_map, HashMap};
 use std::convert::TryFrom;
-
-use crate::{TYPE, VALUE, VALUES};
+#[cfg(feature = "googapis")]
+pub(crate) use wire_deserializer::{WireDeserializer, WireDocumentDeserializer};
+
+use crate::geopoint::LatLng;
+use crate::value_path::PathSegment;
+use crate::{
+    DeserializerConfig, EnumRepresentation, FirestoreValue, U64Overflow, ValueKind, GEOPOINT_MAGIC,
+    REFERENCE_MAGIC,
+};
 
 use self::{
     plain_byte_deserializer::PlainByteDeserializer,
     plain_string_deserializer::PlainStringDeserializer,
 };
 
+#[cfg(feature = "googapis")]
+mod document_deserializer;
 mod error;
 mod plain_byte_deserializer;
 mod plain_string_deserializer;
+#[cfg(feature = "googapis")]
+mod wire_deserializer;
+
+/// The value being deserialized. Strings and bytes are lent to the visitor
+/// out of a borrowed value, and moved into it out of an owned one.
+enum ValueRef<'de, F> {
+    Borrowed(&'de F),
+    Owned(F),
+}
+
+/// The contents of a string, reference, bytes, array or map value, taken
+/// out of a `ValueRef`.
+enum Contents<'de, F> {
+    Str(Cow<'de, str>),
+    Bytes(Cow<'de, [u8]>),
+    Elements(Elements<'de, F>),
+    Fields(Fields<'de, F>),
+}
+
+impl<'de, F: FirestoreValue> ValueRef<'de, F> {
+    fn value(&self) -> &F {
+        match self {
+            ValueRef::Borrowed(value) => value,
+            ValueRef::Owned(value) => value,
+        }
+    }
+
+    fn into_contents(self) -> Contents<'de, F> {
+        const PANIC_MESSAGE: &str =
+            "Should only be called on a string, reference, bytes, array or map value.";
+
+        match self {
+            ValueRef::Borrowed(value) => match value.kind() {
+                ValueKind::String(v) | ValueKind::Reference(v) => Contents::Str(Cow::Borrowed(v)),
+                ValueKind::Bytes(bytes) => Contents::Bytes(Cow::Borrowed(bytes)),
+                ValueKind::Array(values) => Contents::Elements(Elements::Borrowed(values.iter())),
+                ValueKind::Map(fields) => Contents::Fields(Fields::Borrowed(fields.collect())),
+                _ => panic!("{}", PANIC_MESSAGE),
+            },
+            // `kind` borrows the value, so it is only used to decide which
+            // contents to move out.
+            ValueRef::Owned(value) => {
+                if matches!(value.kind(), ValueKind::String(_) | ValueKind::Reference(_)) {
+                    value.into_string().map(|v| Contents::Str(Cow::Owned(v)))
+                } else if matches!(value.kind(), ValueKind::Bytes(_)) {
+                    value
+                        .into_bytes()
+                        .map(|bytes| Contents::Bytes(Cow::Owned(bytes)))
+                } else if matches!(value.kind(), ValueKind::Array(_)) {
+                    value
+                        .into_array()
+                        .map(|values| Contents::Elements(Elements::Owned(values.into_iter())))
+                } else {
+                    value
+                        .into_map()
+                        .map(|fields| Contents::Fields(Fields::Owned(fields)))
+                }
+                .expect(PANIC_MESSAGE)
+            }
+        }
+    }
+
+    /// The contents of a string or reference value.
+    fn into_str(self) -> Cow<'de, str> {
+        match self.into_contents() {
+            Contents::Str(v) => v,
+            _ => panic!("Should only be called on a string or reference value."),
+        }
+    }
+
+    /// The contents of a bytes value.
+    fn into_bytes(self) -> Cow<'de, [u8]> {
+        match self.into_contents() {
+            Contents::Bytes(bytes) => bytes,
+            _ => panic!("Should only be called on a bytes value."),
+        }
+    }
+
+    /// The elements of an array value.
+    fn into_elements(self) -> Elements<'de, F> {
+        match self.into_contents() {
+            Contents::Elements(values) => values,
+            _ => panic!("Should only be called on an array value."),
+        }
+    }
+
+    /// The fields of a map value.
+    fn into_fields(self) -> Fields<'de, F> {
+        match self.into_contents() {
+            Contents::Fields(fields) => fields,
+            _ => panic!("Should only be called on a map value."),
+        }
+    }
+}
+
+enum Elements<'de, F> {
+    Borrowed(std::slice::Iter<'de, F>),
+    Owned(std::vec::IntoIter<F>),
+}
+
+impl<'de, F> Iterator for Elements<'de, F> {
+    type Item = ValueRef<'de, F>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Elements::Borrowed(values) => values.next().map(ValueRef::Borrowed),
+            Elements::Owned(values) => values.next().map(ValueRef::Owned),
+        }
+    }
+}
+
+/// The fields of a map value or document.
+enum Fields<'de, F> {
+    Borrowed(Vec<(&'de str, &'de F)>),
+    Owned(HashMap<String, F>),
+}
+
+impl<'de, F: FirestoreValue> Fields<'de, F> {
+    fn len(&self) -> usize {
+        match self {
+            Fields::Borrowed(fields) => fields.len(),
+            Fields::Owned(fields) => fields.len(),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&F> {
+        match self {
+            Fields::Borrowed(fields) => fields.iter().find(|(k, _)| *k == key).map(|(_, v)| *v),
+            Fields::Owned(fields) => fields.get(key),
+        }
+    }
+
+    /// Takes the value of the given field. Borrowed fields are left in place.
+    fn remove(&mut self, key: &str) -> Option<ValueRef<'de, F>> {
+        match self {
+            Fields::Borrowed(fields) => fields
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| ValueRef::Borrowed(*v)),
+            Fields::Owned(fields) => fields.remove(key).map(ValueRef::Owned),
+        }
+    }
+
+    /// Takes the string value of the given field, such as an enum tag.
+    fn remove_str(&mut self, key: &'static str) -> Result<Cow<'de, str>> {
+        match self.get(key).map(FirestoreValue::kind) {
+            Some(ValueKind::String(_)) => (),
+            Some(kind) => return Err(DeserializationError::WrongType("string", kind.name())),
+            None => return Err(DeserializationError::MissingField(key)),
+        }
+
+        Ok(self
+            .remove(key)
+            .expect("Already checked that the field is present.")
+            .into_str())
+    }
+
+    /// Visits the enum which the fields hold, in the configured
+    /// representation.
+    fn visit_enum<V>(mut self, config: DeserializerConfig, visitor: V) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match config.enum_representation {
+            EnumRepresentation::TypeValue {
+                type_key,
+                value_key,
+                values_key,
+            } => {
+                let typ = self.remove_str(type_key)?;
+
+                let content = if let Some(value) = self.remove(value_key) {
+                    VariantContent::Value(Cow::Borrowed(value_key), value)
+                } else if let Some(value) = self.remove(values_key) {
+                    VariantContent::Value(Cow::Borrowed(values_key), value)
+                } else {
+                    return Err(DeserializationError::MissingField(value_key));
+                };
+
+                visitor.visit_enum(Enum::new(typ, content, config))
+            }
+            EnumRepresentation::ExternallyTagged => {
+                if self.len() == 1 {
+                    let (typ, value) = self
+                        .into_entries()
+                        .next()
+                        .expect("Already checked that the map has one entry.");
+
+                    let content = VariantContent::Value(typ.clone(), value);
+                    visitor.visit_enum(Enum::new(typ, content, config))
+                } else {
+                    Err(DeserializationError::WrongType("enum", "map"))
+                }
+            }
+            EnumRepresentation::InternallyTagged { tag_key } => {
+                let typ = self.remove_str(tag_key)?;
+
+                visitor.visit_enum(Enum::new(
+                    typ,
+                    VariantContent::Fields(self, tag_key),
+                    config,
+                ))
+            }
+        }
+    }
+
+    fn into_entries(self) -> Entries<'de, F> {
+        match self {
+            Fields::Borrowed(fields) => Entries::Borrowed(fields.into_iter()),
+            Fields::Owned(fields) => Entries::Owned(fields.into_iter()),
+        }
+    }
+}
+
+enum Entries<'de, F> {
+    Borrowed(std::vec::IntoIter<(&'de str, &'de F)>),
+    Owned(hash_map::IntoIter<String, F>),
+}
+
+impl<'de, F> Iterator for Entries<'de, F> {
+    type Item = (Cow<'de, str>, ValueRef<'de, F>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Entries::Borrowed(entries) => entries
+                .next()
+                .map(|(k, v)| (Cow::Borrowed(k), ValueRef::Borrowed(v))),
+            Entries::Owned(entries) => entries
+                .next()
+                .map(|(k, v)| (Cow::Owned(k), ValueRef::Owned(v))),
+        }
+    }
+}
+
+/// Deserializes Rust values from any implementation of `FirestoreValue`.
+pub struct ValueDeserializer<'de, F> {
+    value: ValueRef<'de, F>,
+    config: DeserializerConfig,
+}
+
+impl<'de, F: FirestoreValue> ValueDeserializer<'de, F> {
+    pub fn new(value: &'de F) -> Self {
+        ValueDeserializer::with_config(value, DeserializerConfig::default())
+    }
+
+    pub fn with_config(value: &'de F, config: DeserializerConfig) -> Self {
+        ValueDeserializer {
+            value: ValueRef::Borrowed(value),
+            config,
+        }
+    }
+
+    /// A deserializer which consumes `value`, moving its strings and bytes
+    /// into the result instead of cloning them.
+    #[cfg(feature = "googapis")]
+    pub(crate) fn owned(value: F, config: DeserializerConfig) -> Self {
+        ValueDeserializer {
+            value: ValueRef::Owned(value),
+            config,
+        }
+    }
+
+    fn value(&self) -> &F {
+        self.value.value()
+    }
+
+    fn wrong_type(&self, expected: &'static str) -> DeserializationError {
+        DeserializationError::WrongType(expected, self.value().kind().name())
+    }
+
+    /// Takes the value, for methods which visit its contents. Each method
+    /// visits the value at most once, so it is never needed again.
+    fn take(&mut self) -> ValueRef<'de, F> {
+        std::mem::replace(&mut self.value, ValueRef::Owned(F::unset()))
+    }
+}
 
-pub struct ValueDeserializer<'de>(pub &'de Value);
+fn visit_bytes<'de, V>(bytes: Cow<'de, [u8]>, visitor: V) -> Result<V::Value>
+where
+    V: serde::de::Visitor<'de>,
+{
+    match bytes {
+        Cow::Borrowed(bytes) => visitor.visit_borrowed_bytes(bytes),
+        Cow::Owned(bytes) => visitor.visit_byte_buf(bytes),
+    }
+}
 
-struct ArrayValueSeq<'de> {
-    values: std::slice::Iter<'de, Value>,
+struct ArrayValueSeq<'de, F> {
+    values: Elements<'de, F>,
+    index: usize,
+    config: DeserializerConfig,
 }
 
-impl<'de> ArrayValueSeq<'de> {
-    pub fn new(values: std::slice::Iter<'de, Value>) -> Self {
-        ArrayValueSeq { values }
+impl<'de, F> ArrayValueSeq<'de, F> {
+    pub fn new(values: Elements<'de, F>, config: DeserializerConfig) -> Self {
+        ArrayValueSeq {
+            values,
+            index: 0,
+            config,
+        }
     }
 }
 
-impl<'de> SeqAccess<'de> for ArrayValueSeq<'de> {
+impl<'de, F: FirestoreValue> SeqAccess<'de> for ArrayValueSeq<'de, F> {
     type Error = DeserializationError;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
     where
         T: serde::de::DeserializeSeed<'de>,
     {
-        if let Some(v) = self.values.next() {
-            seed.deserialize(&mut ValueDeserializer(v)).map(Some)
+        if let Some(value) = self.values.next() {
+            let index = self.index;
+            self.index += 1;
+
+            seed.deserialize(&mut ValueDeserializer {
+                value,
+                config: self.config,
+            })
+            .map(Some)
+            .map_err(|e| e.at(PathSegment::Index(index)))
         } else {
             Ok(None)
         }
@@ -46,12 +360,13 @@ impl<'de> SeqAccess<'de> for ArrayValueSeq<'de> {
 }
 
 struct BytesSeq<'de> {
-    bytes: core::slice::Iter<'de, u8>,
+    bytes: Cow<'de, [u8]>,
+    index: usize,
 }
 
 impl<'de> BytesSeq<'de> {
-    pub fn new(bytes: core::slice::Iter<'de, u8>) -> Self {
-        BytesSeq { bytes }
+    pub fn new(bytes: Cow<'de, [u8]>) -> Self {
+        BytesSeq { bytes, index: 0 }
     }
 }
 
@@ -62,7 +377,8 @@ impl<'de> SeqAccess<'de> for BytesSeq<'de> {
     where
         T: serde::de::DeserializeSeed<'de>,
     {
-        if let Some(v) = self.bytes.next() {
+        if let Some(v) = self.bytes.get(self.index) {
+            self.index += 1;
             seed.deserialize(PlainByteDeserializer(*v)).map(Some)
         } else {
             Ok(None)
@@ -70,69 +386,126 @@ impl<'de> SeqAccess<'de> for BytesSeq<'de> {
     }
 }
 
-struct MapValueSeq<'de> {
-    values: std::collections::hash_map::Iter<'de, String, Value>,
-    next_value: Option<&'de Value>,
+struct MapValueSeq<'de, F> {
+    values: Entries<'de, F>,
+    extra: std::vec::IntoIter<(Cow<'de, str>, ValueRef<'de, F>)>,
+    /// The key of the entry whose value is to be visited next, which is
+    /// kept to report the location of errors in the value.
+    next_value: Option<(Cow<'de, str>, ValueRef<'de, F>)>,
+    skip_key: Option<&'static str>,
+    config: DeserializerConfig,
 }
 
-impl<'de> MapValueSeq<'de> {
-    pub fn new(values: std::collections::hash_map::Iter<'de, String, Value>) -> Self {
+impl<'de, F> MapValueSeq<'de, F> {
+    pub fn new(values: Entries<'de, F>, config: DeserializerConfig) -> Self {
         MapValueSeq {
             values,
+            extra: Vec::new().into_iter(),
             next_value: None,
+            skip_key: None,
+            config,
         }
     }
+
+    /// Omits the given key, e.g. the tag of an internally tagged enum, while
+    /// visiting the map.
+    pub fn skipping(mut self, key: &'static str) -> Self {
+        self.skip_key = Some(key);
+        self
+    }
+
+    /// Visits the given entries after those of the map itself.
+    #[cfg(feature = "googapis")]
+    pub fn with_extra(mut self, extra: Vec<(Cow<'de, str>, ValueRef<'de, F>)>) -> Self {
+        self.extra = extra.into_iter();
+        self
+    }
 }
 
-impl<'de> MapAccess<'de> for MapValueSeq<'de> {
+impl<'de, F: FirestoreValue> MapAccess<'de> for MapValueSeq<'de, F> {
     type Error = DeserializationError;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
     where
         K: serde::de::DeserializeSeed<'de>,
     {
-        if let Some((k, v)) = self.values.next() {
-            self.next_value = Some(v);
+        for (k, v) in self.values.by_ref().chain(self.extra.by_ref()) {
+            if Some(k.as_ref()) == self.skip_key {
+                continue;
+            }
 
-            Ok(Some(seed.deserialize(PlainStringDeserializer(k))?))
-        } else {
-            Ok(None)
+            self.next_value = Some((k.clone(), v));
+
+            return Ok(Some(seed.deserialize(PlainStringDeserializer(k))?));
         }
+
+        Ok(None)
     }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
     where
         V: serde::de::DeserializeSeed<'de>,
     {
-        let value = self
+        let (key, value) = self
             .next_value
             .take()
             .expect("Shouldn't visit value before key.");
-        seed.deserialize(&mut ValueDeserializer(value))
+        seed.deserialize(&mut ValueDeserializer {
+            value,
+            config: self.config,
+        })
+        .map_err(|e| e.at(PathSegment::Key(key.into_owned())))
     }
 }
 
-impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
+impl<'de, 'a, F: FirestoreValue> Deserializer<'de> for &'a mut ValueDeserializer<'de, F> {
     type Error = DeserializationError;
 
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(DeserializationError::Unrepresentable("any"))
+        match self.value().kind() {
+            ValueKind::Unset | ValueKind::Null => return visitor.visit_unit(),
+            ValueKind::Boolean(v) => return visitor.visit_bool(v),
+            ValueKind::Integer(v) => return visitor.visit_i64(v),
+            ValueKind::Double(v) => return visitor.visit_f64(v),
+            // Timestamps and geopoints are visited as their encoded bytes, which is
+            // what the timestamp and geopoint helpers expect to read back when the
+            // value has been buffered (e.g. by an untagged enum or flattened struct).
+            // Any other target gets the encoding itself; see `from_grpc_value`.
+            ValueKind::Timestamp { seconds, nanos } => {
+                return visitor.visit_byte_buf(Timestamp { seconds, nanos }.encode_to_vec())
+            }
+            ValueKind::GeoPoint(point) => {
+                return visitor.visit_byte_buf(LatLng::from(point).encode_to_vec())
+            }
+            ValueKind::String(_)
+            | ValueKind::Reference(_)
+            | ValueKind::Bytes(_)
+            | ValueKind::Array(_)
+            | ValueKind::Map(_) => (),
+        }
+
+        match self.take().into_contents() {
+            Contents::Str(v) => PlainStringDeserializer(v).deserialize_any(visitor),
+            Contents::Bytes(bytes) => visit_bytes(bytes, visitor),
+            Contents::Elements(values) => {
+                visitor.visit_seq(ArrayValueSeq::new(values, self.config))
+            }
+            Contents::Fields(fields) => {
+                visitor.visit_map(MapValueSeq::new(fields.into_entries(), self.config))
+            }
+        }
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::BooleanValue(v)),
-        } = self.0
-        {
-            visitor.visit_bool(*v)
-        } else {
-            Err(DeserializationError::WrongType("bool", self.0.clone()))
+        match self.value().kind() {
+            ValueKind::Boolean(v) => visitor.visit_bool(v),
+            _ => Err(self.wrong_type("bool")),
         }
     }
 
@@ -140,14 +513,11 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::IntegerValue(v)),
-        } = self.0
-        {
-            visitor
-                .visit_i8(i8::try_from(*v).map_err(|_| DeserializationError::IntRange("i8", *v))?)
-        } else {
-            Err(DeserializationError::WrongType("i8", self.0.clone()))
+        match self.value().kind() {
+            ValueKind::Integer(v) => visitor.visit_i8(
+                i8::try_from(v).map_err(|_| DeserializationError::IntRange("i8", v.to_string()))?,
+            ),
+            _ => Err(self.wrong_type("i8")),
         }
     }
 
@@ -155,15 +525,12 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::IntegerValue(v)),
-        } = self.0
-        {
-            visitor.visit_i16(
-                i16::try_from(*v).map_err(|_| DeserializationError::IntRange("i16", *v))?,
-            )
-        } else {
-            Err(DeserializationError::WrongType("i16", self.0.clone()))
+        match self.value().kind() {
+            ValueKind::Integer(v) => visitor.visit_i16(
+                i16::try_from(v)
+                    .map_err(|_| DeserializationError::IntRange("i16", v.to_string()))?,
+            ),
+            _ => Err(self.wrong_type("i16")),
         }
     }
 
@@ -171,15 +538,12 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::IntegerValue(v)),
-        } = self.0
-        {
-            visitor.visit_i32(
-                i32::try_from(*v).map_err(|_| DeserializationError::IntRange("i32", *v))?,
-            )
-        } else {
-            Err(DeserializationError::WrongType("i32", self.0.clone()))
+        match self.value().kind() {
+            ValueKind::Integer(v) => visitor.visit_i32(
+                i32::try_from(v)
+                    .map_err(|_| DeserializationError::IntRange("i32", v.to_string()))?,
+            ),
+            _ => Err(self.wrong_type("i32")),
         }
     }
 
@@ -187,13 +551,22 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::IntegerValue(v)),
-        } = self.0
-        {
-            visitor.visit_i64(*v)
-        } else {
-            Err(DeserializationError::WrongType("i64", self.0.clone()))
+        match self.value().kind() {
+            ValueKind::Integer(v) => visitor.visit_i64(v),
+            _ => Err(self.wrong_type("i64")),
+        }
+    }
+
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match self.value().kind() {
+            ValueKind::Integer(v) => visitor.visit_i128(i128::from(v)),
+            kind => match self.config.i128_fallback.load_i128(&kind) {
+                Some(v) => visitor.visit_i128(v?),
+                None => Err(DeserializationError::WrongType("i128", kind.name())),
+            },
         }
     }
 
@@ -201,14 +574,11 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::IntegerValue(v)),
-        } = self.0
-        {
-            visitor
-                .visit_u8(u8::try_from(*v).map_err(|_| DeserializationError::IntRange("u8", *v))?)
-        } else {
-            Err(DeserializationError::WrongType("i8", self.0.clone()))
+        match self.value().kind() {
+            ValueKind::Integer(v) => visitor.visit_u8(
+                u8::try_from(v).map_err(|_| DeserializationError::IntRange("u8", v.to_string()))?,
+            ),
+            _ => Err(self.wrong_type("i8")),
         }
     }
 
@@ -216,15 +586,12 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::IntegerValue(v)),
-        } = self.0
-        {
-            visitor.visit_u16(
-                u16::try_from(*v).map_err(|_| DeserializationError::IntRange("u16", *v))?,
-            )
-        } else {
-            Err(DeserializationError::WrongType("u16", self.0.clone()))
+        match self.value().kind() {
+            ValueKind::Integer(v) => visitor.visit_u16(
+                u16::try_from(v)
+                    .map_err(|_| DeserializationError::IntRange("u16", v.to_string()))?,
+            ),
+            _ => Err(self.wrong_type("u16")),
         }
     }
 
@@ -232,15 +599,12 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::IntegerValue(v)),
-        } = self.0
-        {
-            visitor.visit_u32(
-                u32::try_from(*v).map_err(|_| DeserializationError::IntRange("u32", *v))?,
-            )
-        } else {
-            Err(DeserializationError::WrongType("u32", self.0.clone()))
+        match self.value().kind() {
+            ValueKind::Integer(v) => visitor.visit_u32(
+                u32::try_from(v)
+                    .map_err(|_| DeserializationError::IntRange("u32", v.to_string()))?,
+            ),
+            _ => Err(self.wrong_type("u32")),
         }
     }
 
@@ -248,15 +612,34 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::IntegerValue(v)),
-        } = self.0
-        {
-            visitor.visit_u64(
-                u64::try_from(*v).map_err(|_| DeserializationError::IntRange("u64", *v))?,
-            )
-        } else {
-            Err(DeserializationError::WrongType("u64", self.0.clone()))
+        let overflow = self.config.u64_overflow;
+
+        match self.value().kind() {
+            ValueKind::Integer(v) if overflow == U64Overflow::Wrap => visitor.visit_u64(v as u64),
+            ValueKind::Integer(v) => visitor.visit_u64(
+                u64::try_from(v)
+                    .map_err(|_| DeserializationError::IntRange("u64", v.to_string()))?,
+            ),
+            kind => match overflow.load(&kind) {
+                Some(v) => visitor.visit_u64(v),
+                None => Err(DeserializationError::WrongType("u64", kind.name())),
+            },
+        }
+    }
+
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match self.value().kind() {
+            ValueKind::Integer(v) => visitor.visit_u128(
+                u128::try_from(v)
+                    .map_err(|_| DeserializationError::IntRange("u128", v.to_string()))?,
+            ),
+            kind => match self.config.i128_fallback.load_u128(&kind) {
+                Some(v) => visitor.visit_u128(v?),
+                None => Err(DeserializationError::WrongType("u128", kind.name())),
+            },
         }
     }
 
@@ -264,14 +647,10 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::DoubleValue(v)),
-        } = self.0
-        {
+        match self.value().kind() {
             #[allow(clippy::cast_possible_truncation)]
-            visitor.visit_f32(*v as f32)
-        } else {
-            Err(DeserializationError::WrongType("f32", self.0.clone()))
+            ValueKind::Double(v) => visitor.visit_f32(v as f32),
+            _ => Err(self.wrong_type("f32")),
         }
     }
 
@@ -279,13 +658,9 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::DoubleValue(v)),
-        } = self.0
-        {
-            visitor.visit_f64(*v)
-        } else {
-            Err(DeserializationError::WrongType("f64", self.0.clone()))
+        match self.value().kind() {
+            ValueKind::Double(v) => visitor.visit_f64(v),
+            _ => Err(self.wrong_type("f64")),
         }
     }
 
@@ -293,21 +668,13 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::StringValue(v)),
-        } = self.0
-        {
-            if v.len() == 1 {
-                visitor.visit_char(
-                    v.chars()
-                        .next()
-                        .expect("Already checked that string has exactly one char."),
-                )
-            } else {
-                Err(DeserializationError::WrongType("char", self.0.clone()))
-            }
-        } else {
-            Err(DeserializationError::WrongType("char", self.0.clone()))
+        match self.value().kind() {
+            ValueKind::String(v) if v.len() == 1 => visitor.visit_char(
+                v.chars()
+                    .next()
+                    .expect("Already checked that string has exactly one char."),
+            ),
+            _ => Err(self.wrong_type("char")),
         }
     }
 
@@ -315,13 +682,10 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::StringValue(v)),
-        } = self.0
-        {
-            visitor.visit_str(v)
+        if matches!(self.value().kind(), ValueKind::String(_)) {
+            PlainStringDeserializer(self.take().into_str()).deserialize_str(visitor)
         } else {
-            Err(DeserializationError::WrongType("str", self.0.clone()))
+            Err(self.wrong_type("str"))
         }
     }
 
@@ -329,13 +693,10 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::StringValue(v)),
-        } = self.0
-        {
-            visitor.visit_string(v.clone())
+        if matches!(self.value().kind(), ValueKind::String(_)) {
+            PlainStringDeserializer(self.take().into_str()).deserialize_string(visitor)
         } else {
-            Err(DeserializationError::WrongType("string", self.0.clone()))
+            Err(self.wrong_type("string"))
         }
     }
 
@@ -343,13 +704,10 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::BytesValue(bytes)),
-        } = self.0
-        {
-            visitor.visit_bytes(bytes)
+        if matches!(self.value().kind(), ValueKind::Bytes(_)) {
+            visit_bytes(self.take().into_bytes(), visitor)
         } else {
-            Err(DeserializationError::WrongType("bytes", self.0.clone()))
+            Err(self.wrong_type("bytes"))
         }
     }
 
@@ -357,54 +715,73 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::BytesValue(bytes)),
-        } = self.0
-        {
-            visitor.visit_byte_buf(bytes.clone())
-        } else if let Value {
-            value_type: Some(ValueType::TimestampValue(timestamp)),
-        } = self.0
-        {
-            let bytes = timestamp.encode_to_vec();
-            visitor.visit_byte_buf(bytes)
-        } else {
-            Err(DeserializationError::WrongType("byte_buf", self.0.clone()))
+        match self.value().kind() {
+            ValueKind::Bytes(_) => (),
+            ValueKind::Timestamp { seconds, nanos } => {
+                let bytes = Timestamp { seconds, nanos }.encode_to_vec();
+                return visitor.visit_byte_buf(bytes);
+            }
+            ValueKind::GeoPoint(point) => {
+                let bytes = LatLng::from(point).encode_to_vec();
+                return visitor.visit_byte_buf(bytes);
+            }
+            _ => return Err(self.wrong_type("byte_buf")),
         }
+
+        visit_bytes(self.take().into_bytes(), visitor)
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::NullValue(_)),
-        } = self.0
-        {
+        if matches!(self.value().kind(), ValueKind::Null) {
             visitor.visit_none()
         } else {
             visitor.visit_some(self)
         }
     }
 
-    fn deserialize_unit<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(DeserializationError::Unrepresentable("unit"))
+        if !self.config.unit_as_null {
+            Err(DeserializationError::Unrepresentable("unit"))
+        } else if matches!(self.value().kind(), ValueKind::Null) {
+            visitor.visit_unit()
+        } else {
+            Err(self.wrong_type("unit"))
+        }
     }
 
-    fn deserialize_unit_struct<V>(self, _name: &'static str, _visitor: V) -> Result<V::Value>
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(DeserializationError::Unrepresentable("unit_struct"))
+        if self.config.unit_as_null {
+            self.deserialize_unit(visitor)
+        } else {
+            Err(DeserializationError::Unrepresentable("unit_struct"))
+        }
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
+        if name == GEOPOINT_MAGIC && !matches!(self.value().kind(), ValueKind::GeoPoint(_)) {
+            return Err(self.wrong_type("geopoint"));
+        }
+
+        if name == REFERENCE_MAGIC {
+            return if matches!(self.value().kind(), ValueKind::Reference(_)) {
+                visitor.visit_newtype_struct(PlainStringDeserializer(self.take().into_str()))
+            } else {
+                Err(self.wrong_type("reference"))
+            };
+        }
+
         visitor.visit_newtype_struct(self)
     }
 
@@ -412,18 +789,17 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::ArrayValue(ArrayValue { values })),
-        } = self.0
-        {
-            visitor.visit_seq(ArrayValueSeq::new(values.iter()))
-        } else if let Value {
-            value_type: Some(ValueType::BytesValue(bytes)),
-        } = self.0
-        {
-            visitor.visit_seq(BytesSeq::new(bytes.iter()))
-        } else {
-            Err(DeserializationError::WrongType("seq", self.0.clone()))
+        match self.value().kind() {
+            ValueKind::Array(_) | ValueKind::Bytes(_) => (),
+            _ => return Err(self.wrong_type("seq")),
+        }
+
+        match self.take().into_contents() {
+            Contents::Elements(values) => {
+                visitor.visit_seq(ArrayValueSeq::new(values, self.config))
+            }
+            Contents::Bytes(bytes) => visitor.visit_seq(BytesSeq::new(bytes)),
+            _ => unreachable!("Already checked that the value is an array or bytes."),
         }
     }
 
@@ -431,13 +807,10 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::ArrayValue(ArrayValue { values })),
-        } = self.0
-        {
-            visitor.visit_seq(ArrayValueSeq::new(values.iter()))
+        if matches!(self.value().kind(), ValueKind::Array(_)) {
+            visitor.visit_seq(ArrayValueSeq::new(self.take().into_elements(), self.config))
         } else {
-            Err(DeserializationError::WrongType("tuple", self.0.clone()))
+            Err(self.wrong_type("tuple"))
         }
     }
 
@@ -457,13 +830,13 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        if let Value {
-            value_type: Some(ValueType::MapValue(MapValue { fields })),
-        } = self.0
-        {
-            visitor.visit_map(MapValueSeq::new(fields.iter()))
+        if matches!(self.value().kind(), ValueKind::Map(_)) {
+            visitor.visit_map(MapValueSeq::new(
+                self.take().into_fields().into_entries(),
+                self.config,
+            ))
         } else {
-            Err(DeserializationError::WrongType("map", self.0.clone()))
+            Err(self.wrong_type("map"))
         }
     }
 
@@ -488,48 +861,29 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        match &self.0.value_type {
-            Some(ValueType::StringValue(v)) => visitor.visit_enum(v.clone().into_deserializer()),
-            Some(ValueType::MapValue(MapValue { fields })) => {
-                let mut typ: Option<&String> = None;
-                let mut value: Option<&Value> = None;
-
-                for (k, v) in fields {
-                    if k == TYPE {
-                        if let Value {
-                            value_type: Some(ValueType::StringValue(v)),
-                        } = v
-                        {
-                            typ = Some(v);
-                        } else {
-                            return Err(DeserializationError::WrongType("string", v.clone()));
-                        }
-                    } else if k == VALUE || k == VALUES {
-                        value = Some(v);
-                    }
-                }
+        let config = self.config;
 
-                let typ = if let Some(typ) = typ {
-                    typ
-                } else {
-                    return Err(DeserializationError::MissingField(TYPE));
-                };
-
-                if let Some(value) = value {
-                    visitor.visit_enum(Enum::new(typ, value))
-                } else {
-                    Err(DeserializationError::MissingField(VALUE))
-                }
-            }
-            _ => Err(DeserializationError::WrongType("enum", self.0.clone())),
+        if matches!(self.value().kind(), ValueKind::String(_)) {
+            return match self.take().into_str() {
+                Cow::Borrowed(v) => visitor.visit_enum(v.into_deserializer()),
+                Cow::Owned(v) => visitor.visit_enum(v.into_deserializer()),
+            };
+        } else if !matches!(self.value().kind(), ValueKind::Map(_)) {
+            return Err(self.wrong_type("enum"));
         }
+
+        self.take().into_fields().visit_enum(config, visitor)
     }
 
-    fn deserialize_identifier<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(DeserializationError::Unrepresentable("identifier"))
+        if matches!(self.value().kind(), ValueKind::String(_)) {
+            PlainStringDeserializer(self.take().into_str()).deserialize_identifier(visitor)
+        } else {
+            Err(self.wrong_type("identifier"))
+        }
     }
 
     fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
@@ -540,57 +894,117 @@ impl<'de, 'a> Deserializer<'de> for &'a mut ValueDeserializer<'de> {
     }
 }
 
-struct Enum<'de> {
-    typ: &'de str,
-    value: &'de Value,
+/// The data of an enum variant.
+enum VariantContent<'de, F> {
+    /// A value holding the variant's data, under the given key.
+    Value(Cow<'de, str>, ValueRef<'de, F>),
+    /// The fields of an internally tagged variant, alongside the given tag.
+    Fields(Fields<'de, F>, &'static str),
+}
+
+struct Enum<'de, F> {
+    typ: Cow<'de, str>,
+    content: VariantContent<'de, F>,
+    config: DeserializerConfig,
+}
+
+impl<'de, F> Enum<'de, F> {
+    pub fn new(
+        typ: Cow<'de, str>,
+        content: VariantContent<'de, F>,
+        config: DeserializerConfig,
+    ) -> Self {
+        Enum {
+            typ,
+            content,
+            config,
+        }
+    }
 }
 
-impl<'de> Enum<'de> {
-    pub fn new(typ: &'de str, value: &'de Value) -> Self {
-        Enum { typ, value }
+impl<'de, F: FirestoreValue> Enum<'de, F> {
+    /// Deserializes the variant's data with `f`, reporting errors at the
+    /// key which holds it.
+    fn deserialize_content<T>(
+        key: Cow<'de, str>,
+        value: ValueRef<'de, F>,
+        config: DeserializerConfig,
+        f: impl FnOnce(&mut ValueDeserializer<'de, F>) -> Result<T>,
+    ) -> Result<T> {
+        f(&mut ValueDeserializer { value, config })
+            .map_err(|e| e.at(PathSegment::Key(key.into_owned())))
     }
 }
 
-impl<'de> EnumAccess<'de> for Enum<'de> {
+impl<'de, F: FirestoreValue> EnumAccess<'de> for Enum<'de, F> {
     type Error = DeserializationError;
 
-    type Variant = Enum<'de>;
+    type Variant = Enum<'de, F>;
 
-    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    fn variant_seed<V>(mut self, seed: V) -> Result<(V::Value, Self::Variant)>
     where
         V: serde::de::DeserializeSeed<'de>,
     {
-        let val = seed.deserialize(PlainStringDeserializer(self.typ))?;
+        let typ = std::mem::take(&mut self.typ);
+        let val = seed.deserialize(PlainStringDeserializer(typ))?;
 
         Ok((val, self))
     }
 }
 
-impl<'de> VariantAccess<'de> for Enum<'de> {
+impl<'de, F: FirestoreValue> VariantAccess<'de> for Enum<'de, F> {
     type Error = DeserializationError;
 
     fn unit_variant(self) -> Result<()> {
-        panic!("Unit variant was already handled.")
+        match self.content {
+            VariantContent::Fields(..) => Ok(()),
+            VariantContent::Value(_, value) => Err(DeserializationError::WrongType(
+                "unit variant",
+                value.value().kind().name(),
+            )),
+        }
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
     where
         T: serde::de::DeserializeSeed<'de>,
     {
-        seed.deserialize(&mut ValueDeserializer(self.value))
+        match self.content {
+            VariantContent::Fields(fields, tag_key) => {
+                seed.deserialize(MapAccessDeserializer::new(
+                    MapValueSeq::new(fields.into_entries(), self.config).skipping(tag_key),
+                ))
+            }
+            VariantContent::Value(key, value) => {
+                Enum::deserialize_content(key, value, self.config, |d| seed.deserialize(d))
+            }
+        }
     }
 
     fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        ValueDeserializer(self.value).deserialize_seq(visitor)
+        match self.content {
+            VariantContent::Fields(..) => {
+                Err(DeserializationError::WrongType("tuple variant", "map"))
+            }
+            VariantContent::Value(key, value) => {
+                Enum::deserialize_content(key, value, self.config, |d| d.deserialize_seq(visitor))
+            }
+        }
     }
 
     fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        ValueDeserializer(self.value).deserialize_map(visitor)
+        match self.content {
+            VariantContent::Fields(fields, tag_key) => visitor
+                .visit_map(MapValueSeq::new(fields.into_entries(), self.config).skipping(tag_key)),
+            VariantContent::Value(key, value) => {
+                Enum::deserialize_content(key, value, self.config, |d| d.deserialize_map(visitor))
+            }
+        }
     }
 }
@@ -1,7 +1,22 @@
 use super::DeserializationError;
 use serde::Deserializer;
+use std::borrow::Cow;
 
-pub struct PlainStringDeserializer<'de>(pub &'de str);
+/// Deserializes a string which is either borrowed from the value being
+/// deserialized (and lent to the visitor), or owned (and moved into it).
+pub struct PlainStringDeserializer<'de>(pub Cow<'de, str>);
+
+impl<'de> PlainStringDeserializer<'de> {
+    fn visit<V>(self, visitor: V) -> Result<V::Value, DeserializationError>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        match self.0 {
+            Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+            Cow::Owned(s) => visitor.visit_string(s),
+        }
+    }
+}
 
 const PANIC_MESSAGE: &str =
     "PlainStringDeserializer should only ever be asked for string, str, or identifier.";
@@ -9,11 +24,11 @@ const PANIC_MESSAGE: &str =
 impl<'de> Deserializer<'de> for PlainStringDeserializer<'de> {
     type Error = DeserializationError;
 
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        panic!("{}", PANIC_MESSAGE)
+        self.visit(visitor)
     }
 
     fn deserialize_bool<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
@@ -100,18 +115,18 @@ impl<'de> Deserializer<'de> for PlainStringDeserializer<'de> {
         panic!("{}", PANIC_MESSAGE)
     }
 
-    fn deserialize_str<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        panic!("{}", PANIC_MESSAGE)
+        self.visit(visitor)
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_str(self.0)
+        self.visit(visitor)
     }
 
     fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
@@ -225,7 +240,7 @@ impl<'de> Deserializer<'de> for PlainStringDeserializer<'de> {
     where
         V: serde::de::Visitor<'de>,
     {
-        visitor.visit_str(self.0)
+        self.visit(visitor)
     }
 
     fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
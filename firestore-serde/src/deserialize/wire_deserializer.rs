@@ -0,0 +1,1042 @@
+use super::{BytesSeq, DeserializationError, PlainStringDeserializer, Result, ValueDeserializer};
+use crate::firestore::Value;
+use crate::metadata::{is_metadata_field, DocumentMetadata, CREATE_TIME_FIELD, UPDATE_TIME_FIELD};
+use crate::value_path::PathSegment;
+use crate::wire::{
+    ARRAY_VALUES, DOCUMENT_CREATE_TIME, DOCUMENT_FIELDS, DOCUMENT_NAME, DOCUMENT_UPDATE_TIME,
+    ENTRY_KEY, ENTRY_VALUE, MAP_FIELDS, VALUE_ARRAY, VALUE_BOOLEAN, VALUE_BYTES, VALUE_DOUBLE,
+    VALUE_GEO_POINT, VALUE_INTEGER, VALUE_MAP, VALUE_NULL, VALUE_REFERENCE, VALUE_STRING,
+    VALUE_TIMESTAMP,
+};
+use crate::{
+    DeserializerConfig, EnumRepresentation, U64Overflow, ValueKind, GEOPOINT_MAGIC, REFERENCE_MAGIC,
+};
+use prost::encoding::{decode_key, decode_varint, WireType};
+use prost::{DecodeError, Message};
+use prost_types::Timestamp;
+use serde::de::{
+    value::MapAccessDeserializer, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::{forward_to_deserialize_any, Deserializer};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+fn malformed(error: DecodeError) -> DeserializationError {
+    DeserializationError::Malformed(error.to_string())
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str> {
+    std::str::from_utf8(bytes).map_err(|e| DeserializationError::Malformed(e.to_string()))
+}
+
+/// The payload of a field of an encoded message.
+enum Field<'de> {
+    Varint(u64),
+    SixtyFourBit(u64),
+    ThirtyTwoBit,
+    LengthDelimited(&'de [u8]),
+}
+
+/// Removes `len` bytes from the front of `bytes`.
+fn take<'de>(bytes: &mut &'de [u8], len: usize) -> Result<&'de [u8]> {
+    let remaining: &'de [u8] = bytes;
+
+    if remaining.len() < len {
+        return Err(DeserializationError::Malformed(
+            "unexpected end of message".to_string(),
+        ));
+    }
+
+    let (head, tail) = remaining.split_at(len);
+    *bytes = tail;
+    Ok(head)
+}
+
+/// Removes the next field from the front of `bytes`, returning its number
+/// and payload.
+fn next_field<'de>(bytes: &mut &'de [u8]) -> Result<Option<(u32, Field<'de>)>> {
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+
+    let (tag, wire_type) = decode_key(bytes).map_err(malformed)?;
+
+    let field = match wire_type {
+        WireType::Varint => Field::Varint(decode_varint(bytes).map_err(malformed)?),
+        WireType::SixtyFourBit => {
+            let mut v = [0; 8];
+            v.copy_from_slice(take(bytes, 8)?);
+            Field::SixtyFourBit(u64::from_le_bytes(v))
+        }
+        WireType::ThirtyTwoBit => {
+            take(bytes, 4)?;
+            Field::ThirtyTwoBit
+        }
+        WireType::LengthDelimited => {
+            let len = decode_varint(bytes).map_err(malformed)?;
+            let len = usize::try_from(len)
+                .map_err(|_| DeserializationError::Malformed("length overflow".to_string()))?;
+            Field::LengthDelimited(take(bytes, len)?)
+        }
+        WireType::StartGroup | WireType::EndGroup => {
+            return Err(DeserializationError::Malformed(
+                "groups are not supported".to_string(),
+            ))
+        }
+    };
+
+    Ok(Some((tag, field)))
+}
+
+/// The key and encoded value of a map entry.
+fn entry(mut bytes: &[u8]) -> Result<(&str, &[u8])> {
+    let mut key = "";
+    let mut value: &[u8] = &[];
+
+    while let Some((tag, field)) = next_field(&mut bytes)? {
+        match (tag, field) {
+            (ENTRY_KEY, Field::LengthDelimited(v)) => key = utf8(v)?,
+            (ENTRY_VALUE, Field::LengthDelimited(v)) => value = v,
+            _ => (),
+        }
+    }
+
+    Ok((key, value))
+}
+
+/// The contents of an encoded `Value`, as far as they are read without
+/// decoding it.
+enum Contents<'de> {
+    Unset,
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Double(f64),
+    /// An encoded `Timestamp`.
+    Timestamp(&'de [u8]),
+    String(&'de str),
+    Bytes(&'de [u8]),
+    Reference(&'de str),
+    /// An encoded `LatLng`.
+    GeoPoint(&'de [u8]),
+    Array(&'de [u8]),
+    Map(&'de [u8]),
+}
+
+impl<'de> Contents<'de> {
+    /// A short description of the kind, like `ValueKind::name`.
+    fn name(&self) -> &'static str {
+        match self {
+            Contents::Unset => "empty value",
+            Contents::Null => "null",
+            Contents::Boolean(_) => "boolean",
+            Contents::Integer(_) => "integer",
+            Contents::Double(_) => "double",
+            Contents::Timestamp(_) => "timestamp",
+            Contents::String(_) => "string",
+            Contents::Bytes(_) => "bytes",
+            Contents::Reference(_) => "reference",
+            Contents::GeoPoint(_) => "geopoint",
+            Contents::Array(_) => "array",
+            Contents::Map(_) => "map",
+        }
+    }
+
+    fn wrong_type(&self, expected: &'static str) -> DeserializationError {
+        DeserializationError::WrongType(expected, self.name())
+    }
+
+    /// The kind of a string, bytes or double value, which are the forms the
+    /// `u64` and `i128` fallbacks read.
+    fn fallback_kind(&self) -> Option<ValueKind<'de, Value>> {
+        match *self {
+            Contents::Double(v) => Some(ValueKind::Double(v)),
+            Contents::String(v) => Some(ValueKind::String(v)),
+            Contents::Bytes(v) => Some(ValueKind::Bytes(v)),
+            _ => None,
+        }
+    }
+}
+
+fn contents(mut bytes: &[u8]) -> Result<Contents<'_>> {
+    let mut contents = Contents::Unset;
+
+    // Fields of a oneof replace each other, so the last one wins.
+    while let Some((tag, field)) = next_field(&mut bytes)? {
+        contents = match (tag, field) {
+            (VALUE_NULL, Field::Varint(_)) => Contents::Null,
+            (VALUE_BOOLEAN, Field::Varint(v)) => Contents::Boolean(v != 0),
+            (VALUE_INTEGER, Field::Varint(v)) => Contents::Integer(v as i64),
+            (VALUE_DOUBLE, Field::SixtyFourBit(v)) => Contents::Double(f64::from_bits(v)),
+            (VALUE_TIMESTAMP, Field::LengthDelimited(v)) => Contents::Timestamp(v),
+            (VALUE_STRING, Field::LengthDelimited(v)) => Contents::String(utf8(v)?),
+            (VALUE_BYTES, Field::LengthDelimited(v)) => Contents::Bytes(v),
+            (VALUE_REFERENCE, Field::LengthDelimited(v)) => Contents::Reference(utf8(v)?),
+            (VALUE_GEO_POINT, Field::LengthDelimited(v)) => Contents::GeoPoint(v),
+            (VALUE_ARRAY, Field::LengthDelimited(v)) => Contents::Array(v),
+            (VALUE_MAP, Field::LengthDelimited(v)) => Contents::Map(v),
+            (
+                VALUE_NULL | VALUE_BOOLEAN | VALUE_INTEGER | VALUE_DOUBLE | VALUE_STRING
+                | VALUE_BYTES | VALUE_ARRAY | VALUE_MAP | VALUE_TIMESTAMP | VALUE_GEO_POINT
+                | VALUE_REFERENCE,
+                _,
+            ) => {
+                return Err(DeserializationError::Malformed(format!(
+                    "invalid wire type for Value field {}",
+                    tag
+                )))
+            }
+            // Unknown fields are skipped.
+            _ => continue,
+        };
+    }
+
+    Ok(contents)
+}
+
+/// The keys and encoded values of the entries of an encoded map or
+/// document, whose field number is `entry_tag`.
+fn entries(mut bytes: &[u8], entry_tag: u32) -> Result<Vec<(&str, &[u8])>> {
+    let mut entries = Vec::new();
+
+    while let Some((tag, field)) = next_field(&mut bytes)? {
+        if let Field::LengthDelimited(v) = field {
+            if tag == entry_tag {
+                entries.push(entry(v)?);
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// The encoded value of the given entry. Later entries replace earlier ones
+/// with the same key, as when decoding.
+fn get<'de>(entries: &[(&'de str, &'de [u8])], key: &str) -> Option<&'de [u8]> {
+    entries
+        .iter()
+        .rev()
+        .find(|(k, _)| *k == key)
+        .map(|&(_, v)| v)
+}
+
+/// The string value of the given entry, such as an enum tag.
+fn get_str<'de>(entries: &[(&'de str, &'de [u8])], key: &'static str) -> Result<&'de str> {
+    match get(entries, key).map(contents).transpose()? {
+        Some(Contents::String(v)) => Ok(v),
+        Some(contents) => Err(contents.wrong_type("string")),
+        None => Err(DeserializationError::MissingField(key)),
+    }
+}
+
+/// Visits the enum which an encoded map or document holds, in the
+/// configured representation, like `Fields::visit_enum`.
+fn visit_enum<'de, V>(
+    bytes: &'de [u8],
+    entry_tag: u32,
+    config: DeserializerConfig,
+    visitor: V,
+) -> Result<V::Value>
+where
+    V: Visitor<'de>,
+{
+    let entries = entries(bytes, entry_tag)?;
+
+    match config.enum_representation {
+        EnumRepresentation::TypeValue {
+            type_key,
+            value_key,
+            values_key,
+        } => {
+            let typ = get_str(&entries, type_key)?;
+
+            let content = if let Some(value) = get(&entries, value_key) {
+                WireVariant::Value(value_key, value)
+            } else if let Some(value) = get(&entries, values_key) {
+                WireVariant::Value(values_key, value)
+            } else {
+                return Err(DeserializationError::MissingField(value_key));
+            };
+
+            visitor.visit_enum(WireEnum {
+                typ,
+                content,
+                config,
+            })
+        }
+        EnumRepresentation::ExternallyTagged => match entries[..] {
+            [(typ, value)] => visitor.visit_enum(WireEnum {
+                typ,
+                content: WireVariant::Value(typ, value),
+                config,
+            }),
+            _ => Err(DeserializationError::WrongType("enum", "map")),
+        },
+        EnumRepresentation::InternallyTagged { tag_key } => {
+            let typ = get_str(&entries, tag_key)?;
+
+            visitor.visit_enum(WireEnum {
+                typ,
+                content: WireVariant::Fields(bytes, entry_tag, tag_key),
+                config,
+            })
+        }
+    }
+}
+
+/// Deserializes the protobuf encoding of a `Value`. Strings and bytes are
+/// lent to the visitor out of the encoded bytes; arrays and maps are visited
+/// as they are read, rather than decoded first.
+pub(crate) struct WireDeserializer<'de> {
+    bytes: &'de [u8],
+    config: DeserializerConfig,
+}
+
+impl<'de> WireDeserializer<'de> {
+    pub fn new(bytes: &'de [u8], config: DeserializerConfig) -> Self {
+        WireDeserializer { bytes, config }
+    }
+
+    /// Reads an integer value, which must fit in `T`.
+    fn integer<T: TryFrom<i64>>(&self, expected: &'static str) -> Result<T> {
+        match contents(self.bytes)? {
+            Contents::Integer(v) => {
+                T::try_from(v).map_err(|_| DeserializationError::IntRange(expected, v.to_string()))
+            }
+            contents => Err(contents.wrong_type(expected)),
+        }
+    }
+}
+
+struct WireSeq<'de> {
+    bytes: &'de [u8],
+    index: usize,
+    config: DeserializerConfig,
+}
+
+impl<'de> SeqAccess<'de> for WireSeq<'de> {
+    type Error = DeserializationError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: serde::de::DeserializeSeed<'de>,
+    {
+        while let Some((tag, field)) = next_field(&mut self.bytes)? {
+            if let (ARRAY_VALUES, Field::LengthDelimited(element)) = (tag, field) {
+                let index = self.index;
+                self.index += 1;
+
+                return seed
+                    .deserialize(WireDeserializer::new(element, self.config))
+                    .map(Some)
+                    .map_err(|e| e.at(PathSegment::Index(index)));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// The value of a `WireMap` entry whose key has been visited.
+enum EntryValue<'de> {
+    Encoded(&'de str, &'de [u8]),
+    /// Document metadata.
+    Decoded(&'static str, Value),
+}
+
+struct WireMap<'de> {
+    bytes: &'de [u8],
+    /// The field number of each entry: `MapValue.fields` or
+    /// `Document.fields`. Other fields are skipped.
+    entry_tag: u32,
+    extra: std::vec::IntoIter<(&'static str, Value)>,
+    next_value: Option<EntryValue<'de>>,
+    skip_key: Option<&'static str>,
+    config: DeserializerConfig,
+}
+
+impl<'de> WireMap<'de> {
+    fn new(bytes: &'de [u8], entry_tag: u32, config: DeserializerConfig) -> Self {
+        WireMap {
+            bytes,
+            entry_tag,
+            extra: Vec::new().into_iter(),
+            next_value: None,
+            skip_key: None,
+            config,
+        }
+    }
+
+    /// Visits the given entries after those of the map itself.
+    fn with_extra(mut self, extra: Vec<(&'static str, Value)>) -> Self {
+        self.extra = extra.into_iter();
+        self
+    }
+
+    /// Omits the given key, e.g. the tag of an internally tagged enum, while
+    /// visiting the map.
+    fn skipping(mut self, key: &'static str) -> Self {
+        self.skip_key = Some(key);
+        self
+    }
+}
+
+impl<'de> MapAccess<'de> for WireMap<'de> {
+    type Error = DeserializationError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: serde::de::DeserializeSeed<'de>,
+    {
+        while let Some((tag, field)) = next_field(&mut self.bytes)? {
+            if let Field::LengthDelimited(bytes) = field {
+                if tag == self.entry_tag {
+                    let (key, value) = entry(bytes)?;
+
+                    if Some(key) == self.skip_key {
+                        continue;
+                    }
+
+                    self.next_value = Some(EntryValue::Encoded(key, value));
+
+                    return Ok(Some(
+                        seed.deserialize(PlainStringDeserializer(Cow::Borrowed(key)))?,
+                    ));
+                }
+            }
+        }
+
+        if let Some((key, value)) = self.extra.next() {
+            self.next_value = Some(EntryValue::Decoded(key, value));
+
+            return Ok(Some(
+                seed.deserialize(PlainStringDeserializer(Cow::Borrowed(key)))?,
+            ));
+        }
+
+        Ok(None)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        match self
+            .next_value
+            .take()
+            .expect("Shouldn't visit value before key.")
+        {
+            EntryValue::Encoded(key, value) => seed
+                .deserialize(WireDeserializer::new(value, self.config))
+                .map_err(|e| e.at(PathSegment::Key(key.to_string()))),
+            EntryValue::Decoded(key, value) => seed
+                .deserialize(&mut ValueDeserializer::owned(value, self.config))
+                .map_err(|e| e.at(PathSegment::Key(key.to_string()))),
+        }
+    }
+}
+
+/// The data of an enum variant in an encoded map or document, like
+/// `VariantContent`.
+enum WireVariant<'de> {
+    /// The encoded value holding the variant's data, under the given key.
+    Value(&'de str, &'de [u8]),
+    /// The entries, with the given field number, of an internally tagged
+    /// variant, alongside the given tag.
+    Fields(&'de [u8], u32, &'static str),
+}
+
+struct WireEnum<'de> {
+    typ: &'de str,
+    content: WireVariant<'de>,
+    config: DeserializerConfig,
+}
+
+impl<'de> WireEnum<'de> {
+    /// Deserializes the variant's data with `f`, reporting errors at the key
+    /// which holds it.
+    fn deserialize_content<T>(
+        key: &str,
+        value: &'de [u8],
+        config: DeserializerConfig,
+        f: impl FnOnce(WireDeserializer<'de>) -> Result<T>,
+    ) -> Result<T> {
+        f(WireDeserializer::new(value, config)).map_err(|e| e.at(PathSegment::Key(key.to_string())))
+    }
+}
+
+impl<'de> EnumAccess<'de> for WireEnum<'de> {
+    type Error = DeserializationError;
+
+    type Variant = WireEnum<'de>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        let val = seed.deserialize(PlainStringDeserializer(Cow::Borrowed(self.typ)))?;
+
+        Ok((val, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for WireEnum<'de> {
+    type Error = DeserializationError;
+
+    fn unit_variant(self) -> Result<()> {
+        match self.content {
+            WireVariant::Fields(..) => Ok(()),
+            WireVariant::Value(_, value) => Err(contents(value)?.wrong_type("unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: serde::de::DeserializeSeed<'de>,
+    {
+        match self.content {
+            WireVariant::Fields(bytes, entry_tag, tag_key) => {
+                seed.deserialize(MapAccessDeserializer::new(
+                    WireMap::new(bytes, entry_tag, self.config).skipping(tag_key),
+                ))
+            }
+            WireVariant::Value(key, value) => {
+                WireEnum::deserialize_content(key, value, self.config, |d| seed.deserialize(d))
+            }
+        }
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.content {
+            WireVariant::Fields(..) => Err(DeserializationError::WrongType("tuple variant", "map")),
+            WireVariant::Value(key, value) => {
+                WireEnum::deserialize_content(key, value, self.config, |d| {
+                    d.deserialize_seq(visitor)
+                })
+            }
+        }
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.content {
+            WireVariant::Fields(bytes, entry_tag, tag_key) => {
+                visitor.visit_map(WireMap::new(bytes, entry_tag, self.config).skipping(tag_key))
+            }
+            WireVariant::Value(key, value) => {
+                WireEnum::deserialize_content(key, value, self.config, |d| {
+                    d.deserialize_map(visitor)
+                })
+            }
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for WireDeserializer<'de> {
+    type Error = DeserializationError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match contents(self.bytes)? {
+            Contents::Unset | Contents::Null => visitor.visit_unit(),
+            Contents::Boolean(v) => visitor.visit_bool(v),
+            Contents::Integer(v) => visitor.visit_i64(v),
+            Contents::Double(v) => visitor.visit_f64(v),
+            // Timestamps and geopoints are visited as their encoded bytes, like
+            // the `ValueDeserializer` does.
+            Contents::Timestamp(v) | Contents::GeoPoint(v) => visitor.visit_borrowed_bytes(v),
+            Contents::String(v) | Contents::Reference(v) => visitor.visit_borrowed_str(v),
+            Contents::Bytes(v) => visitor.visit_borrowed_bytes(v),
+            Contents::Array(v) => visitor.visit_seq(WireSeq {
+                bytes: v,
+                index: 0,
+                config: self.config,
+            }),
+            Contents::Map(v) => visitor.visit_map(WireMap::new(v, MAP_FIELDS, self.config)),
+        }
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match contents(self.bytes)? {
+            Contents::Boolean(v) => visitor.visit_bool(v),
+            contents => Err(contents.wrong_type("bool")),
+        }
+    }
+
+    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i8(self.integer("i8")?)
+    }
+
+    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i16(self.integer("i16")?)
+    }
+
+    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i32(self.integer("i32")?)
+    }
+
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i64(self.integer("i64")?)
+    }
+
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let fallback = self.config.i128_fallback;
+
+        match contents(self.bytes)? {
+            Contents::Integer(v) => visitor.visit_i128(i128::from(v)),
+            contents => match contents
+                .fallback_kind()
+                .and_then(|kind| fallback.load_i128(&kind))
+            {
+                Some(v) => visitor.visit_i128(v?),
+                None => Err(contents.wrong_type("i128")),
+            },
+        }
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u8(self.integer("u8")?)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u16(self.integer("u16")?)
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u32(self.integer("u32")?)
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let overflow = self.config.u64_overflow;
+
+        match contents(self.bytes)? {
+            Contents::Integer(v) if overflow == U64Overflow::Wrap => visitor.visit_u64(v as u64),
+            Contents::Integer(_) => visitor.visit_u64(self.integer("u64")?),
+            contents => match contents
+                .fallback_kind()
+                .and_then(|kind| overflow.load(&kind))
+            {
+                Some(v) => visitor.visit_u64(v),
+                None => Err(contents.wrong_type("u64")),
+            },
+        }
+    }
+
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let fallback = self.config.i128_fallback;
+
+        match contents(self.bytes)? {
+            Contents::Integer(_) => visitor.visit_u128(self.integer("u128")?),
+            contents => match contents
+                .fallback_kind()
+                .and_then(|kind| fallback.load_u128(&kind))
+            {
+                Some(v) => visitor.visit_u128(v?),
+                None => Err(contents.wrong_type("u128")),
+            },
+        }
+    }
+
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match contents(self.bytes)? {
+            #[allow(clippy::cast_possible_truncation)]
+            Contents::Double(v) => visitor.visit_f32(v as f32),
+            contents => Err(contents.wrong_type("f32")),
+        }
+    }
+
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match contents(self.bytes)? {
+            Contents::Double(v) => visitor.visit_f64(v),
+            contents => Err(contents.wrong_type("f64")),
+        }
+    }
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match contents(self.bytes)? {
+            Contents::String(v) if v.len() == 1 => visitor.visit_char(
+                v.chars()
+                    .next()
+                    .expect("Already checked that string has exactly one char."),
+            ),
+            contents => Err(contents.wrong_type("char")),
+        }
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match contents(self.bytes)? {
+            Contents::String(v) => visitor.visit_borrowed_str(v),
+            contents => Err(contents.wrong_type("str")),
+        }
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match contents(self.bytes)? {
+            Contents::Bytes(v) => visitor.visit_borrowed_bytes(v),
+            contents => Err(contents.wrong_type("bytes")),
+        }
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match contents(self.bytes)? {
+            Contents::Bytes(v) | Contents::Timestamp(v) | Contents::GeoPoint(v) => {
+                visitor.visit_borrowed_bytes(v)
+            }
+            contents => Err(contents.wrong_type("byte_buf")),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if let Contents::Null = contents(self.bytes)? {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if !self.config.unit_as_null {
+            return Err(DeserializationError::Unrepresentable("unit"));
+        }
+
+        match contents(self.bytes)? {
+            Contents::Null => visitor.visit_unit(),
+            contents => Err(contents.wrong_type("unit")),
+        }
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.config.unit_as_null {
+            self.deserialize_unit(visitor)
+        } else {
+            Err(DeserializationError::Unrepresentable("unit_struct"))
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if name == GEOPOINT_MAGIC {
+            match contents(self.bytes)? {
+                Contents::GeoPoint(_) => visitor.visit_newtype_struct(self),
+                contents => Err(contents.wrong_type("geopoint")),
+            }
+        } else if name == REFERENCE_MAGIC {
+            match contents(self.bytes)? {
+                Contents::Reference(v) => {
+                    visitor.visit_newtype_struct(PlainStringDeserializer(Cow::Borrowed(v)))
+                }
+                contents => Err(contents.wrong_type("reference")),
+            }
+        } else {
+            visitor.visit_newtype_struct(self)
+        }
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match contents(self.bytes)? {
+            Contents::Array(v) => visitor.visit_seq(WireSeq {
+                bytes: v,
+                index: 0,
+                config: self.config,
+            }),
+            Contents::Bytes(v) => visitor.visit_seq(BytesSeq::new(Cow::Borrowed(v))),
+            contents => Err(contents.wrong_type("seq")),
+        }
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match contents(self.bytes)? {
+            Contents::Array(_) => self.deserialize_seq(visitor),
+            contents => Err(contents.wrong_type("tuple")),
+        }
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match contents(self.bytes)? {
+            Contents::Map(v) => visitor.visit_map(WireMap::new(v, MAP_FIELDS, self.config)),
+            contents => Err(contents.wrong_type("map")),
+        }
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match contents(self.bytes)? {
+            Contents::String(v) => visitor.visit_enum(v.into_deserializer()),
+            Contents::Map(v) => visit_enum(v, MAP_FIELDS, self.config, visitor),
+            contents => Err(contents.wrong_type("enum")),
+        }
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match contents(self.bytes)? {
+            Contents::String(v) => visitor.visit_borrowed_str(v),
+            contents => Err(contents.wrong_type("identifier")),
+        }
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+}
+
+/// Deserializes the fields of an encoded `Document` as a map, like the
+/// `DocumentDeserializer`.
+pub(crate) struct WireDocumentDeserializer<'de> {
+    bytes: &'de [u8],
+    config: DeserializerConfig,
+}
+
+impl<'de> WireDocumentDeserializer<'de> {
+    pub fn new(bytes: &'de [u8], config: DeserializerConfig) -> Self {
+        WireDocumentDeserializer { bytes, config }
+    }
+
+    /// The document's metadata fields whose names are among `requested`,
+    /// unless the document has a field of the same name.
+    fn metadata(&self, requested: &[&str]) -> Result<Vec<(&'static str, Value)>> {
+        if !requested.iter().any(|field| is_metadata_field(field)) {
+            return Ok(Vec::new());
+        }
+
+        let mut name = "";
+        let mut create_time = None;
+        let mut update_time = None;
+        // Document fields which shadow a metadata field.
+        let mut shadowed = HashSet::new();
+
+        let mut bytes = self.bytes;
+        while let Some((tag, field)) = next_field(&mut bytes)? {
+            if let Field::LengthDelimited(v) = field {
+                match tag {
+                    DOCUMENT_NAME => name = utf8(v)?,
+                    DOCUMENT_FIELDS => {
+                        let key = entry(v)?.0;
+
+                        if is_metadata_field(key) {
+                            shadowed.insert(key);
+                        }
+                    }
+                    DOCUMENT_CREATE_TIME => create_time = Some(v),
+                    DOCUMENT_UPDATE_TIME => update_time = Some(v),
+                    _ => (),
+                }
+            }
+        }
+
+        // Timestamps are only decoded if they are requested.
+        let decode = |field: &str, bytes: Option<&[u8]>| match bytes {
+            Some(bytes) if requested.contains(&field) => {
+                Timestamp::decode(bytes).map(Some).map_err(malformed)
+            }
+            _ => Ok(None),
+        };
+
+        let metadata = DocumentMetadata {
+            name: Cow::Borrowed(name),
+            create_time: decode(CREATE_TIME_FIELD, create_time)?,
+            update_time: decode(UPDATE_TIME_FIELD, update_time)?,
+        };
+
+        Ok(metadata
+            .into_fields(requested)
+            .into_iter()
+            .filter(|(k, _)| !shadowed.contains(k))
+            .collect())
+    }
+}
+
+impl<'de> Deserializer<'de> for WireDocumentDeserializer<'de> {
+    type Error = DeserializationError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(WireMap::new(self.bytes, DOCUMENT_FIELDS, self.config))
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let metadata = self.metadata(fields)?;
+
+        visitor.visit_map(
+            WireMap::new(self.bytes, DOCUMENT_FIELDS, self.config).with_extra(metadata),
+        )
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visit_enum(self.bytes, DOCUMENT_FIELDS, self.config, visitor)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct identifier
+    }
+}
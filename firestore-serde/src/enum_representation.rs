@@ -0,0 +1,35 @@
+use crate::{TYPE, VALUE, VALUES};
+
+/// Determines how enum variants which carry data are laid out in a `Value`.
+///
+/// Unit variants are always stored as a plain string containing the variant
+/// name, except with `InternallyTagged`, where they are stored as a map
+/// containing only the tag.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EnumRepresentation {
+    /// `{type: "Variant", value: ...}` for newtype variants and
+    /// `{type: "Variant", values: ...}` for tuple and struct variants, with
+    /// configurable key names. This is the default, using `TYPE`, `VALUE` and
+    /// `VALUES` as key names.
+    TypeValue {
+        type_key: &'static str,
+        value_key: &'static str,
+        values_key: &'static str,
+    },
+    /// `{Variant: ...}`, which matches serde's default representation.
+    ExternallyTagged,
+    /// `{tag: "Variant", ...fields}` for struct variants and for newtype
+    /// variants which wrap a map. Tuple variants, and newtype variants which
+    /// wrap anything else, can't be represented.
+    InternallyTagged { tag_key: &'static str },
+}
+
+impl Default for EnumRepresentation {
+    fn default() -> Self {
+        EnumRepresentation::TypeValue {
+            type_key: TYPE,
+            value_key: VALUE,
+            values_key: VALUES,
+        }
+    }
+}
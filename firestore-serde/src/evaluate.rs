@@ -0,0 +1,752 @@
+//! Runs a `StructuredQuery` against documents in memory, e.g. in tests or
+//! over a local cache, following Firestore's semantics as closely as
+//! possible:
+//!
+//! - Filters, orderings and projections whose field is missing from a
+//!   document exclude it, as do range filters (`<`, `>`, etc.) on a value of
+//!   a different type, or on `NaN`.
+//! - Results are ordered by the `order_by` fields, then by document name.
+//!   If there is no `order_by`, the first field with an inequality filter is
+//!   ordered by ascending.
+//! - Documents with a name are only returned if their collection is among
+//!   those the query selects from. Documents without a name (such as those
+//!   produced by `to_document`) are always considered.
+
+use crate::field_path::InvalidFieldPath;
+use crate::firestore::{
+    structured_query::{
+        composite_filter, field_filter, filter::FilterType, unary_filter,
+        unary_filter::OperandType, CompositeFilter, Direction, FieldFilter, FieldReference,
+        Filter, UnaryFilter,
+    },
+    value::ValueType,
+    ArrayValue, Cursor, Document, StructuredQuery, Value,
+};
+use crate::ordering::{compare, query_equals, type_order};
+use crate::query::DOCUMENT_NAME_FIELD;
+use crate::FieldPath;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+/// The value of `CompositeFilter::op` for an OR filter, which the version
+/// of the protocol buffers in `googapis` predates.
+const COMPOSITE_OR: i32 = 2;
+
+#[derive(Debug, PartialEq)]
+pub enum EvaluationError {
+    InvalidFieldPath(InvalidFieldPath),
+    /// A filter or ordering with no field.
+    MissingField,
+    /// An unspecified or unknown operator, or direction.
+    InvalidOperator(i32),
+    /// An operator was given an operand it can't be applied to, e.g. an
+    /// `in` filter with a value which is not an array.
+    InvalidOperand(String),
+    /// A cursor has more values than the query has orderings.
+    TooManyCursorValues,
+}
+
+impl Display for EvaluationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvaluationError::InvalidFieldPath(e) => write!(f, "{}", e),
+            EvaluationError::MissingField => writeln!(f, "A filter or ordering has no field."),
+            EvaluationError::InvalidOperator(op) => writeln!(f, "Invalid operator {}.", op),
+            EvaluationError::InvalidOperand(e) => writeln!(f, "Invalid operand: {}", e),
+            EvaluationError::TooManyCursorValues => writeln!(
+                f,
+                "A cursor has more values than the query has orderings."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EvaluationError {}
+
+impl From<InvalidFieldPath> for EvaluationError {
+    fn from(e: InvalidFieldPath) -> Self {
+        EvaluationError::InvalidFieldPath(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, EvaluationError>;
+
+/// Returns the documents which `query` matches, in order, after applying
+/// its cursors, offset, limit and projection.
+pub fn run_query(query: &StructuredQuery, documents: Vec<Document>) -> Result<Vec<Document>> {
+    let predicate = query.r#where.as_ref().map(Predicate::new).transpose()?;
+    let orders = orders(query, predicate.as_ref())?;
+
+    for cursor in query.start_at.iter().chain(query.end_at.iter()) {
+        if cursor.values.len() > orders.len() {
+            return Err(EvaluationError::TooManyCursorValues);
+        }
+    }
+
+    let mut results: Vec<Document> = documents
+        .into_iter()
+        .filter(|document| in_collection(query, document))
+        .filter(|document| predicate.as_ref().is_none_or(|p| p.matches(document)))
+        .filter(|document| orders.iter().all(|o| o.field.get(document).is_some()))
+        .collect();
+
+    results.sort_by(|a, b| {
+        orders
+            .iter()
+            .map(|order| order.compare(a, b))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+
+    results.retain(|document| {
+        let after_start = query.start_at.as_ref().is_none_or(|cursor| {
+            match cursor_position(document, &orders, cursor) {
+                Ordering::Greater => true,
+                Ordering::Equal => cursor.before,
+                Ordering::Less => false,
+            }
+        });
+        let before_end = query.end_at.as_ref().is_none_or(|cursor| {
+            match cursor_position(document, &orders, cursor) {
+                Ordering::Less => true,
+                Ordering::Equal => !cursor.before,
+                Ordering::Greater => false,
+            }
+        });
+
+        after_start && before_end
+    });
+
+    #[allow(clippy::cast_sign_loss)]
+    let mut results: Vec<Document> = results
+        .into_iter()
+        .skip(query.offset.max(0) as usize)
+        .take(query.limit.map_or(usize::MAX, |limit| limit.max(0) as usize))
+        .collect();
+
+    if let Some(projection) = &query.select {
+        let paths = projection
+            .fields
+            .iter()
+            .map(|field| Field::new(Some(field)))
+            .collect::<Result<Vec<_>>>()?;
+
+        for document in &mut results {
+            let mut fields = std::mem::take(&mut document.fields);
+
+            for path in &paths {
+                if let Field::Path(path) = path {
+                    if let Some(value) = path.remove(&mut fields) {
+                        path.set(&mut document.fields, value);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+/// A field a filter or ordering refers to.
+enum Field {
+    /// The document's name, as a reference.
+    Name,
+    Path(FieldPath),
+}
+
+impl Field {
+    fn new(field: Option<&FieldReference>) -> Result<Self> {
+        let field = field.ok_or(EvaluationError::MissingField)?;
+
+        if field.field_path == DOCUMENT_NAME_FIELD {
+            Ok(Field::Name)
+        } else {
+            Ok(Field::Path(field.field_path.parse()?))
+        }
+    }
+
+    fn get<'a>(&self, document: &'a Document) -> Option<Cow<'a, Value>> {
+        match self {
+            Field::Name => Some(Cow::Owned(Value {
+                value_type: Some(ValueType::ReferenceValue(document.name.clone())),
+            })),
+            Field::Path(path) => path.get(document).map(Cow::Borrowed),
+        }
+    }
+
+    fn is(&self, other: &Field) -> bool {
+        match (self, other) {
+            (Field::Name, Field::Name) => true,
+            (Field::Path(a), Field::Path(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// A filter, with its field paths parsed and operators checked.
+enum Predicate {
+    And(Vec<Predicate>),
+    Or(Vec<Predicate>),
+    Field {
+        field: Field,
+        op: field_filter::Operator,
+        value: Value,
+    },
+    Unary {
+        field: Field,
+        op: unary_filter::Operator,
+    },
+}
+
+impl Predicate {
+    fn new(filter: &Filter) -> Result<Self> {
+        match &filter.filter_type {
+            Some(FilterType::CompositeFilter(CompositeFilter { op, filters })) => {
+                let filters = filters
+                    .iter()
+                    .map(Predicate::new)
+                    .collect::<Result<Vec<_>>>()?;
+
+                if *op == composite_filter::Operator::And as i32 {
+                    Ok(Predicate::And(filters))
+                } else if *op == COMPOSITE_OR {
+                    Ok(Predicate::Or(filters))
+                } else {
+                    Err(EvaluationError::InvalidOperator(*op))
+                }
+            }
+            Some(FilterType::FieldFilter(FieldFilter { field, op, value })) => {
+                use field_filter::Operator;
+
+                let op = match Operator::from_i32(*op) {
+                    Some(Operator::Unspecified) | None => {
+                        return Err(EvaluationError::InvalidOperator(*op))
+                    }
+                    Some(op) => op,
+                };
+                let value = value.clone().unwrap_or_default();
+
+                if let Operator::In | Operator::NotIn | Operator::ArrayContainsAny = op {
+                    if !matches!(value.value_type, Some(ValueType::ArrayValue(_))) {
+                        return Err(EvaluationError::InvalidOperand(format!(
+                            "{:?} requires an array",
+                            op
+                        )));
+                    }
+                }
+
+                Ok(Predicate::Field {
+                    field: Field::new(field.as_ref())?,
+                    op,
+                    value,
+                })
+            }
+            Some(FilterType::UnaryFilter(UnaryFilter { op, operand_type })) => {
+                let op = match unary_filter::Operator::from_i32(*op) {
+                    Some(unary_filter::Operator::Unspecified) | None => {
+                        return Err(EvaluationError::InvalidOperator(*op))
+                    }
+                    Some(op) => op,
+                };
+                let field = operand_type.as_ref().map(|OperandType::Field(field)| field);
+
+                Ok(Predicate::Unary {
+                    field: Field::new(field)?,
+                    op,
+                })
+            }
+            None => Err(EvaluationError::InvalidOperand(
+                "filter with no type".to_string(),
+            )),
+        }
+    }
+
+    fn matches(&self, document: &Document) -> bool {
+        match self {
+            Predicate::And(predicates) => predicates.iter().all(|p| p.matches(document)),
+            Predicate::Or(predicates) => predicates.iter().any(|p| p.matches(document)),
+            Predicate::Field { field, op, value } => match field.get(document) {
+                Some(other) => field_matches(&other, *op, value),
+                None => false,
+            },
+            Predicate::Unary { field, op } => match field.get(document) {
+                Some(other) => match op {
+                    unary_filter::Operator::IsNan => is_nan(&other),
+                    unary_filter::Operator::IsNull => is_null(&other),
+                    unary_filter::Operator::IsNotNan => !is_nan(&other),
+                    unary_filter::Operator::IsNotNull => !is_null(&other),
+                    unary_filter::Operator::Unspecified => unreachable!("Rejected by new."),
+                },
+                None => false,
+            },
+        }
+    }
+
+    /// The field of the first inequality filter, which the results are
+    /// ordered by if the query has no explicit ordering.
+    fn inequality_field(&self) -> Option<&Field> {
+        use field_filter::Operator;
+
+        match self {
+            Predicate::And(predicates) | Predicate::Or(predicates) => {
+                predicates.iter().find_map(Predicate::inequality_field)
+            }
+            Predicate::Field {
+                field,
+                op:
+                    Operator::LessThan
+                    | Operator::LessThanOrEqual
+                    | Operator::GreaterThan
+                    | Operator::GreaterThanOrEqual
+                    | Operator::NotEqual
+                    | Operator::NotIn,
+                ..
+            } => Some(field),
+            _ => None,
+        }
+    }
+}
+
+fn field_matches(other: &Value, op: field_filter::Operator, value: &Value) -> bool {
+    use field_filter::Operator;
+
+    let range = |expected: &[Ordering]| {
+        type_order(&other.value_type) == type_order(&value.value_type)
+            && !is_nan(other)
+            && !is_nan(value)
+            && expected.contains(&compare(other, value))
+    };
+
+    match op {
+        Operator::LessThan => range(&[Ordering::Less]),
+        Operator::LessThanOrEqual => range(&[Ordering::Less, Ordering::Equal]),
+        Operator::GreaterThan => range(&[Ordering::Greater]),
+        Operator::GreaterThanOrEqual => range(&[Ordering::Greater, Ordering::Equal]),
+        Operator::Equal => query_equals(other, value),
+        // Like the inequalities, `!=` and `not-in` never match null, and `!=`
+        // never matches NaN either.
+        Operator::NotEqual => !is_null(other) && !is_nan(other) && !query_equals(other, value),
+        Operator::ArrayContains => elements(other).iter().any(|e| query_equals(e, value)),
+        Operator::In => elements(value).iter().any(|v| query_equals(other, v)),
+        Operator::NotIn => {
+            !is_null(other)
+                && !elements(value)
+                    .iter()
+                    .any(|v| is_null(v) || query_equals(other, v))
+        }
+        Operator::ArrayContainsAny => elements(other)
+            .iter()
+            .any(|e| elements(value).iter().any(|v| query_equals(e, v))),
+        Operator::Unspecified => unreachable!("Rejected by Predicate::new."),
+    }
+}
+
+fn elements(value: &Value) -> &[Value] {
+    match &value.value_type {
+        Some(ValueType::ArrayValue(ArrayValue { values })) => values,
+        _ => &[],
+    }
+}
+
+fn is_nan(value: &Value) -> bool {
+    matches!(value.value_type, Some(ValueType::DoubleValue(v)) if v.is_nan())
+}
+
+fn is_null(value: &Value) -> bool {
+    matches!(value.value_type, None | Some(ValueType::NullValue(_)))
+}
+
+struct Order {
+    field: Field,
+    descending: bool,
+}
+
+impl Order {
+    fn compare(&self, a: &Document, b: &Document) -> Ordering {
+        let a = self.field.get(a).expect("Documents without the field are excluded.");
+        let b = self.field.get(b).expect("Documents without the field are excluded.");
+
+        self.directed(compare(&a, &b))
+    }
+
+    fn directed(&self, ordering: Ordering) -> Ordering {
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+/// The orderings of the query results, including the implicit ones.
+fn orders(query: &StructuredQuery, predicate: Option<&Predicate>) -> Result<Vec<Order>> {
+    let mut orders = Vec::new();
+
+    for order in &query.order_by {
+        let descending = match Direction::from_i32(order.direction) {
+            Some(Direction::Ascending) => false,
+            Some(Direction::Descending) => true,
+            _ => return Err(EvaluationError::InvalidOperator(order.direction)),
+        };
+
+        orders.push(Order {
+            field: Field::new(order.field.as_ref())?,
+            descending,
+        });
+    }
+
+    if orders.is_empty() {
+        if let Some(Field::Path(path)) = predicate.and_then(Predicate::inequality_field) {
+            orders.push(Order {
+                field: Field::Path(path.clone()),
+                descending: false,
+            });
+        }
+    }
+
+    if !orders.iter().any(|order| order.field.is(&Field::Name)) {
+        let descending = orders.last().is_some_and(|order| order.descending);
+        orders.push(Order {
+            field: Field::Name,
+            descending,
+        });
+    }
+
+    Ok(orders)
+}
+
+/// Where `document` falls relative to the position `cursor` describes.
+fn cursor_position(document: &Document, orders: &[Order], cursor: &Cursor) -> Ordering {
+    orders
+        .iter()
+        .zip(&cursor.values)
+        .map(|(order, value)| {
+            let field = order
+                .field
+                .get(document)
+                .expect("Documents without the field are excluded.");
+            order.directed(compare(&field, value))
+        })
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+fn in_collection(query: &StructuredQuery, document: &Document) -> bool {
+    if document.name.is_empty() || query.from.is_empty() {
+        return true;
+    }
+
+    let segments: Vec<&str> = document.name.split('/').collect();
+    let collection_id = segments[segments.len().saturating_sub(2)];
+
+    query
+        .from
+        .iter()
+        .any(|selector| selector.collection_id == collection_id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::{Direction, FieldOperator, QueryBuilder, UnaryOperator};
+    use crate::{from_document, to_document};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct City {
+        name: String,
+        state: String,
+        population: Option<f64>,
+        regions: Vec<String>,
+    }
+
+    fn city(name: &str, state: &str, population: Option<f64>, regions: &[&str]) -> City {
+        City {
+            name: name.to_string(),
+            state: state.to_string(),
+            population,
+            regions: regions.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    fn cities() -> Vec<City> {
+        vec![
+            city("San Francisco", "CA", Some(860000.0), &["west_coast", "norcal"]),
+            city("Los Angeles", "CA", Some(3900000.0), &["west_coast", "socal"]),
+            city("Washington", "DC", Some(680000.0), &["east_coast"]),
+            city("Tokyo", "", Some(9000000.0), &["kanto", "honshu"]),
+            city("Beijing", "", Some(f64::NAN), &["jingjinji", "hebei"]),
+            city("Atlantis", "", None, &[]),
+        ]
+    }
+
+    fn documents() -> Vec<Document> {
+        cities()
+            .iter()
+            .enumerate()
+            .map(|(i, city)| {
+                let mut document = to_document(city).unwrap();
+                document.name = format!("projects/p/databases/(default)/documents/cities/{}", i);
+                document
+            })
+            .collect()
+    }
+
+    fn names(query: StructuredQuery) -> Vec<String> {
+        run_query(&query, documents())
+            .unwrap()
+            .into_iter()
+            .map(|document| from_document::<City>(document).unwrap().name)
+            .collect()
+    }
+
+    fn cities_query() -> QueryBuilder<City> {
+        QueryBuilder::new("cities")
+    }
+
+    #[test]
+    fn test_field_filters() {
+        assert_eq!(
+            vec!["San Francisco", "Los Angeles"],
+            names(
+                cities_query()
+                    .where_field("state", FieldOperator::Equal, "CA")
+                    .unwrap()
+                    .build()
+            )
+        );
+
+        // Ordered by population, which is implied by the inequality. NaN and
+        // null are excluded.
+        assert_eq!(
+            vec!["Washington", "San Francisco", "Los Angeles"],
+            names(
+                cities_query()
+                    .where_field("population", FieldOperator::LessThan, &5000000)
+                    .unwrap()
+                    .build()
+            )
+        );
+
+        assert_eq!(
+            vec!["Atlantis", "Beijing", "Tokyo"],
+            names(
+                cities_query()
+                    .where_field("state", FieldOperator::NotIn, &["CA", "DC"])
+                    .unwrap()
+                    .order_by("__name__", Direction::Descending)
+                    .unwrap()
+                    .build()
+            )
+        );
+
+        assert_eq!(
+            vec!["San Francisco", "Washington"],
+            names(
+                cities_query()
+                    .where_field(
+                        "regions",
+                        FieldOperator::ArrayContainsAny,
+                        &["norcal", "east_coast"]
+                    )
+                    .unwrap()
+                    .build()
+            )
+        );
+
+        assert_eq!(
+            vec!["Los Angeles"],
+            names(
+                cities_query()
+                    .where_field("regions", FieldOperator::ArrayContains, "socal")
+                    .unwrap()
+                    .build()
+            )
+        );
+
+        assert_eq!(
+            vec!["Tokyo", "Beijing"],
+            names(
+                cities_query()
+                    .where_field("name", FieldOperator::In, &["Tokyo", "Beijing", "Paris"])
+                    .unwrap()
+                    .build()
+            )
+        );
+    }
+
+    #[test]
+    fn test_not_equal_excludes_null_and_nan() {
+        // Atlantis has a null population and Beijing a NaN one.
+        assert_eq!(
+            vec!["San Francisco", "Los Angeles", "Tokyo"],
+            names(
+                cities_query()
+                    .where_field("population", FieldOperator::NotEqual, &680000)
+                    .unwrap()
+                    .build()
+            )
+        );
+    }
+
+    #[test]
+    fn test_not_in_excludes_null() {
+        // NaN is not in the list, so Beijing matches, ordered before the
+        // numbers.
+        assert_eq!(
+            vec!["Beijing", "San Francisco", "Los Angeles", "Tokyo"],
+            names(
+                cities_query()
+                    .where_field("population", FieldOperator::NotIn, &[680000])
+                    .unwrap()
+                    .build()
+            )
+        );
+    }
+
+    #[test]
+    fn test_unary_filters() {
+        assert_eq!(
+            vec!["Beijing"],
+            names(
+                cities_query()
+                    .where_unary("population", UnaryOperator::IsNan)
+                    .unwrap()
+                    .build()
+            )
+        );
+
+        assert_eq!(
+            vec!["Atlantis"],
+            names(
+                cities_query()
+                    .where_unary("population", UnaryOperator::IsNull)
+                    .unwrap()
+                    .build()
+            )
+        );
+    }
+
+    #[test]
+    fn test_composite_or() {
+        let query = cities_query();
+        let filter = Filter {
+            filter_type: Some(FilterType::CompositeFilter(CompositeFilter {
+                op: COMPOSITE_OR,
+                filters: vec![
+                    query
+                        .field_filter("state", FieldOperator::Equal, "DC")
+                        .unwrap(),
+                    query
+                        .unary_filter("population", UnaryOperator::IsNull)
+                        .unwrap(),
+                ],
+            })),
+        };
+
+        assert_eq!(vec!["Washington", "Atlantis"], names(query.filter(filter).build()));
+    }
+
+    #[test]
+    fn test_order_cursors_limit() {
+        let query = || {
+            cities_query()
+                .order_by("state", Direction::Ascending)
+                .unwrap()
+                .order_by("name", Direction::Descending)
+                .unwrap()
+        };
+
+        assert_eq!(
+            vec![
+                "Tokyo",
+                "Beijing",
+                "Atlantis",
+                "San Francisco",
+                "Los Angeles",
+                "Washington"
+            ],
+            names(query().build())
+        );
+
+        assert_eq!(
+            vec!["San Francisco", "Los Angeles"],
+            names(query().start_at(&("CA",)).unwrap().end_before(&("DC",)).unwrap().build())
+        );
+
+        assert_eq!(
+            vec!["Los Angeles", "Washington"],
+            names(query().start_after(&("CA", "San Francisco")).unwrap().build())
+        );
+
+        assert_eq!(
+            vec!["Beijing", "Atlantis", "San Francisco"],
+            names(
+                query()
+                    .end_at(&("CA", "San Francisco"))
+                    .unwrap()
+                    .offset(1)
+                    .limit(3)
+                    .build()
+            )
+        );
+    }
+
+    #[test]
+    fn test_projection_and_collection() {
+        let query = cities_query()
+            .where_field("name", FieldOperator::Equal, "Tokyo")
+            .unwrap()
+            .select(&["state"])
+            .unwrap()
+            .build();
+
+        let mut documents = documents();
+        let mut other = documents[3].clone();
+        other.name = "projects/p/databases/(default)/documents/towns/3".to_string();
+        documents.push(other);
+
+        let results = run_query(&query, documents).unwrap();
+
+        assert_eq!(1, results.len());
+        assert_eq!(
+            "projects/p/databases/(default)/documents/cities/3",
+            results[0].name
+        );
+        assert_eq!(vec!["state"], results[0].fields.keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_invalid_query() {
+        let query = StructuredQuery {
+            r#where: Some(Filter {
+                filter_type: Some(FilterType::FieldFilter(FieldFilter {
+                    field: Some(FieldReference {
+                        field_path: "name".to_string(),
+                    }),
+                    op: FieldOperator::In as i32,
+                    value: Some(crate::to_grpc_value(&"Tokyo").unwrap()),
+                })),
+            }),
+            ..StructuredQuery::default()
+        };
+
+        assert!(matches!(
+            run_query(&query, documents()),
+            Err(EvaluationError::InvalidOperand(_))
+        ));
+
+        let query = cities_query()
+            .order_by("name", Direction::Ascending)
+            .unwrap()
+            .start_at(&("a", "b", "c"))
+            .unwrap()
+            .build();
+
+        assert_eq!(
+            Err(EvaluationError::TooManyCursorValues),
+            run_query(&query, documents())
+        );
+    }
+}
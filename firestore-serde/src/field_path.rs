@@ -0,0 +1,346 @@
+#[cfg(feature = "googapis")]
+use crate::firestore::{value::ValueType, Document, MapValue, Value};
+use std::borrow::Cow;
+#[cfg(feature = "googapis")]
+use std::collections::HashMap;
+use std::{fmt::Display, str::FromStr};
+
+/// A path to a (possibly nested) field of a document, e.g. `address.city`.
+///
+/// Paths are formatted in Firestore's syntax, where segments are separated
+/// by dots, and segments which are not simple identifiers (matching
+/// `[a-zA-Z_][a-zA-Z_0-9]*`) are quoted with backticks, e.g.
+/// ``address.`zip code` ``. Within backticks, `` ` `` and `\` are escaped
+/// with a backslash.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FieldPath {
+    segments: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidFieldPath(pub String);
+
+impl Display for InvalidFieldPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:?} is not a valid field path.", self.0)
+    }
+}
+
+impl std::error::Error for InvalidFieldPath {}
+
+#[cfg(feature = "googapis")]
+/// Types which hold a map of fields that a `FieldPath` can be resolved in.
+pub trait HasFields {
+    fn fields(&self) -> &HashMap<String, Value>;
+
+    fn fields_mut(&mut self) -> &mut HashMap<String, Value>;
+}
+
+#[cfg(feature = "googapis")]
+impl HasFields for Document {
+    fn fields(&self) -> &HashMap<String, Value> {
+        &self.fields
+    }
+
+    fn fields_mut(&mut self) -> &mut HashMap<String, Value> {
+        &mut self.fields
+    }
+}
+
+#[cfg(feature = "googapis")]
+impl HasFields for MapValue {
+    fn fields(&self) -> &HashMap<String, Value> {
+        &self.fields
+    }
+
+    fn fields_mut(&mut self) -> &mut HashMap<String, Value> {
+        &mut self.fields
+    }
+}
+
+#[cfg(feature = "googapis")]
+impl HasFields for HashMap<String, Value> {
+    fn fields(&self) -> &HashMap<String, Value> {
+        self
+    }
+
+    fn fields_mut(&mut self) -> &mut HashMap<String, Value> {
+        self
+    }
+}
+
+impl FieldPath {
+    /// Builds a path from unescaped segments.
+    ///
+    /// Panics if `segments` is empty or any segment is empty.
+    pub fn new<I, S>(segments: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let segments: Vec<String> = segments.into_iter().map(Into::into).collect();
+
+        assert!(
+            !segments.is_empty() && segments.iter().all(|s| !s.is_empty()),
+            "Field paths must have at least one segment, and segments must not be empty."
+        );
+
+        FieldPath { segments }
+    }
+
+    /// Returns a new path with `segment` appended.
+    ///
+    /// Panics if `segment` is empty.
+    pub fn child(&self, segment: impl Into<String>) -> Self {
+        let mut path = self.clone();
+        path.push(segment);
+        path
+    }
+
+    /// Appends `segment` to the path.
+    ///
+    /// Panics if `segment` is empty.
+    pub fn push(&mut self, segment: impl Into<String>) {
+        let segment = segment.into();
+        assert!(
+            !segment.is_empty(),
+            "Field path segments must not be empty."
+        );
+        self.segments.push(segment);
+    }
+
+    /// The unescaped segments of the path.
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    #[cfg(feature = "googapis")]
+    /// Returns the value at this path, if every segment but the last
+    /// resolves to a map and the last segment is present.
+    pub fn get<'a, F: HasFields>(&self, container: &'a F) -> Option<&'a Value> {
+        let (last, parents) = self.segments.split_last().expect("Path is never empty.");
+        let mut fields = container.fields();
+
+        for segment in parents {
+            match fields.get(segment) {
+                Some(Value {
+                    value_type: Some(ValueType::MapValue(MapValue { fields: inner })),
+                }) => fields = inner,
+                _ => return None,
+            }
+        }
+
+        fields.get(last)
+    }
+
+    #[cfg(feature = "googapis")]
+    /// Stores `value` at this path, returning the value it replaced.
+    ///
+    /// Missing intermediate maps are created, and intermediate values which
+    /// are not maps are replaced with maps, as Firestore does when applying
+    /// an update mask.
+    pub fn set<F: HasFields>(&self, container: &mut F, value: Value) -> Option<Value> {
+        let (last, parents) = self.segments.split_last().expect("Path is never empty.");
+        let mut fields = container.fields_mut();
+
+        for segment in parents {
+            let entry = fields.entry(segment.clone()).or_default();
+
+            if !matches!(entry.value_type, Some(ValueType::MapValue(_))) {
+                entry.value_type = Some(ValueType::MapValue(MapValue::default()));
+            }
+
+            fields = match &mut entry.value_type {
+                Some(ValueType::MapValue(MapValue { fields })) => fields,
+                _ => unreachable!("Just ensured the entry is a map."),
+            };
+        }
+
+        fields.insert(last.clone(), value)
+    }
+
+    #[cfg(feature = "googapis")]
+    /// Removes and returns the value at this path, if present. Maps which
+    /// become empty as a result are left in place.
+    pub fn remove<F: HasFields>(&self, container: &mut F) -> Option<Value> {
+        let (last, parents) = self.segments.split_last().expect("Path is never empty.");
+        let mut fields = container.fields_mut();
+
+        for segment in parents {
+            match fields.get_mut(segment) {
+                Some(Value {
+                    value_type: Some(ValueType::MapValue(MapValue { fields: inner })),
+                }) => fields = inner,
+                _ => return None,
+            }
+        }
+
+        fields.remove(last)
+    }
+}
+
+impl Display for FieldPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format_segments(&self.segments))
+    }
+}
+
+impl FromStr for FieldPath {
+    type Err = InvalidFieldPath;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidFieldPath(path.to_string());
+        let mut segments = Vec::new();
+        let mut chars = path.chars().peekable();
+
+        loop {
+            let mut segment = String::new();
+
+            if chars.peek() == Some(&'`') {
+                chars.next();
+
+                loop {
+                    match chars.next().ok_or_else(invalid)? {
+                        '`' => break,
+                        '\\' => segment.push(chars.next().ok_or_else(invalid)?),
+                        c => segment.push(c),
+                    }
+                }
+
+                if segment.is_empty() {
+                    return Err(invalid());
+                }
+            } else {
+                while let Some(&c) = chars.peek() {
+                    if c == '.' {
+                        break;
+                    }
+                    segment.push(c);
+                    chars.next();
+                }
+
+                if !is_simple_segment(&segment) {
+                    return Err(invalid());
+                }
+            }
+
+            segments.push(segment);
+
+            match chars.next() {
+                None => break,
+                Some('.') => (),
+                Some(_) => return Err(invalid()),
+            }
+        }
+
+        Ok(FieldPath { segments })
+    }
+}
+
+/// Returns true if `segment` can appear in a field path without quoting, i.e.
+/// it matches `[a-zA-Z_][a-zA-Z_0-9]*`.
+fn is_simple_segment(segment: &str) -> bool {
+    let mut chars = segment.chars();
+
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Quotes a field name with backticks if it is not a simple identifier.
+pub(crate) fn escape_segment(segment: &str) -> Cow<'_, str> {
+    if is_simple_segment(segment) {
+        Cow::Borrowed(segment)
+    } else {
+        Cow::Owned(format!(
+            "`{}`",
+            segment.replace('\\', "\\\\").replace('`', "\\`")
+        ))
+    }
+}
+
+/// Formats a list of field names as a dotted field path.
+pub(crate) fn format_segments<S: AsRef<str>>(segments: &[S]) -> String {
+    segments
+        .iter()
+        .map(|segment| escape_segment(segment.as_ref()))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(all(test, feature = "googapis"))]
+mod test {
+    use super::{FieldPath, InvalidFieldPath};
+    use crate::firestore::{value::ValueType, Document, MapValue, Value};
+
+    fn int_value(v: i64) -> Value {
+        Value {
+            value_type: Some(ValueType::IntegerValue(v)),
+        }
+    }
+
+    #[test]
+    fn test_parse_and_format() {
+        let path: FieldPath = "address.`zip code`.`a\\`b\\\\c`._x1".parse().unwrap();
+
+        assert_eq!(
+            FieldPath::new(vec!["address", "zip code", "a`b\\c", "_x1"]),
+            path
+        );
+        assert_eq!("address.`zip code`.`a\\`b\\\\c`._x1", path.to_string());
+
+        assert_eq!("`1`", FieldPath::new(vec!["1"]).to_string());
+        assert_eq!("`a.b`", FieldPath::new(vec!["a.b"]).to_string());
+        assert_eq!("a.b", FieldPath::new(vec!["a"]).child("b").to_string());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for path in &[
+            "", "a.", ".a", "a..b", "1a", "a-b", "``", "`a", "`a`b", "a.`b\\",
+        ] {
+            assert_eq!(
+                Err(InvalidFieldPath(path.to_string())),
+                path.parse::<FieldPath>(),
+                "{:?}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_get_set_remove() {
+        let mut document = Document::default();
+        let path: FieldPath = "a.b.c".parse().unwrap();
+
+        assert_eq!(None, path.get(&document));
+        assert_eq!(None, path.set(&mut document, int_value(1)));
+        assert_eq!(Some(&int_value(1)), path.get(&document));
+        assert_eq!(Some(int_value(1)), path.set(&mut document, int_value(2)));
+
+        let parent: FieldPath = "a.b".parse().unwrap();
+        match parent.get(&document) {
+            Some(Value {
+                value_type: Some(ValueType::MapValue(map)),
+            }) => assert_eq!(Some(&int_value(2)), FieldPath::new(vec!["c"]).get(map)),
+            other => panic!("Expected a map, got {:?}", other),
+        }
+
+        // Setting a path through a non-map value replaces it with a map.
+        let through: FieldPath = "a.b.c.d".parse().unwrap();
+        through.set(&mut document, int_value(3));
+        assert_eq!(Some(&int_value(3)), through.get(&document));
+
+        assert_eq!(Some(int_value(3)), through.remove(&mut document));
+        assert_eq!(None, through.remove(&mut document));
+        assert_eq!(
+            Some(&Value {
+                value_type: Some(ValueType::MapValue(MapValue::default()))
+            }),
+            path.get(&document)
+        );
+    }
+}
@@ -1,5 +1,8 @@
 #[cfg(feature = "google-firestore-v1")]
 pub use googapis::google::firestore::v1::*;
 
-#[cfg(feature = "google-firestore-v1beta1")]
+#[cfg(all(
+    feature = "google-firestore-v1beta1",
+    not(feature = "google-firestore-v1")
+))]
 pub use googapis::google::firestore::v1beta1::*;
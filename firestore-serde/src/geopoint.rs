@@ -0,0 +1,258 @@
+use crate::GEOPOINT_MAGIC;
+use prost::Message;
+use serde::{
+    de::{Error, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_bytes::ByteBuf;
+
+/// A latitude/longitude pair, stored in Firestore as a `GeoPointValue`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GeoPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl GeoPoint {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        GeoPoint {
+            latitude,
+            longitude,
+        }
+    }
+}
+
+/// The protobuf encoding of a `google.type.LatLng`, in which geopoints are
+/// passed through the serializer and deserializer as bytes.
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct LatLng {
+    #[prost(double, tag = "1")]
+    pub latitude: f64,
+    #[prost(double, tag = "2")]
+    pub longitude: f64,
+}
+
+impl From<LatLng> for GeoPoint {
+    fn from(lat_lng: LatLng) -> Self {
+        GeoPoint::new(lat_lng.latitude, lat_lng.longitude)
+    }
+}
+
+impl From<GeoPoint> for LatLng {
+    fn from(point: GeoPoint) -> Self {
+        LatLng {
+            latitude: point.latitude,
+            longitude: point.longitude,
+        }
+    }
+}
+
+#[cfg(feature = "googapis")]
+impl From<googapis::google::r#type::LatLng> for GeoPoint {
+    fn from(lat_lng: googapis::google::r#type::LatLng) -> Self {
+        GeoPoint::new(lat_lng.latitude, lat_lng.longitude)
+    }
+}
+
+#[cfg(feature = "googapis")]
+impl From<GeoPoint> for googapis::google::r#type::LatLng {
+    fn from(point: GeoPoint) -> Self {
+        googapis::google::r#type::LatLng {
+            latitude: point.latitude,
+            longitude: point.longitude,
+        }
+    }
+}
+
+impl From<(f64, f64)> for GeoPoint {
+    fn from((latitude, longitude): (f64, f64)) -> Self {
+        GeoPoint::new(latitude, longitude)
+    }
+}
+
+impl From<GeoPoint> for (f64, f64) {
+    fn from(point: GeoPoint) -> Self {
+        (point.latitude, point.longitude)
+    }
+}
+
+impl Serialize for GeoPoint {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for GeoPoint {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize(deserializer)
+    }
+}
+
+pub fn serialize<P, S>(point: &P, serializer: S) -> Result<S::Ok, S::Error>
+where
+    P: Clone + Into<GeoPoint>,
+    S: Serializer,
+{
+    let lat_lng: LatLng = point.clone().into().into();
+    let v = ByteBuf::from(lat_lng.encode_to_vec());
+
+    serializer.serialize_newtype_struct(GEOPOINT_MAGIC, &v)
+}
+
+pub fn deserialize<'de, P, D>(deserializer: D) -> Result<P, D::Error>
+where
+    P: From<GeoPoint>,
+    D: Deserializer<'de>,
+{
+    let point = deserializer.deserialize_newtype_struct(GEOPOINT_MAGIC, GeoPointVisitor)?;
+
+    Ok(P::from(point))
+}
+
+struct GeoPointVisitor;
+
+impl<'de> Visitor<'de> for GeoPointVisitor {
+    type Value = GeoPoint;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a geopoint")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<GeoPoint, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let buf = ByteBuf::deserialize(deserializer)?;
+        let lat_lng = LatLng::decode(buf.as_slice()).map_err(D::Error::custom)?;
+
+        Ok(lat_lng.into())
+    }
+}
+
+#[cfg(all(test, feature = "googapis"))]
+mod test {
+    use super::GeoPoint;
+    use crate::deserialize::DeserializationError;
+    use crate::firestore::{value::ValueType, MapValue, Value};
+    use crate::serialize::SerializationError;
+    use crate::{from_grpc_value, to_grpc_value};
+    use googapis::google::r#type::LatLng;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct StructWithGeoPoint {
+        point: GeoPoint,
+        #[serde(with = "crate::geopoint")]
+        pair: (f64, f64),
+    }
+
+    #[test]
+    fn test_serialize_geopoint() {
+        let point = GeoPoint::new(43.65, -79.38);
+
+        let result = to_grpc_value(&point).unwrap();
+
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::GeoPointValue(LatLng {
+                    latitude: 43.65,
+                    longitude: -79.38
+                }))
+            },
+            result
+        );
+
+        assert_eq!(point, from_grpc_value(&result).unwrap());
+    }
+
+    #[test]
+    fn test_serialize_in_struct() {
+        let st = StructWithGeoPoint {
+            point: GeoPoint::new(1.5, 2.5),
+            pair: (-3.5, 4.5),
+        };
+
+        let result = to_grpc_value(&st).unwrap();
+
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::MapValue(MapValue {
+                    fields: vec![
+                        (
+                            "point".to_string(),
+                            Value {
+                                value_type: Some(ValueType::GeoPointValue(LatLng {
+                                    latitude: 1.5,
+                                    longitude: 2.5
+                                }))
+                            }
+                        ),
+                        (
+                            "pair".to_string(),
+                            Value {
+                                value_type: Some(ValueType::GeoPointValue(LatLng {
+                                    latitude: -3.5,
+                                    longitude: 4.5
+                                }))
+                            }
+                        )
+                    ]
+                    .into_iter()
+                    .collect()
+                }))
+            },
+            result
+        );
+
+        assert_eq!(st, from_grpc_value(&result).unwrap());
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    #[serde(untagged)]
+    enum GeoPointOrName {
+        Point(GeoPoint),
+        Name(String),
+    }
+
+    #[test]
+    fn test_serialize_untagged() {
+        let point = GeoPointOrName::Point(GeoPoint::new(1.5, 2.5));
+        let result = to_grpc_value(&point).unwrap();
+        assert_eq!(point, from_grpc_value(&result).unwrap());
+
+        let name = GeoPointOrName::Name("Toronto".to_string());
+        let result = to_grpc_value(&name).unwrap();
+        assert_eq!(name, from_grpc_value(&result).unwrap());
+    }
+
+    #[test]
+    fn test_geopoint_out_of_range() {
+        assert_eq!(
+            SerializationError::LatitudeOutOfRange(90.5),
+            to_grpc_value(&GeoPoint::new(90.5, 0.0)).unwrap_err()
+        );
+
+        assert_eq!(
+            SerializationError::LongitudeOutOfRange(-180.5),
+            to_grpc_value(&GeoPoint::new(0.0, -180.5)).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_geopoint_wrong_type() {
+        let value = Value {
+            value_type: Some(ValueType::DoubleValue(1.0)),
+        };
+
+        assert_eq!(
+            DeserializationError::WrongType("geopoint", "double"),
+            from_grpc_value::<GeoPoint>(&value).unwrap_err()
+        );
+    }
+}
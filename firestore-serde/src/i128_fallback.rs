@@ -0,0 +1,206 @@
+use crate::deserialize::{DeserializationError, Result};
+use crate::{FirestoreValue, ValueKind};
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// Determines how `i128` and `u128` values outside the range of `i64` are
+/// stored. Values in range are always stored as an `IntegerValue`.
+///
+/// The `DeserializerConfig` must use the same fallback to read the values
+/// back.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum I128Fallback {
+    /// Store a `StringValue` of the decimal digits. This is the default.
+    #[default]
+    String,
+    /// Store a 16-byte, big-endian `BytesValue`, in two's complement for
+    /// `i128`. The bytes don't record the sign, so values must be read back
+    /// into the type they were written from.
+    Bytes,
+}
+
+impl I128Fallback {
+    pub(crate) fn store_i128<V: FirestoreValue>(self, v: i128) -> V {
+        match self {
+            I128Fallback::String => V::string(v.to_string()),
+            I128Fallback::Bytes => V::bytes(v.to_be_bytes().to_vec()),
+        }
+    }
+
+    pub(crate) fn store_u128<V: FirestoreValue>(self, v: u128) -> V {
+        match self {
+            I128Fallback::String => V::string(v.to_string()),
+            I128Fallback::Bytes => V::bytes(v.to_be_bytes().to_vec()),
+        }
+    }
+
+    /// Reads a value stored by `store_i128`, or returns `None` if `value`
+    /// isn't in this fallback's form. Integers are left to the caller.
+    pub(crate) fn load_i128<V>(self, value: &ValueKind<'_, V>) -> Option<Result<i128>> {
+        match (self, value) {
+            (I128Fallback::String, ValueKind::String(s)) => parse_integer("i128", s),
+            (I128Fallback::Bytes, ValueKind::Bytes(bytes)) => <[u8; 16]>::try_from(*bytes)
+                .ok()
+                .map(i128::from_be_bytes)
+                .map(Ok),
+            _ => None,
+        }
+    }
+
+    /// Like `load_i128`, for values stored by `store_u128`.
+    pub(crate) fn load_u128<V>(self, value: &ValueKind<'_, V>) -> Option<Result<u128>> {
+        match (self, value) {
+            (I128Fallback::String, ValueKind::String(s)) => parse_integer("u128", s),
+            (I128Fallback::Bytes, ValueKind::Bytes(bytes)) => <[u8; 16]>::try_from(*bytes)
+                .ok()
+                .map(u128::from_be_bytes)
+                .map(Ok),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a decimal string, failing with `IntRange` if it's an integer which
+/// doesn't fit in `T`, or returning `None` if it isn't an integer at all.
+fn parse_integer<T: FromStr>(typ: &'static str, s: &str) -> Option<Result<T>> {
+    match s.parse() {
+        Ok(v) => Some(Ok(v)),
+        Err(_) if is_integer(s) => Some(Err(DeserializationError::IntRange(typ, s.to_string()))),
+        Err(_) => None,
+    }
+}
+
+fn is_integer(s: &str) -> bool {
+    let digits = s
+        .strip_prefix('-')
+        .or_else(|| s.strip_prefix('+'))
+        .unwrap_or(s);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(all(test, feature = "googapis"))]
+mod test {
+    use super::*;
+    use crate::firestore::{value::ValueType, Value};
+    use crate::{
+        from_grpc_value, from_grpc_value_with, read_value, to_grpc_value, to_grpc_value_with,
+        write_value, DeserializerConfig, SerializerConfig,
+    };
+    use bytes::BytesMut;
+    use serde::{Deserialize, Serialize};
+
+    fn value(value_type: ValueType) -> Value {
+        Value {
+            value_type: Some(value_type),
+        }
+    }
+
+    #[test]
+    fn test_small_values_are_integers() {
+        assert_eq!(
+            value(ValueType::IntegerValue(-5)),
+            to_grpc_value(&-5i128).unwrap()
+        );
+        assert_eq!(
+            value(ValueType::IntegerValue(i64::MAX)),
+            to_grpc_value(&(i64::MAX as u128)).unwrap()
+        );
+
+        assert_eq!(
+            -5i128,
+            from_grpc_value(&value(ValueType::IntegerValue(-5))).unwrap()
+        );
+        assert_eq!(
+            5u128,
+            from_grpc_value(&value(ValueType::IntegerValue(5))).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_string_fallback() {
+        let v = to_grpc_value(&i128::MIN).unwrap();
+        assert_eq!(
+            value(ValueType::StringValue(
+                "-170141183460469231731687303715884105728".to_string()
+            )),
+            v
+        );
+        assert_eq!(i128::MIN, from_grpc_value(&v).unwrap());
+
+        let v = to_grpc_value(&u128::MAX).unwrap();
+        assert_eq!(u128::MAX, from_grpc_value(&v).unwrap());
+
+        let mut buf = BytesMut::new();
+        write_value(&u128::MAX, &mut buf).unwrap();
+        assert_eq!(u128::MAX, read_value(&buf).unwrap());
+    }
+
+    #[test]
+    fn test_bytes_fallback() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Account {
+            id: u128,
+            balance_micros: i128,
+        }
+
+        let account = Account {
+            id: 0x0123_4567_89ab_cdef_0123_4567_89ab_cdef,
+            balance_micros: -(1 << 100),
+        };
+
+        let v = to_grpc_value_with(
+            &account,
+            SerializerConfig::new().with_i128_fallback(I128Fallback::Bytes),
+        )
+        .unwrap();
+
+        assert_eq!(
+            account,
+            from_grpc_value_with(
+                &v,
+                DeserializerConfig::new().with_i128_fallback(I128Fallback::Bytes)
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(
+            Err(DeserializationError::IntRange("u128", "-1".to_string())),
+            from_grpc_value::<u128>(&value(ValueType::IntegerValue(-1)))
+        );
+
+        let bytes = value(ValueType::BytesValue(vec![0; 16]));
+        assert_eq!(
+            Err(DeserializationError::WrongType("i128", "bytes")),
+            from_grpc_value::<i128>(&bytes)
+        );
+    }
+
+    #[test]
+    fn test_string_range() {
+        let negative = value(ValueType::StringValue("-5".to_string()));
+        assert_eq!(
+            Err(DeserializationError::IntRange("u128", "-5".to_string())),
+            from_grpc_value::<u128>(&negative)
+        );
+        assert_eq!(Ok(-5), from_grpc_value::<i128>(&negative));
+
+        let huge = "9".repeat(40);
+        assert_eq!(
+            Err(DeserializationError::IntRange("i128", huge.clone())),
+            from_grpc_value::<i128>(&value(ValueType::StringValue(huge.clone())))
+        );
+        assert_eq!(
+            Err(DeserializationError::IntRange("u128", huge.clone())),
+            from_grpc_value::<u128>(&value(ValueType::StringValue(huge)))
+        );
+
+        let text = value(ValueType::StringValue("five".to_string()));
+        assert_eq!(
+            Err(DeserializationError::WrongType("u128", "string")),
+            from_grpc_value::<u128>(&text)
+        );
+    }
+}
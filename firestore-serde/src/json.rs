@@ -0,0 +1,466 @@
+//! Conversions between Firestore values and `serde_json::Value`, enabled by
+//! the `json` feature.
+//!
+//! Since neither `Value` nor `serde_json::Value` is defined in this crate,
+//! the `From`/`TryFrom` implementations are on two wrappers of
+//! `serde_json::Value`: `Json` converts lossily, and `StrictJson` strictly.
+//! The functions below do the same conversions without a wrapper.
+//!
+//! ```rust,ignore
+//! let Json(json) = Json::from(&value);
+//! let value = Value::try_from(StrictJson(json))?;
+//! ```
+//!
+//! Firestore values which JSON has no type for are converted as follows:
+//!
+//! | Firestore        | JSON                                           |
+//! |------------------|------------------------------------------------|
+//! | timestamp        | RFC 3339 string, e.g. `"2022-02-03T04:05:06Z"` |
+//! | bytes            | base64 string                                  |
+//! | reference        | the document's resource name, as a string      |
+//! | geopoint         | `{"latitude": .., "longitude": ..}`            |
+//! | NaN and infinity | `"NaN"`, `"Infinity"` and `"-Infinity"`        |
+//!
+//! Timestamps which RFC 3339 can't represent (such as those with negative
+//! nanoseconds) become `{"seconds": .., "nanos": ..}` instead.
+//!
+//! Since these come back from JSON as strings (or a map), the conversion is
+//! lossy. The `_strict` variants fail with `JsonError::Unrepresentable`
+//! instead, as they do for a value with no type, which is otherwise
+//! converted to `null`. In the other direction, JSON integers beyond the range of `i64`
+//! become doubles, or fail with `JsonError::IntegerOutOfRange` in the strict
+//! variants.
+
+use crate::firestore::{value::ValueType, ArrayValue, Document, MapValue, Value};
+use crate::rest::format_timestamp;
+use serde_json::{Map, Number};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Display;
+
+#[derive(Debug, PartialEq)]
+pub enum JsonError {
+    /// A Firestore value with no exact JSON representation, converted in
+    /// strict mode.
+    Unrepresentable(&'static str),
+    /// A JSON integer outside the range of `i64`, converted in strict mode.
+    IntegerOutOfRange(u64),
+    /// A document was converted from JSON which is not an object.
+    NotAnObject,
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::Unrepresentable(typ) => {
+                writeln!(f, "A {} value has no exact JSON representation.", typ)
+            }
+            JsonError::IntegerOutOfRange(v) => writeln!(
+                f,
+                "Integer {} is too large for Firestore, which uses 64-bit signed integers.",
+                v
+            ),
+            JsonError::NotAnObject => writeln!(f, "Documents must be JSON objects."),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+pub type Result<T> = std::result::Result<T, JsonError>;
+
+/// A `serde_json::Value` which Firestore values and documents convert to and
+/// from lossily, like `to_json` and `from_json`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Json(pub serde_json::Value);
+
+/// A `serde_json::Value` which Firestore values and documents convert to and
+/// from strictly, like `to_json_strict` and `from_json_strict`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StrictJson(pub serde_json::Value);
+
+impl From<&Value> for Json {
+    fn from(value: &Value) -> Self {
+        Json(to_json(value))
+    }
+}
+
+impl From<Value> for Json {
+    fn from(value: Value) -> Self {
+        Json::from(&value)
+    }
+}
+
+impl From<Json> for Value {
+    fn from(json: Json) -> Self {
+        from_json(json.0)
+    }
+}
+
+impl From<&Document> for Json {
+    fn from(document: &Document) -> Self {
+        Json(document_to_json(document))
+    }
+}
+
+impl From<Document> for Json {
+    fn from(document: Document) -> Self {
+        Json::from(&document)
+    }
+}
+
+impl TryFrom<Json> for Document {
+    type Error = JsonError;
+
+    fn try_from(json: Json) -> Result<Self> {
+        document_from_json(json.0)
+    }
+}
+
+impl TryFrom<&Value> for StrictJson {
+    type Error = JsonError;
+
+    fn try_from(value: &Value) -> Result<Self> {
+        to_json_strict(value).map(StrictJson)
+    }
+}
+
+impl TryFrom<Value> for StrictJson {
+    type Error = JsonError;
+
+    fn try_from(value: Value) -> Result<Self> {
+        StrictJson::try_from(&value)
+    }
+}
+
+impl TryFrom<StrictJson> for Value {
+    type Error = JsonError;
+
+    fn try_from(json: StrictJson) -> Result<Self> {
+        from_json_strict(json.0)
+    }
+}
+
+impl TryFrom<&Document> for StrictJson {
+    type Error = JsonError;
+
+    fn try_from(document: &Document) -> Result<Self> {
+        document_to_json_strict(document).map(StrictJson)
+    }
+}
+
+impl TryFrom<Document> for StrictJson {
+    type Error = JsonError;
+
+    fn try_from(document: Document) -> Result<Self> {
+        StrictJson::try_from(&document)
+    }
+}
+
+impl TryFrom<StrictJson> for Document {
+    type Error = JsonError;
+
+    fn try_from(json: StrictJson) -> Result<Self> {
+        document_from_json_strict(json.0)
+    }
+}
+
+/// Converts a Firestore value to JSON, using the mapping described in the
+/// module documentation for types JSON does not have.
+pub fn to_json(value: &Value) -> serde_json::Value {
+    convert_to_json(value, false).expect("Lossy conversion never fails.")
+}
+
+/// Converts a Firestore value to JSON, failing if it contains any value
+/// which JSON cannot represent exactly.
+pub fn to_json_strict(value: &Value) -> Result<serde_json::Value> {
+    convert_to_json(value, true)
+}
+
+/// Converts JSON to a Firestore value. Integers outside the range of `i64`
+/// become doubles.
+pub fn from_json(json: serde_json::Value) -> Value {
+    convert_from_json(json, false).expect("Lossy conversion never fails.")
+}
+
+/// Converts JSON to a Firestore value, failing if it contains an integer
+/// outside the range of `i64`.
+pub fn from_json_strict(json: serde_json::Value) -> Result<Value> {
+    convert_from_json(json, true)
+}
+
+/// Converts the fields of a document to a JSON object. The document's name
+/// and timestamps are not included.
+pub fn document_to_json(document: &Document) -> serde_json::Value {
+    fields_to_json(&document.fields, false).expect("Lossy conversion never fails.")
+}
+
+/// Like `document_to_json`, but fails if any field contains a value which
+/// JSON cannot represent exactly.
+pub fn document_to_json_strict(document: &Document) -> Result<serde_json::Value> {
+    fields_to_json(&document.fields, true)
+}
+
+/// Converts a JSON object to a document with its entries as fields, and no
+/// name or timestamps.
+pub fn document_from_json(json: serde_json::Value) -> Result<Document> {
+    convert_document_from_json(json, false)
+}
+
+/// Like `document_from_json`, but fails if the object contains an integer
+/// outside the range of `i64`.
+pub fn document_from_json_strict(json: serde_json::Value) -> Result<Document> {
+    convert_document_from_json(json, true)
+}
+
+fn fields_to_json(fields: &HashMap<String, Value>, strict: bool) -> Result<serde_json::Value> {
+    fields
+        .iter()
+        .map(|(k, v)| Ok((k.clone(), convert_to_json(v, strict)?)))
+        .collect::<Result<Map<_, _>>>()
+        .map(serde_json::Value::Object)
+}
+
+fn convert_to_json(value: &Value, strict: bool) -> Result<serde_json::Value> {
+    let lossy = |typ: &'static str, json: serde_json::Value| {
+        if strict {
+            Err(JsonError::Unrepresentable(typ))
+        } else {
+            Ok(json)
+        }
+    };
+
+    match &value.value_type {
+        None => lossy("unset", serde_json::Value::Null),
+        Some(ValueType::NullValue(_)) => Ok(serde_json::Value::Null),
+        Some(ValueType::BooleanValue(v)) => Ok(serde_json::Value::Bool(*v)),
+        Some(ValueType::IntegerValue(v)) => Ok(serde_json::Value::Number((*v).into())),
+        Some(ValueType::DoubleValue(v)) => match Number::from_f64(*v) {
+            Some(number) => Ok(serde_json::Value::Number(number)),
+            None if v.is_nan() => lossy("NaN", "NaN".into()),
+            None if *v > 0. => lossy("infinite double", "Infinity".into()),
+            None => lossy("infinite double", "-Infinity".into()),
+        },
+        Some(ValueType::TimestampValue(v)) => lossy(
+            "timestamp",
+            match format_timestamp(v) {
+                Some(timestamp) => timestamp.into(),
+                None => serde_json::json!({"seconds": v.seconds, "nanos": v.nanos}),
+            },
+        ),
+        Some(ValueType::StringValue(v)) => Ok(serde_json::Value::String(v.clone())),
+        Some(ValueType::BytesValue(v)) => lossy("bytes", base64::encode(v).into()),
+        Some(ValueType::ReferenceValue(v)) => lossy("reference", v.clone().into()),
+        Some(ValueType::GeoPointValue(v)) => lossy(
+            "geopoint",
+            serde_json::json!({"latitude": v.latitude, "longitude": v.longitude}),
+        ),
+        Some(ValueType::ArrayValue(ArrayValue { values })) => values
+            .iter()
+            .map(|v| convert_to_json(v, strict))
+            .collect::<Result<Vec<_>>>()
+            .map(serde_json::Value::Array),
+        Some(ValueType::MapValue(MapValue { fields })) => fields_to_json(fields, strict),
+    }
+}
+
+fn convert_from_json(json: serde_json::Value, strict: bool) -> Result<Value> {
+    let value_type = match json {
+        serde_json::Value::Null => ValueType::NullValue(0),
+        serde_json::Value::Bool(v) => ValueType::BooleanValue(v),
+        serde_json::Value::Number(v) => {
+            if let Some(v) = v.as_i64() {
+                ValueType::IntegerValue(v)
+            } else if let Some(v) = v.as_u64().filter(|_| strict) {
+                return Err(JsonError::IntegerOutOfRange(v));
+            } else {
+                ValueType::DoubleValue(v.as_f64().expect("Every JSON number converts to f64."))
+            }
+        }
+        serde_json::Value::String(v) => ValueType::StringValue(v),
+        serde_json::Value::Array(values) => ValueType::ArrayValue(ArrayValue {
+            values: values
+                .into_iter()
+                .map(|v| convert_from_json(v, strict))
+                .collect::<Result<_>>()?,
+        }),
+        serde_json::Value::Object(fields) => ValueType::MapValue(MapValue {
+            fields: fields
+                .into_iter()
+                .map(|(k, v)| Ok((k, convert_from_json(v, strict)?)))
+                .collect::<Result<_>>()?,
+        }),
+    };
+
+    Ok(Value {
+        value_type: Some(value_type),
+    })
+}
+
+fn convert_document_from_json(json: serde_json::Value, strict: bool) -> Result<Document> {
+    match convert_from_json(json, strict)?.value_type {
+        Some(ValueType::MapValue(MapValue { fields })) => Ok(Document {
+            fields,
+            ..Document::default()
+        }),
+        _ => Err(JsonError::NotAnObject),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{to_document, to_grpc_value, GeoPoint};
+    use prost_types::Timestamp;
+    use serde::Serialize;
+    use serde_json::json;
+
+    #[derive(Serialize)]
+    struct APlainStruct {
+        name: String,
+        count: i64,
+        ratio: f64,
+        tags: Vec<String>,
+        parent: Option<String>,
+    }
+
+    #[test]
+    fn test_plain_document_roundtrip() {
+        let document = to_document(&APlainStruct {
+            name: "a".to_string(),
+            count: -3,
+            ratio: 1.5,
+            tags: vec!["x".to_string()],
+            parent: None,
+        })
+        .unwrap();
+
+        let json = document_to_json_strict(&document).unwrap();
+        assert_eq!(
+            json!({"name": "a", "count": -3, "ratio": 1.5, "tags": ["x"], "parent": null}),
+            json
+        );
+        assert_eq!(document, document_from_json_strict(json).unwrap());
+    }
+
+    #[test]
+    fn test_lossy_values() {
+        let cases = vec![
+            (
+                Some(ValueType::TimestampValue(Timestamp {
+                    seconds: 1_643_861_106,
+                    nanos: 0,
+                })),
+                json!("2022-02-03T04:05:06Z"),
+            ),
+            (
+                Some(ValueType::TimestampValue(Timestamp {
+                    seconds: 0,
+                    nanos: -1,
+                })),
+                json!({"seconds": 0, "nanos": -1}),
+            ),
+            (Some(ValueType::BytesValue(vec![1, 2, 3])), json!("AQID")),
+            (
+                Some(ValueType::ReferenceValue("projects/p".to_string())),
+                json!("projects/p"),
+            ),
+            (
+                to_grpc_value(&GeoPoint::new(1.5, -2.)).unwrap().value_type,
+                json!({"latitude": 1.5, "longitude": -2.0}),
+            ),
+            (Some(ValueType::DoubleValue(f64::NAN)), json!("NaN")),
+            (
+                Some(ValueType::DoubleValue(f64::INFINITY)),
+                json!("Infinity"),
+            ),
+        ];
+
+        for (value_type, expected) in cases {
+            let value = Value { value_type };
+
+            assert_eq!(expected, to_json(&value));
+            assert!(matches!(
+                to_json_strict(&value),
+                Err(JsonError::Unrepresentable(_))
+            ));
+
+            let wrapped = Value {
+                value_type: Some(ValueType::ArrayValue(ArrayValue {
+                    values: vec![value],
+                })),
+            };
+            assert!(to_json_strict(&wrapped).is_err());
+        }
+    }
+
+    #[test]
+    fn test_large_integers() {
+        let json = json!(u64::MAX);
+
+        #[allow(clippy::cast_precision_loss)]
+        let expected = Value {
+            value_type: Some(ValueType::DoubleValue(u64::MAX as f64)),
+        };
+        assert_eq!(expected, from_json(json.clone()));
+        assert_eq!(
+            Err(JsonError::IntegerOutOfRange(u64::MAX)),
+            from_json_strict(json)
+        );
+    }
+
+    #[test]
+    fn test_document_not_an_object() {
+        assert_eq!(Err(JsonError::NotAnObject), document_from_json(json!([1])));
+    }
+
+    #[test]
+    fn test_unset_values() {
+        let unset = Value { value_type: None };
+
+        assert_eq!(json!(null), to_json(&unset));
+        assert_eq!(
+            Err(JsonError::Unrepresentable("unset")),
+            to_json_strict(&unset)
+        );
+    }
+
+    #[test]
+    fn test_conversion_traits() {
+        let document = to_document(&APlainStruct {
+            name: "a".to_string(),
+            count: 1,
+            ratio: 0.5,
+            tags: vec![],
+            parent: Some("b".to_string()),
+        })
+        .unwrap();
+
+        let StrictJson(json) = StrictJson::try_from(&document).unwrap();
+        assert_eq!(Json::from(&document), Json(json.clone()));
+        assert_eq!(
+            document,
+            Document::try_from(StrictJson(json.clone())).unwrap()
+        );
+        assert_eq!(document, Document::try_from(Json(json)).unwrap());
+
+        let value = Value {
+            value_type: Some(ValueType::BytesValue(vec![1, 2, 3])),
+        };
+        assert_eq!(Json(json!("AQID")), Json::from(value.clone()));
+        assert_eq!(
+            Err(JsonError::Unrepresentable("bytes")),
+            StrictJson::try_from(value)
+        );
+
+        let json = json!(u64::MAX);
+        assert!(matches!(
+            Value::from(Json(json.clone())).value_type,
+            Some(ValueType::DoubleValue(_))
+        ));
+        assert_eq!(
+            Err(JsonError::IntegerOutOfRange(u64::MAX)),
+            Value::try_from(StrictJson(json))
+        );
+    }
+}
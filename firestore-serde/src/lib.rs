@@ -1,70 +1,383 @@
+pub use crate::config::{DeserializerConfig, SerializerConfig};
 pub use crate::deserialize::ValueDeserializer;
-use crate::firestore::{value::ValueType, Document, MapValue, Value};
-use crate::serialize::SerializationError;
+#[cfg(feature = "googapis")]
+use crate::deserialize::{DocumentDeserializer, WireDeserializer, WireDocumentDeserializer};
+pub use crate::enum_representation::EnumRepresentation;
+pub use crate::field_path::FieldPath;
+#[cfg(feature = "googapis")]
+use crate::firestore::{document_transform::FieldTransform, Document, DocumentMask, Value};
+pub use crate::geopoint::GeoPoint;
+pub use crate::i128_fallback::I128Fallback;
+#[cfg(feature = "googapis")]
+pub use crate::mask::document_mask;
+pub use crate::metadata::{CreateTime, DocumentId, DocumentName, UpdateTime};
+pub use crate::reference::DocumentReference;
+#[cfg(feature = "googapis")]
+pub use crate::serialize::DocumentSerializer;
 pub use crate::serialize::ValueSerializer;
+#[cfg(feature = "googapis")]
+use crate::serialize::{SerializationError, WireSerializer};
+#[cfg(feature = "googapis")]
+use crate::transform::Transforms;
+pub use crate::transform::{ArrayRemove, ArrayUnion, Increment, Maximum, Minimum, ServerTimestamp};
+pub use crate::u64_overflow::U64Overflow;
+pub use crate::value_model::{FirestoreValue, ValueKind};
+pub use crate::value_path::{PathSegment, ValuePath};
+#[cfg(feature = "googapis")]
+use bytes::BytesMut;
+#[cfg(feature = "googapis")]
+use prost::Message;
+#[cfg(feature = "googapis")]
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+#[cfg(feature = "googapis")]
+use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "googapis")]
+pub mod canonical;
+mod config;
 mod deserialize;
+mod enum_representation;
+#[cfg(feature = "googapis")]
+pub mod evaluate;
+pub mod field_path;
+#[cfg(feature = "googapis")]
 pub mod firestore;
+pub mod geopoint;
+mod i128_fallback;
+#[cfg(all(feature = "json", feature = "googapis"))]
+pub mod json;
+#[cfg(feature = "googapis")]
+mod mask;
+pub mod metadata;
+#[cfg(feature = "googapis")]
+pub mod ordering;
+#[cfg(feature = "googapis")]
+pub mod query;
+pub mod reference;
+#[cfg(feature = "googapis")]
+pub mod rest;
 mod serialize;
+mod transform;
+mod u64_overflow;
+#[cfg(all(feature = "google-firestore-v1", feature = "google-firestore-v1beta1"))]
+pub mod v1beta1;
+#[cfg(feature = "googapis")]
+pub mod validate;
+pub mod value_model;
+pub mod value_path;
+#[cfg(feature = "googapis")]
+mod wire;
 
 pub const TYPE: &str = "type";
 pub const VALUE: &str = "value";
 pub const VALUES: &str = "values";
 pub const DATE_MAGIC: &str = "$TimestampValue";
+pub const GEOPOINT_MAGIC: &str = "$GeoPointValue";
+pub const REFERENCE_MAGIC: &str = "$ReferenceValue";
+pub const METADATA_MAGIC: &str = "$DocumentMetadata";
 
-#[cfg(all(feature = "google-firestore-v1", feature = "google-firestore-v1beta1"))]
-compile_error!("If you enable the google-firestore-v1beta1 crate feature, you must disable the default feature google-firestore-v1 to avoid a conflict.");
-
+#[cfg(feature = "googapis")]
 pub fn to_grpc_value<T>(value: &T) -> crate::serialize::Result<Value>
 where
     T: Serialize,
 {
-    let result = value.serialize(ValueSerializer)?;
+    to_grpc_value_with(value, SerializerConfig::default())
+}
+
+#[cfg(feature = "googapis")]
+pub fn to_grpc_value_with<T>(value: &T, config: SerializerConfig) -> crate::serialize::Result<Value>
+where
+    T: Serialize,
+{
+    let result = value.serialize(ValueSerializer::with_config(config))?;
     Ok(result)
 }
 
+/// Deserializes a `Value` into `T`.
+///
+/// Targets which deserialize whatever they are given (such as untagged
+/// enums, flattened fields and `serde_json::Value`) receive timestamps and
+/// geopoints as the bytes of their protobuf encoding, a
+/// `google.protobuf.Timestamp` or `google.type.LatLng`, rather than as a
+/// structured value. The `firestore_serde_timestamp::timestamp` and
+/// `geopoint` helpers read these bytes back, but a `Vec<u8>` field gets the
+/// encoding itself, and `serde_json::Value`, which has no bytes, fails. Use
+/// the helpers (or `GeoPoint`) for such fields, or the `json` module.
+#[cfg(feature = "googapis")]
 pub fn from_grpc_value<T>(value: &Value) -> crate::deserialize::Result<T>
 where
     T: DeserializeOwned,
 {
-    T::deserialize(&mut ValueDeserializer(value))
+    from_grpc_value_with(value, DeserializerConfig::default())
 }
 
+#[cfg(feature = "googapis")]
+pub fn from_grpc_value_with<T>(
+    value: &Value,
+    config: DeserializerConfig,
+) -> crate::deserialize::Result<T>
+where
+    T: DeserializeOwned,
+{
+    T::deserialize(&mut ValueDeserializer::with_config(value, config))
+}
+
+/// Like `from_grpc_value`, but allows `&str` and `&[u8]` fields of the result
+/// to borrow directly from `value`.
+#[cfg(feature = "googapis")]
+pub fn from_grpc_value_ref<'de, T>(value: &'de Value) -> crate::deserialize::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    from_grpc_value_ref_with(value, DeserializerConfig::default())
+}
+
+#[cfg(feature = "googapis")]
+pub fn from_grpc_value_ref_with<'de, T>(
+    value: &'de Value,
+    config: DeserializerConfig,
+) -> crate::deserialize::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    T::deserialize(&mut ValueDeserializer::with_config(value, config))
+}
+
+#[cfg(feature = "googapis")]
 pub fn to_document<T>(value: &T) -> crate::serialize::Result<Document>
 where
     T: Serialize,
 {
-    if let Value {
-        value_type: Some(ValueType::MapValue(MapValue { fields })),
-    } = to_grpc_value(value)?
-    {
-        Ok(Document {
-            fields,
-            ..Document::default()
-        })
+    to_document_with(value, SerializerConfig::default())
+}
+
+#[cfg(feature = "googapis")]
+pub fn to_document_with<T>(
+    value: &T,
+    config: SerializerConfig,
+) -> crate::serialize::Result<Document>
+where
+    T: Serialize,
+{
+    let document = value.serialize(DocumentSerializer::with_config(config))?;
+
+    if config.validate {
+        check_document(&document)?;
+    }
+
+    Ok(document)
+}
+
+#[cfg(feature = "googapis")]
+fn check_document(document: &Document) -> crate::serialize::Result<()> {
+    let violations = crate::validate::validate_document(document);
+
+    if violations.is_empty() {
+        Ok(())
     } else {
-        Err(SerializationError::NotAMap)
+        Err(SerializationError::InvalidDocument(violations))
+    }
+}
+
+/// Like `to_document_with`, but also accepts field transform markers (such as
+/// `ServerTimestamp` and `Increment`) anywhere outside of an array. The
+/// markers' fields (and maps which only contained markers) are left out of
+/// the returned `Document`, and the markers are returned instead as
+/// `FieldTransform`s to be sent alongside it.
+#[cfg(feature = "googapis")]
+pub fn to_document_with_transforms<T>(
+    value: &T,
+    config: SerializerConfig,
+) -> crate::serialize::Result<(Document, Vec<FieldTransform>)>
+where
+    T: Serialize,
+{
+    let transforms = Transforms::collecting();
+    let serializer = DocumentSerializer::with_config(config).with_transforms(transforms.clone());
+    let document = value.serialize(serializer)?;
+
+    if config.validate {
+        check_document(&document)?;
     }
+
+    Ok((document, transforms.take()))
+}
+
+/// Serializes `value` as a document and returns its `DocumentMask`. See
+/// `document_mask` for the meaning of `expand_maps`.
+#[cfg(feature = "googapis")]
+pub fn to_document_mask<T>(value: &T, expand_maps: bool) -> crate::serialize::Result<DocumentMask>
+where
+    T: Serialize,
+{
+    to_document_mask_with(value, SerializerConfig::default(), expand_maps)
+}
+
+#[cfg(feature = "googapis")]
+pub fn to_document_mask_with<T>(
+    value: &T,
+    config: SerializerConfig,
+    expand_maps: bool,
+) -> crate::serialize::Result<DocumentMask>
+where
+    T: Serialize,
+{
+    Ok(document_mask(
+        &to_document_with(value, config)?,
+        expand_maps,
+    ))
 }
 
+#[cfg(feature = "googapis")]
 pub fn from_document<T>(document: Document) -> crate::deserialize::Result<T>
 where
     T: DeserializeOwned,
 {
-    let fields = document.fields;
-    let value = Value {
-        value_type: Some(ValueType::MapValue(MapValue { fields })),
-    };
+    from_document_with(document, DeserializerConfig::default())
+}
 
-    from_grpc_value(&value)
+/// Deserializes a document, moving its strings and bytes into the result
+/// instead of cloning them.
+#[cfg(feature = "googapis")]
+pub fn from_document_with<T>(
+    document: Document,
+    config: DeserializerConfig,
+) -> crate::deserialize::Result<T>
+where
+    T: DeserializeOwned,
+{
+    T::deserialize(DocumentDeserializer::owned(document, config))
 }
 
-#[cfg(test)]
+/// Like `from_document`, but borrows the document, so that `&str` and `&[u8]`
+/// fields of the result can borrow directly from it.
+#[cfg(feature = "googapis")]
+pub fn from_document_ref<'de, T>(document: &'de Document) -> crate::deserialize::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    from_document_ref_with(document, DeserializerConfig::default())
+}
+
+#[cfg(feature = "googapis")]
+pub fn from_document_ref_with<'de, T>(
+    document: &'de Document,
+    config: DeserializerConfig,
+) -> crate::deserialize::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    T::deserialize(DocumentDeserializer::borrowed(document, config))
+}
+
+/// Like `to_grpc_value`, but appends the protobuf encoding of the `Value` to
+/// `buf` without building the `Value` itself. On error, `buf` is left as it
+/// was.
+#[cfg(feature = "googapis")]
+pub fn write_value<T>(value: &T, buf: &mut BytesMut) -> crate::serialize::Result<()>
+where
+    T: Serialize,
+{
+    write_value_with(value, SerializerConfig::default(), buf)
+}
+
+#[cfg(feature = "googapis")]
+pub fn write_value_with<T>(
+    value: &T,
+    config: SerializerConfig,
+    buf: &mut BytesMut,
+) -> crate::serialize::Result<()>
+where
+    T: Serialize,
+{
+    WireSerializer::write(value, ValueSerializer::with_config(config), false, buf)
+}
+
+/// Like `to_document`, but appends the protobuf encoding of the `Document`
+/// to `buf` without building the `Document` itself. On error, `buf` is left
+/// as it was.
+#[cfg(feature = "googapis")]
+pub fn write_document<T>(value: &T, buf: &mut BytesMut) -> crate::serialize::Result<()>
+where
+    T: Serialize,
+{
+    write_document_with(value, SerializerConfig::default(), buf)
+}
+
+/// Like `to_document_with`. Validation, if enabled, decodes the written
+/// document to check it.
+#[cfg(feature = "googapis")]
+pub fn write_document_with<T>(
+    value: &T,
+    config: SerializerConfig,
+    buf: &mut BytesMut,
+) -> crate::serialize::Result<()>
+where
+    T: Serialize,
+{
+    let start = buf.len();
+    WireSerializer::write(value, ValueSerializer::with_config(config), true, buf)?;
+
+    if config.validate {
+        let document = Document::decode(&buf[start..])
+            .expect("Should always be able to decode a document we just encoded.");
+
+        if let Err(e) = check_document(&document) {
+            buf.truncate(start);
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Like `from_grpc_value_ref`, but reads the protobuf encoding of a `Value`
+/// without decoding it into a `Value` first.
+#[cfg(feature = "googapis")]
+pub fn read_value<'de, T>(bytes: &'de [u8]) -> crate::deserialize::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    read_value_with(bytes, DeserializerConfig::default())
+}
+
+#[cfg(feature = "googapis")]
+pub fn read_value_with<'de, T>(
+    bytes: &'de [u8],
+    config: DeserializerConfig,
+) -> crate::deserialize::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    T::deserialize(WireDeserializer::new(bytes, config))
+}
+
+/// Like `from_document_ref`, but reads the protobuf encoding of a `Document`
+/// without decoding it into a `Document` first.
+#[cfg(feature = "googapis")]
+pub fn read_document<'de, T>(bytes: &'de [u8]) -> crate::deserialize::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    read_document_with(bytes, DeserializerConfig::default())
+}
+
+#[cfg(feature = "googapis")]
+pub fn read_document_with<'de, T>(
+    bytes: &'de [u8],
+    config: DeserializerConfig,
+) -> crate::deserialize::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    T::deserialize(WireDocumentDeserializer::new(bytes, config))
+}
+
+#[cfg(all(test, feature = "googapis"))]
 mod test {
     use super::*;
-    use crate::firestore::ArrayValue;
+    use crate::deserialize::DeserializationError;
+    use crate::firestore::{value::ValueType, ArrayValue, MapValue};
     use crate::serialize::SerializationError;
     use serde::Deserialize;
     use serde_bytes::{ByteBuf, Bytes};
@@ -212,7 +525,7 @@ mod test {
             result
         );
 
-        assert_eq!(99.9f32, from_grpc_value(&result).unwrap());
+        assert_eq!(99.9f32, from_grpc_value::<f32>(&result).unwrap());
 
         let result = to_grpc_value(&99.9f64).unwrap();
 
@@ -223,7 +536,7 @@ mod test {
             result
         );
 
-        assert_eq!(99.9f64, from_grpc_value(&result).unwrap());
+        assert_eq!(99.9f64, from_grpc_value::<f64>(&result).unwrap());
     }
 
     #[test]
@@ -237,7 +550,7 @@ mod test {
             result_true
         );
 
-        assert_eq!(true, from_grpc_value(&result_true).unwrap());
+        assert_eq!(true, from_grpc_value::<bool>(&result_true).unwrap());
 
         let result_false = to_grpc_value(&false).unwrap();
 
@@ -248,7 +561,7 @@ mod test {
             result_false
         );
 
-        assert_eq!(false, from_grpc_value(&result_false).unwrap());
+        assert_eq!(false, from_grpc_value::<bool>(&result_false).unwrap());
     }
 
     #[derive(Serialize)]
@@ -648,4 +961,789 @@ mod test {
 
         assert_eq!(record, from_grpc_value(&result).unwrap());
     }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    #[serde(untagged)]
+    enum AnUntaggedEnum {
+        Number(i64),
+        Text(String),
+        Record {
+            an_int_field: u32,
+            a_bool_field: bool,
+        },
+        List(Vec<Option<f64>>),
+    }
+
+    #[test]
+    fn deserialize_untagged_enum() {
+        for v in [
+            AnUntaggedEnum::Number(-4),
+            AnUntaggedEnum::Text("blah".to_string()),
+            AnUntaggedEnum::Record {
+                an_int_field: 8,
+                a_bool_field: true,
+            },
+            AnUntaggedEnum::List(vec![Some(1.5), None]),
+        ] {
+            let result = to_grpc_value(&v).unwrap();
+
+            assert_eq!(v, from_grpc_value(&result).unwrap());
+        }
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    #[serde(untagged)]
+    enum AnyBytes {
+        Bytes(ByteBuf),
+    }
+
+    #[test]
+    fn deserialize_any_timestamp_and_geopoint() {
+        let timestamp = prost_types::Timestamp {
+            seconds: 1_600_000_000,
+            nanos: 5,
+        };
+        let point = GeoPoint::new(1.5, -2.5);
+
+        // Targets which accept any type get the protobuf encoding of
+        // timestamps and geopoints as bytes.
+        for (value, encoded) in [
+            (
+                ValueType::TimestampValue(timestamp.clone()),
+                timestamp.encode_to_vec(),
+            ),
+            (
+                ValueType::GeoPointValue(point.into()),
+                crate::geopoint::LatLng::from(point).encode_to_vec(),
+            ),
+        ] {
+            let value = Value {
+                value_type: Some(value),
+            };
+
+            assert_eq!(
+                Err(DeserializationError::Message(
+                    "invalid type: byte array, expected any valid JSON value".to_string()
+                )),
+                from_grpc_value::<serde_json::Value>(&value)
+            );
+            assert_eq!(
+                AnyBytes::Bytes(ByteBuf::from(encoded.clone())),
+                from_grpc_value(&value).unwrap()
+            );
+            assert_eq!(
+                AnyBytes::Bytes(ByteBuf::from(encoded)),
+                read_value(&value.encode_to_vec()).unwrap()
+            );
+        }
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct AFlattenedStruct {
+        a_string_field: String,
+        #[serde(flatten)]
+        inner: AnInnerStruct,
+        #[serde(flatten)]
+        rest: HashMap<String, Vec<u8>>,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct AnInnerStruct {
+        an_int_field: u32,
+        a_vec_field: Vec<bool>,
+    }
+
+    #[test]
+    fn deserialize_flattened_struct() {
+        let v = AFlattenedStruct {
+            a_string_field: "blah".to_string(),
+            inner: AnInnerStruct {
+                an_int_field: 8,
+                a_vec_field: vec![true],
+            },
+            rest: vec![("extra".to_string(), vec![1, 2, 3])]
+                .into_iter()
+                .collect(),
+        };
+
+        let result = to_grpc_value(&v).unwrap();
+
+        if let Value {
+            value_type: Some(ValueType::MapValue(MapValue { fields })),
+        } = &result
+        {
+            assert_eq!(4, fields.len());
+        } else {
+            panic!("Expected a map, got {:?}", result);
+        }
+
+        assert_eq!(v, from_grpc_value(&result).unwrap());
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    #[serde(tag = "kind")]
+    enum AnInternallyTaggedEnum {
+        Circle { radius: f64 },
+        Rectangle { width: f64, height: f64 },
+    }
+
+    #[test]
+    fn deserialize_internally_tagged_enum() {
+        let v = AnInternallyTaggedEnum::Rectangle {
+            width: 2.0,
+            height: 3.0,
+        };
+        let result = to_grpc_value(&v).unwrap();
+
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::MapValue(MapValue {
+                    fields: vec![
+                        (
+                            "kind".to_string(),
+                            Value {
+                                value_type: Some(ValueType::StringValue("Rectangle".to_string()))
+                            }
+                        ),
+                        (
+                            "width".to_string(),
+                            Value {
+                                value_type: Some(ValueType::DoubleValue(2.0))
+                            }
+                        ),
+                        (
+                            "height".to_string(),
+                            Value {
+                                value_type: Some(ValueType::DoubleValue(3.0))
+                            }
+                        ),
+                    ]
+                    .into_iter()
+                    .collect()
+                }))
+            },
+            result
+        );
+
+        assert_eq!(v, from_grpc_value(&result).unwrap());
+    }
+
+    fn roundtrip_with_enum_representation<T>(
+        v: &T,
+        enum_representation: EnumRepresentation,
+    ) -> Value
+    where
+        T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        let result = to_grpc_value_with(
+            v,
+            SerializerConfig::new().with_enum_representation(enum_representation),
+        )
+        .unwrap();
+
+        assert_eq!(
+            *v,
+            from_grpc_value_with(
+                &result,
+                DeserializerConfig::new().with_enum_representation(enum_representation)
+            )
+            .unwrap()
+        );
+
+        // The owned path takes the variant data out of the map, rather than
+        // borrowing it, so check it separately.
+        assert_eq!(
+            *v,
+            T::deserialize(&mut ValueDeserializer::owned(
+                result.clone(),
+                DeserializerConfig::new().with_enum_representation(enum_representation)
+            ))
+            .unwrap()
+        );
+
+        result
+    }
+
+    #[test]
+    fn test_enum_externally_tagged() {
+        let v = vec![
+            JustAnEnum::TagUnitVariant,
+            JustAnEnum::ANewtypeVariant(55),
+            JustAnEnum::ATupleVariant(55, false),
+            JustAnEnum::ARecordVariant {
+                an_int: 4,
+                a_bool: false,
+            },
+        ];
+        let result = roundtrip_with_enum_representation(&v, EnumRepresentation::ExternallyTagged);
+
+        let values = if let Value {
+            value_type: Some(ValueType::ArrayValue(ArrayValue { values })),
+        } = result
+        {
+            values
+        } else {
+            panic!("Expected an array, got {:?}", result);
+        };
+
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::StringValue("TagUnitVariant".to_string()))
+            },
+            values[0]
+        );
+
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::MapValue(MapValue {
+                    fields: vec![(
+                        "ANewtypeVariant".to_string(),
+                        Value {
+                            value_type: Some(ValueType::IntegerValue(55))
+                        }
+                    )]
+                    .into_iter()
+                    .collect()
+                }))
+            },
+            values[1]
+        );
+    }
+
+    #[test]
+    fn test_enum_custom_type_value_keys() {
+        let enum_representation = EnumRepresentation::TypeValue {
+            type_key: "kind",
+            value_key: "data",
+            values_key: "fields",
+        };
+
+        let result = roundtrip_with_enum_representation(
+            &JustAnEnum::ANewtypeVariant(55),
+            enum_representation,
+        );
+
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::MapValue(MapValue {
+                    fields: vec![
+                        (
+                            "kind".to_string(),
+                            Value {
+                                value_type: Some(ValueType::StringValue(
+                                    "ANewtypeVariant".to_string()
+                                ))
+                            }
+                        ),
+                        (
+                            "data".to_string(),
+                            Value {
+                                value_type: Some(ValueType::IntegerValue(55))
+                            }
+                        ),
+                    ]
+                    .into_iter()
+                    .collect()
+                }))
+            },
+            result
+        );
+
+        roundtrip_with_enum_representation(
+            &JustAnEnum::ATupleVariant(55, false),
+            enum_representation,
+        );
+        roundtrip_with_enum_representation(
+            &JustAnEnum::ARecordVariant {
+                an_int: 4,
+                a_bool: false,
+            },
+            enum_representation,
+        );
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    enum AnInternallyTaggableEnum {
+        Empty,
+        Wrapped(ARecordStruct),
+        Record { an_int: u32, a_bool: bool },
+    }
+
+    #[test]
+    fn test_enum_internally_tagged() {
+        let enum_representation = EnumRepresentation::InternallyTagged { tag_key: "kind" };
+
+        let result = roundtrip_with_enum_representation(
+            &AnInternallyTaggableEnum::Record {
+                an_int: 4,
+                a_bool: false,
+            },
+            enum_representation,
+        );
+
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::MapValue(MapValue {
+                    fields: vec![
+                        (
+                            "kind".to_string(),
+                            Value {
+                                value_type: Some(ValueType::StringValue("Record".to_string()))
+                            }
+                        ),
+                        (
+                            "an_int".to_string(),
+                            Value {
+                                value_type: Some(ValueType::IntegerValue(4))
+                            }
+                        ),
+                        (
+                            "a_bool".to_string(),
+                            Value {
+                                value_type: Some(ValueType::BooleanValue(false))
+                            }
+                        ),
+                    ]
+                    .into_iter()
+                    .collect()
+                }))
+            },
+            result
+        );
+
+        roundtrip_with_enum_representation(&AnInternallyTaggableEnum::Empty, enum_representation);
+        roundtrip_with_enum_representation(
+            &AnInternallyTaggableEnum::Wrapped(ARecordStruct {
+                an_int_field: 8,
+                a_string_field: "blah".to_string(),
+                a_vec_field: vec![true],
+            }),
+            enum_representation,
+        );
+
+        assert_eq!(
+            SerializationError::Unrepresentable(
+                "non-map variant of internally tagged enum".to_string()
+            ),
+            to_grpc_value_with(
+                &JustAnEnum::ATupleVariant(55, false),
+                SerializerConfig::new().with_enum_representation(enum_representation)
+            )
+            .unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_unit_as_null() {
+        let serializer_config = SerializerConfig::new().with_unit_as_null(true);
+        let deserializer_config = DeserializerConfig::new().with_unit_as_null(true);
+
+        let result = to_grpc_value_with(&(), serializer_config).unwrap();
+
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::NullValue(0))
+            },
+            result
+        );
+
+        from_grpc_value_with::<()>(&result, deserializer_config).unwrap();
+
+        let result = to_grpc_value_with(&JustAUnitStruct, serializer_config).unwrap();
+
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::NullValue(0))
+            },
+            result
+        );
+
+        assert_eq!(
+            DeserializationError::Unrepresentable("unit"),
+            from_grpc_value::<()>(&result).unwrap_err()
+        );
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct AStructWithNestedEnums {
+        by_name: HashMap<String, Vec<JustAnEnum>>,
+        pair: (JustAnEnum, Option<JustAnEnum>),
+    }
+
+    #[test]
+    fn test_config_propagates_to_nested_values() {
+        let v = AStructWithNestedEnums {
+            by_name: vec![("foo".to_string(), vec![JustAnEnum::ANewtypeVariant(55)])]
+                .into_iter()
+                .collect(),
+            pair: (
+                JustAnEnum::ATupleVariant(3, true),
+                Some(JustAnEnum::ARecordVariant {
+                    an_int: 4,
+                    a_bool: false,
+                }),
+            ),
+        };
+
+        let serializer_config =
+            SerializerConfig::new().with_enum_representation(EnumRepresentation::ExternallyTagged);
+        let deserializer_config = DeserializerConfig::new()
+            .with_enum_representation(EnumRepresentation::ExternallyTagged);
+
+        let document = to_document_with(&v, serializer_config).unwrap();
+
+        let newtype = &document.fields["by_name"];
+        let expected_newtype = Value {
+            value_type: Some(ValueType::MapValue(MapValue {
+                fields: vec![(
+                    "foo".to_string(),
+                    Value {
+                        value_type: Some(ValueType::ArrayValue(ArrayValue {
+                            values: vec![to_grpc_value_with(
+                                &JustAnEnum::ANewtypeVariant(55),
+                                serializer_config,
+                            )
+                            .unwrap()],
+                        })),
+                    },
+                )]
+                .into_iter()
+                .collect(),
+            })),
+        };
+        assert_eq!(&expected_newtype, newtype);
+
+        assert_eq!(
+            v,
+            from_document_with(document.clone(), deserializer_config).unwrap()
+        );
+        assert!(from_document::<AStructWithNestedEnums>(document).is_err());
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct AStructWithOptions {
+        present: Option<u32>,
+        absent: Option<u32>,
+        list: Vec<Option<u32>>,
+        by_name: HashMap<String, Option<u32>>,
+    }
+
+    #[test]
+    fn test_skip_none() {
+        let v = AStructWithOptions {
+            present: Some(4),
+            absent: None,
+            list: vec![None, Some(5)],
+            by_name: vec![("foo".to_string(), None), ("bar".to_string(), Some(6))]
+                .into_iter()
+                .collect(),
+        };
+
+        let document = to_document_with(&v, SerializerConfig::new().with_skip_none(true)).unwrap();
+
+        assert_eq!(vec!["by_name", "list", "present"], {
+            let mut keys: Vec<&str> = document.fields.keys().map(String::as_str).collect();
+            keys.sort_unstable();
+            keys
+        });
+
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::ArrayValue(ArrayValue {
+                    values: vec![
+                        Value {
+                            value_type: Some(ValueType::NullValue(0))
+                        },
+                        Value {
+                            value_type: Some(ValueType::IntegerValue(5))
+                        },
+                    ]
+                }))
+            },
+            document.fields["list"]
+        );
+
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::MapValue(MapValue {
+                    fields: vec![(
+                        "bar".to_string(),
+                        Value {
+                            value_type: Some(ValueType::IntegerValue(6))
+                        }
+                    )]
+                    .into_iter()
+                    .collect()
+                }))
+            },
+            document.fields["by_name"]
+        );
+
+        let mut expected = v;
+        expected.by_name.remove("foo");
+
+        assert_eq!(expected, from_document(document).unwrap());
+
+        // A top-level None is not a field, so it is still stored as null.
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::NullValue(0))
+            },
+            to_grpc_value_with(&None::<u32>, SerializerConfig::new().with_skip_none(true)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_document_serializer() {
+        #[derive(Serialize)]
+        struct User {
+            email: String,
+        }
+
+        #[derive(Serialize)]
+        struct Wrapper(User);
+
+        let user = User {
+            email: "a@example.com".to_string(),
+        };
+
+        let document = Wrapper(user)
+            .serialize(
+                DocumentSerializer::new()
+                    .with_name("projects/p/databases/(default)/documents/users", "alice"),
+            )
+            .unwrap();
+
+        assert_eq!(
+            "projects/p/databases/(default)/documents/users/alice",
+            document.name
+        );
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::StringValue("a@example.com".to_string()))
+            },
+            document.fields["email"]
+        );
+
+        assert_eq!(
+            Err(SerializationError::NotAMap),
+            to_document(&vec![HashMap::<String, u32>::new()])
+        );
+        assert_eq!(Err(SerializationError::NotAMap), to_document(&4));
+        assert_eq!(Err(SerializationError::NotAMap), to_document(&None::<User>));
+    }
+
+    #[test]
+    fn test_validation() {
+        #[derive(Serialize)]
+        struct Grid {
+            rows: Vec<Vec<u8>>,
+        }
+
+        let v = Grid {
+            rows: vec![vec![1]],
+        };
+
+        assert!(to_document(&v).is_ok());
+
+        let result = to_document_with(&v, SerializerConfig::new().with_validation(true));
+        assert_eq!(
+            Some("rows.0: arrays cannot directly contain arrays.".to_string()),
+            match result {
+                Err(SerializationError::InvalidDocument(violations)) => {
+                    violations.first().map(ToString::to_string)
+                }
+                _ => None,
+            }
+        );
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct ABorrowingStruct<'a> {
+        name: &'a str,
+        #[serde(with = "serde_bytes")]
+        data: &'a [u8],
+        tags: Vec<&'a str>,
+    }
+
+    #[test]
+    fn test_from_document_ref_borrows() {
+        let document = Document {
+            fields: vec![
+                (
+                    "name".to_string(),
+                    Value {
+                        value_type: Some(ValueType::StringValue("alice".to_string())),
+                    },
+                ),
+                (
+                    "data".to_string(),
+                    Value {
+                        value_type: Some(ValueType::BytesValue(vec![1, 2, 3])),
+                    },
+                ),
+                (
+                    "tags".to_string(),
+                    Value {
+                        value_type: Some(ValueType::ArrayValue(ArrayValue {
+                            values: vec![Value {
+                                value_type: Some(ValueType::StringValue("admin".to_string())),
+                            }],
+                        })),
+                    },
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            ..Document::default()
+        };
+
+        let result: ABorrowingStruct = from_document_ref(&document).unwrap();
+
+        assert_eq!(
+            ABorrowingStruct {
+                name: "alice",
+                data: &[1, 2, 3],
+                tags: vec!["admin"],
+            },
+            result
+        );
+
+        if let Some(ValueType::StringValue(name)) = &document.fields["name"].value_type {
+            assert_eq!(name.as_ptr(), result.name.as_ptr());
+        } else {
+            panic!("Expected a string.");
+        }
+    }
+
+    #[test]
+    fn test_from_grpc_value_ref_borrows() {
+        let value = Value {
+            value_type: Some(ValueType::StringValue("alice".to_string())),
+        };
+
+        let result: &str = from_grpc_value_ref(&value).unwrap();
+
+        assert_eq!("alice", result);
+    }
+
+    #[test]
+    fn test_from_document_ref_enum() {
+        let v = AnInternallyTaggableEnum::Record {
+            an_int: 3,
+            a_bool: true,
+        };
+        let document = to_document(&v).unwrap();
+
+        assert_eq!(v, from_document_ref(&document).unwrap());
+        assert_eq!(v, from_document(document).unwrap());
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum ABorrowingEnum<'a> {
+        Named { name: &'a str },
+    }
+
+    #[test]
+    fn test_from_document_ref_enum_borrows() {
+        let v = ABorrowingEnum::Named { name: "alice" };
+
+        for representation in [
+            EnumRepresentation::default(),
+            EnumRepresentation::ExternallyTagged,
+            EnumRepresentation::InternallyTagged { tag_key: "type" },
+        ] {
+            let document = to_document_with(
+                &v,
+                SerializerConfig::new().with_enum_representation(representation),
+            )
+            .unwrap();
+
+            let result: ABorrowingEnum = from_document_ref_with(
+                &document,
+                DeserializerConfig::new().with_enum_representation(representation),
+            )
+            .unwrap();
+            assert_eq!(v, result);
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct AnAddress<Zip> {
+        zip: Zip,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct AUser<Zip> {
+        address: AnAddress<Zip>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct SomeUsers<Zip> {
+        users: Vec<AUser<Zip>>,
+    }
+
+    #[test]
+    fn test_deserialization_error_path() {
+        let users = SomeUsers {
+            users: (0..4)
+                .map(|zip| AUser {
+                    address: AnAddress { zip },
+                })
+                .collect(),
+        };
+        let mut document = to_document(&users).unwrap();
+        assert_eq!(users, from_document(document.clone()).unwrap());
+
+        if let Some(Value {
+            value_type: Some(ValueType::ArrayValue(ArrayValue { values })),
+        }) = document.fields.get_mut("users")
+        {
+            values[3] = to_grpc_value(&AUser {
+                address: AnAddress { zip: "12345" },
+            })
+            .unwrap();
+        }
+
+        let error = from_document::<SomeUsers<i32>>(document).unwrap_err();
+
+        assert_eq!("users.3.address.zip", error.path().unwrap().to_string());
+        assert_eq!(
+            "users.3.address.zip: expected i32, got string\n",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_deserialization_error_path_in_enum() {
+        let mut value = to_grpc_value(&JustAnEnum::ATupleVariant(55, false)).unwrap();
+        if let Some(ValueType::MapValue(map)) = &mut value.value_type {
+            FieldPath::new(vec!["values"]).set(map, to_grpc_value(&(55, 1)).unwrap());
+        }
+
+        let error = from_grpc_value::<JustAnEnum>(&value).unwrap_err();
+
+        assert_eq!("values.1", error.path().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_serialization_error_path() {
+        let mut users: HashMap<&str, Vec<HashMap<&str, GeoPoint>>> = HashMap::new();
+        users.insert(
+            "users",
+            vec![
+                HashMap::new(),
+                vec![("home", GeoPoint::new(91., 0.))].into_iter().collect(),
+            ],
+        );
+
+        let error = to_document(&users).unwrap_err();
+
+        assert_eq!("users.1.home", error.path().unwrap().to_string());
+        assert!(matches!(
+            error,
+            SerializationError::AtPath(_, inner)
+                if *inner == SerializationError::LatitudeOutOfRange(91.)
+        ));
+    }
 }
@@ -0,0 +1,114 @@
+use crate::field_path::format_segments;
+use crate::firestore::{value::ValueType, Document, DocumentMask, MapValue, Value};
+use std::collections::HashMap;
+
+/// Returns a `DocumentMask` listing the fields of `document`, for use in a
+/// partial update which should only touch those fields.
+///
+/// If `expand_maps` is true, the mask lists the path of every leaf value, so
+/// that fields of nested maps which are absent from `document` are left
+/// untouched by the update. Empty maps are treated as leaves. If
+/// `expand_maps` is false, only the top-level fields are listed, so nested
+/// maps are replaced wholesale.
+///
+/// Paths are escaped with backticks where needed, and returned in sorted
+/// order.
+pub fn document_mask(document: &Document, expand_maps: bool) -> DocumentMask {
+    let mut field_paths = Vec::new();
+
+    if expand_maps {
+        collect_leaf_paths(&document.fields, &mut Vec::new(), &mut field_paths);
+    } else {
+        field_paths.extend(document.fields.keys().map(|key| format_segments(&[key])));
+    }
+
+    field_paths.sort();
+
+    DocumentMask { field_paths }
+}
+
+fn collect_leaf_paths<'a>(
+    fields: &'a HashMap<String, Value>,
+    path: &mut Vec<&'a str>,
+    field_paths: &mut Vec<String>,
+) {
+    for (key, value) in fields {
+        path.push(key);
+
+        match value {
+            Value {
+                value_type: Some(ValueType::MapValue(MapValue { fields })),
+            } if !fields.is_empty() => collect_leaf_paths(fields, path, field_paths),
+            _ => field_paths.push(format_segments(path)),
+        }
+
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::to_document;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct Address {
+        city: String,
+        #[serde(rename = "zip code")]
+        zip_code: String,
+    }
+
+    #[derive(Serialize)]
+    struct Profile {
+        name: String,
+        address: Address,
+        tags: Vec<String>,
+        extra: HashMap<String, i64>,
+    }
+
+    fn profile_document() -> Document {
+        to_document(&Profile {
+            name: "Alice".to_string(),
+            address: Address {
+                city: "Springfield".to_string(),
+                zip_code: "12345".to_string(),
+            },
+            tags: vec!["a".to_string()],
+            extra: HashMap::new(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_leaf_mask() {
+        assert_eq!(
+            vec![
+                "address.`zip code`",
+                "address.city",
+                "extra",
+                "name",
+                "tags"
+            ],
+            document_mask(&profile_document(), true).field_paths
+        );
+    }
+
+    #[test]
+    fn test_top_level_mask() {
+        assert_eq!(
+            vec!["address", "extra", "name", "tags"],
+            document_mask(&profile_document(), false).field_paths
+        );
+    }
+
+    #[test]
+    fn test_escaped_top_level_field() {
+        let value: HashMap<&str, i64> = vec![("a.b", 1), ("`c`", 2)].into_iter().collect();
+
+        assert_eq!(
+            vec!["`\\`c\\``", "`a.b`"],
+            document_mask(&to_document(&value).unwrap(), false).field_paths
+        );
+    }
+}
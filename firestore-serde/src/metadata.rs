@@ -0,0 +1,328 @@
+#[cfg(feature = "googapis")]
+use crate::firestore::{value::ValueType, Document, Value};
+use crate::METADATA_MAGIC;
+use chrono::{DateTime, TimeZone, Utc};
+use prost::Message;
+use prost_types::Timestamp;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_bytes::ByteBuf;
+#[cfg(feature = "googapis")]
+use std::borrow::Cow;
+use std::convert::TryFrom;
+
+/// Field name under which `from_document` provides the document's ID.
+pub const DOCUMENT_ID_FIELD: &str = "__id__";
+/// Field name under which `from_document` provides the document's full
+/// resource name.
+pub const DOCUMENT_NAME_FIELD: &str = "__name__";
+/// Field name under which `from_document` provides the document's creation
+/// time, if the `Document` has one.
+pub const CREATE_TIME_FIELD: &str = "__create_time__";
+/// Field name under which `from_document` provides the document's last
+/// update time, if the `Document` has one.
+pub const UPDATE_TIME_FIELD: &str = "__update_time__";
+
+/// The last segment of the document's resource name. Use as the type of a
+/// struct field renamed to `DOCUMENT_ID_FIELD`.
+///
+/// Metadata fields are filled in by `from_document`, and omitted by the
+/// serializer, since Firestore does not store them as fields.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DocumentId(pub String);
+
+/// The document's full resource name. Use as the type of a struct field
+/// renamed to `DOCUMENT_NAME_FIELD`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DocumentName(pub String);
+
+/// The time at which the document was created. Use as the type of a struct
+/// field renamed to `CREATE_TIME_FIELD`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CreateTime(pub DateTime<Utc>);
+
+/// The time at which the document was last changed. Use as the type of a
+/// struct field renamed to `UPDATE_TIME_FIELD`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UpdateTime(pub DateTime<Utc>);
+
+macro_rules! impl_metadata_serialize {
+    ($($marker:ty),*) => {
+        $(
+            impl Serialize for $marker {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: Serializer,
+                {
+                    serializer.serialize_newtype_struct(METADATA_MAGIC, &())
+                }
+            }
+        )*
+    };
+}
+
+impl_metadata_serialize!(DocumentId, DocumentName, CreateTime, UpdateTime);
+
+impl<'de> Deserialize<'de> for DocumentId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer).map(DocumentId)
+    }
+}
+
+impl<'de> Deserialize<'de> for DocumentName {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer).map(DocumentName)
+    }
+}
+
+fn deserialize_time<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let buf = ByteBuf::deserialize(deserializer)?;
+    let timestamp = Timestamp::decode(buf.as_slice()).map_err(serde::de::Error::custom)?;
+
+    u32::try_from(timestamp.nanos)
+        .ok()
+        .and_then(|nanos| Utc.timestamp_opt(timestamp.seconds, nanos).single())
+        .ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "timestamp {}s {}ns is out of range",
+                timestamp.seconds, timestamp.nanos
+            ))
+        })
+}
+
+impl<'de> Deserialize<'de> for CreateTime {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize_time(deserializer).map(CreateTime)
+    }
+}
+
+impl<'de> Deserialize<'de> for UpdateTime {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize_time(deserializer).map(UpdateTime)
+    }
+}
+
+/// Whether `field` is one of the metadata field names.
+#[cfg(feature = "googapis")]
+pub(crate) fn is_metadata_field(field: &str) -> bool {
+    matches!(
+        field,
+        DOCUMENT_ID_FIELD | DOCUMENT_NAME_FIELD | CREATE_TIME_FIELD | UPDATE_TIME_FIELD
+    )
+}
+
+/// The metadata of a `Document`, from which the deserializer supplies values
+/// under the reserved metadata field names.
+#[cfg(feature = "googapis")]
+pub(crate) struct DocumentMetadata<'a> {
+    /// The document's resource name, which is empty if it has none.
+    pub name: Cow<'a, str>,
+    pub create_time: Option<Timestamp>,
+    pub update_time: Option<Timestamp>,
+}
+
+#[cfg(feature = "googapis")]
+impl<'a> DocumentMetadata<'a> {
+    pub fn new(document: &'a Document) -> Self {
+        DocumentMetadata {
+            name: Cow::Borrowed(&document.name),
+            create_time: document.create_time.clone(),
+            update_time: document.update_time.clone(),
+        }
+    }
+
+    /// The values of the metadata fields whose names are among `requested`
+    /// and which the document has. Values are only built for those fields.
+    pub fn into_fields(self, requested: &[&str]) -> Vec<(&'static str, Value)> {
+        let string_value = |s: &str| Value {
+            value_type: Some(ValueType::StringValue(s.to_string())),
+        };
+        let timestamp_value = |t: &Timestamp| Value {
+            value_type: Some(ValueType::TimestampValue(t.clone())),
+        };
+        let name = Some(self.name.as_ref()).filter(|name| !name.is_empty());
+
+        requested
+            .iter()
+            .filter_map(|&field| match field {
+                DOCUMENT_ID_FIELD => name.map(|name| {
+                    let id = name.rsplit('/').next().unwrap_or_default();
+                    (DOCUMENT_ID_FIELD, string_value(id))
+                }),
+                DOCUMENT_NAME_FIELD => name.map(|name| (DOCUMENT_NAME_FIELD, string_value(name))),
+                CREATE_TIME_FIELD => self
+                    .create_time
+                    .as_ref()
+                    .map(|t| (CREATE_TIME_FIELD, timestamp_value(t))),
+                UPDATE_TIME_FIELD => self
+                    .update_time
+                    .as_ref()
+                    .map(|t| (UPDATE_TIME_FIELD, timestamp_value(t))),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "googapis"))]
+mod test {
+    use super::*;
+    use crate::deserialize::DeserializationError;
+    use crate::serialize::SerializationError;
+    use crate::{
+        from_document, from_document_ref, read_document, to_document, to_grpc_value, write_document,
+    };
+    use bytes::BytesMut;
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct User {
+        #[serde(rename = "__id__")]
+        id: DocumentId,
+        #[serde(rename = "__name__")]
+        name: DocumentName,
+        #[serde(rename = "__create_time__")]
+        created: CreateTime,
+        #[serde(
+            rename = "__update_time__",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        updated: Option<UpdateTime>,
+        email: String,
+    }
+
+    fn user_document() -> Document {
+        Document {
+            name: "projects/p/databases/(default)/documents/users/alice".to_string(),
+            fields: vec![(
+                "email".to_string(),
+                Value {
+                    value_type: Some(ValueType::StringValue("alice@example.com".to_string())),
+                },
+            )]
+            .into_iter()
+            .collect(),
+            create_time: Some(Timestamp {
+                seconds: 150,
+                nanos: 200,
+            }),
+            update_time: None,
+        }
+    }
+
+    #[test]
+    fn test_deserialize_metadata() {
+        let user: User = from_document(user_document()).unwrap();
+
+        assert_eq!(
+            User {
+                id: DocumentId("alice".to_string()),
+                name: DocumentName(
+                    "projects/p/databases/(default)/documents/users/alice".to_string()
+                ),
+                created: CreateTime(Utc.timestamp(150, 200)),
+                updated: None,
+                email: "alice@example.com".to_string(),
+            },
+            user
+        );
+    }
+
+    #[test]
+    fn test_deserialize_metadata_from_ref() {
+        let document = user_document();
+        let user: User = from_document_ref(&document).unwrap();
+
+        assert_eq!(from_document::<User>(user_document()).unwrap(), user);
+    }
+
+    #[test]
+    fn test_serialize_skips_metadata() {
+        let user: User = from_document(user_document()).unwrap();
+        let document = to_document(&user).unwrap();
+
+        assert_eq!(user_document().fields, document.fields);
+    }
+
+    #[test]
+    fn test_metadata_outside_of_fields() {
+        let error = || {
+            SerializationError::Unrepresentable(
+                "document metadata outside of a map field".to_string(),
+            )
+        };
+
+        assert_eq!(
+            Err(error()),
+            to_grpc_value(&DocumentId("alice".to_string()))
+        );
+
+        let ids: HashMap<&str, Vec<DocumentId>> =
+            vec![("ids", vec![DocumentId("alice".to_string())])]
+                .into_iter()
+                .collect();
+        let result = to_document(&ids).unwrap_err();
+
+        assert_eq!("ids.0", result.path().unwrap().to_string());
+        assert!(matches!(result, SerializationError::AtPath(_, inner) if *inner == error()));
+
+        let mut buf = BytesMut::new();
+        assert!(write_document(&ids, &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_metadata_not_added_to_maps() {
+        let fields: HashMap<String, String> = from_document(user_document()).unwrap();
+
+        assert_eq!(
+            vec![("email".to_string(), "alice@example.com".to_string())]
+                .into_iter()
+                .collect::<HashMap<_, _>>(),
+            fields
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_metadata() {
+        let mut document = user_document();
+        document.create_time = Some(Timestamp {
+            seconds: 0,
+            nanos: -1,
+        });
+
+        assert!(from_document::<User>(document).is_err());
+    }
+
+    #[test]
+    fn test_missing_metadata() {
+        let mut document = user_document();
+        document.create_time = None;
+
+        assert!(from_document::<User>(document).is_err());
+
+        let mut document = user_document();
+        document.name = String::new();
+        let error = || DeserializationError::Message("missing field `__id__`".to_string());
+
+        assert_eq!(Err(error()), from_document::<User>(document.clone()));
+        assert_eq!(
+            Err(error()),
+            read_document::<User>(&document.encode_to_vec())
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! Firestore's ordering of values, as used to sort query results and
+//! compare them against filters and cursors.
+//!
+//! Values of different types are ordered by type:
+//!
+//! null < booleans < numbers < timestamps < strings < bytes < references
+//! < geopoints < arrays < maps
+//!
+//! Integers and doubles are compared by their numeric value, so `1` and
+//! `1.0` are equal, and `NaN` sorts before every other number (and equal to
+//! itself). A value with no type set is ordered as null.
+
+use crate::firestore::{value::ValueType, ArrayValue, MapValue, Value};
+use googapis::google::r#type::LatLng;
+use prost_types::Timestamp;
+use std::cmp::Ordering;
+
+/// Compares two values in Firestore's total order.
+pub fn compare(a: &Value, b: &Value) -> Ordering {
+    let (a, b) = (&a.value_type, &b.value_type);
+    let by_type = type_order(a).cmp(&type_order(b));
+
+    if by_type != Ordering::Equal {
+        return by_type;
+    }
+
+    match (a, b) {
+        (Some(ValueType::BooleanValue(a)), Some(ValueType::BooleanValue(b))) => a.cmp(b),
+        (Some(ValueType::IntegerValue(a)), Some(ValueType::IntegerValue(b))) => a.cmp(b),
+        (Some(ValueType::DoubleValue(a)), Some(ValueType::DoubleValue(b))) => {
+            compare_doubles(*a, *b)
+        }
+        (Some(ValueType::IntegerValue(a)), Some(ValueType::DoubleValue(b))) => {
+            compare_integer_to_double(*a, *b)
+        }
+        (Some(ValueType::DoubleValue(a)), Some(ValueType::IntegerValue(b))) => {
+            compare_integer_to_double(*b, *a).reverse()
+        }
+        (Some(ValueType::TimestampValue(a)), Some(ValueType::TimestampValue(b))) => {
+            compare_timestamps(a, b)
+        }
+        (Some(ValueType::StringValue(a)), Some(ValueType::StringValue(b))) => a.cmp(b),
+        (Some(ValueType::BytesValue(a)), Some(ValueType::BytesValue(b))) => a.cmp(b),
+        (Some(ValueType::ReferenceValue(a)), Some(ValueType::ReferenceValue(b))) => {
+            a.split('/').cmp(b.split('/'))
+        }
+        (Some(ValueType::GeoPointValue(a)), Some(ValueType::GeoPointValue(b))) => {
+            compare_geopoints(a, b)
+        }
+        (
+            Some(ValueType::ArrayValue(ArrayValue { values: a })),
+            Some(ValueType::ArrayValue(ArrayValue { values: b })),
+        ) => compare_arrays(a, b),
+        (
+            Some(ValueType::MapValue(MapValue { fields: a })),
+            Some(ValueType::MapValue(MapValue { fields: b })),
+        ) => {
+            let mut a: Vec<_> = a.iter().collect();
+            let mut b: Vec<_> = b.iter().collect();
+            a.sort_by_key(|(k, _)| *k);
+            b.sort_by_key(|(k, _)| *k);
+
+            for ((ka, va), (kb, vb)) in a.iter().zip(b.iter()) {
+                let ordering = ka.cmp(kb).then_with(|| compare(va, vb));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+
+            a.len().cmp(&b.len())
+        }
+        // Nulls (and unset values), which are all equal.
+        _ => Ordering::Equal,
+    }
+}
+
+/// Whether two values are equal as far as a query's `==`, `in` or
+/// `array-contains` filter is concerned. This is `compare(a, b) == Equal`,
+/// except that `NaN` is not equal to anything (it is matched by `is-nan`
+/// filters instead).
+pub fn query_equals(a: &Value, b: &Value) -> bool {
+    !contains_nan(a) && !contains_nan(b) && compare(a, b) == Ordering::Equal
+}
+
+/// A wrapper which implements `Ord` (and `Eq`) according to `compare`, e.g.
+/// to sort values or use them as keys of a `BTreeMap`.
+#[derive(Clone, Copy, Debug)]
+pub struct OrderedValue<'a>(pub &'a Value);
+
+impl<'a> PartialEq for OrderedValue<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        compare(self.0, other.0) == Ordering::Equal
+    }
+}
+
+impl<'a> Eq for OrderedValue<'a> {}
+
+impl<'a> PartialOrd for OrderedValue<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Ord for OrderedValue<'a> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(self.0, other.0)
+    }
+}
+
+pub(crate) fn type_order(value_type: &Option<ValueType>) -> u8 {
+    match value_type {
+        None | Some(ValueType::NullValue(_)) => 0,
+        Some(ValueType::BooleanValue(_)) => 1,
+        Some(ValueType::IntegerValue(_)) | Some(ValueType::DoubleValue(_)) => 2,
+        Some(ValueType::TimestampValue(_)) => 3,
+        Some(ValueType::StringValue(_)) => 4,
+        Some(ValueType::BytesValue(_)) => 5,
+        Some(ValueType::ReferenceValue(_)) => 6,
+        Some(ValueType::GeoPointValue(_)) => 7,
+        Some(ValueType::ArrayValue(_)) => 8,
+        Some(ValueType::MapValue(_)) => 9,
+    }
+}
+
+fn contains_nan(value: &Value) -> bool {
+    match &value.value_type {
+        Some(ValueType::DoubleValue(v)) => v.is_nan(),
+        Some(ValueType::ArrayValue(ArrayValue { values })) => values.iter().any(contains_nan),
+        Some(ValueType::MapValue(MapValue { fields })) => fields.values().any(contains_nan),
+        _ => false,
+    }
+}
+
+/// Orders doubles with `NaN` first, and `-0.0` equal to `0.0`.
+fn compare_doubles(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.partial_cmp(&b).expect("Neither value is NaN."),
+    }
+}
+
+/// Compares an integer to a double exactly, without rounding the integer
+/// to the nearest double.
+fn compare_integer_to_double(a: i64, b: f64) -> Ordering {
+    // 2^63, which is exactly representable as a double.
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+
+    if b.is_nan() {
+        Ordering::Greater
+    } else if b >= LIMIT {
+        Ordering::Less
+    } else if b < -LIMIT {
+        Ordering::Greater
+    } else {
+        // `b` is now within the range of `i64`, so truncating it is exact.
+        #[allow(clippy::cast_possible_truncation)]
+        let whole = b.trunc() as i64;
+
+        a.cmp(&whole).then_with(|| compare_doubles(0.0, b - b.trunc()))
+    }
+}
+
+fn compare_timestamps(a: &Timestamp, b: &Timestamp) -> Ordering {
+    (a.seconds, a.nanos).cmp(&(b.seconds, b.nanos))
+}
+
+fn compare_geopoints(a: &LatLng, b: &LatLng) -> Ordering {
+    compare_doubles(a.latitude, b.latitude)
+        .then_with(|| compare_doubles(a.longitude, b.longitude))
+}
+
+fn compare_arrays(a: &[Value], b: &[Value]) -> Ordering {
+    for (va, vb) in a.iter().zip(b.iter()) {
+        let ordering = compare(va, vb);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    a.len().cmp(&b.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{to_grpc_value, DocumentReference, GeoPoint};
+    use std::collections::HashMap;
+
+    fn int_value(v: i64) -> Value {
+        Value {
+            value_type: Some(ValueType::IntegerValue(v)),
+        }
+    }
+
+    fn double_value(v: f64) -> Value {
+        Value {
+            value_type: Some(ValueType::DoubleValue(v)),
+        }
+    }
+
+    #[test]
+    fn test_type_order() {
+        let reference: DocumentReference = "projects/p/databases/(default)/documents/c/d"
+            .parse()
+            .unwrap();
+        let mut map = HashMap::new();
+        map.insert("a", 1);
+
+        // In ascending order, one value of each type.
+        let values = vec![
+            to_grpc_value(&None::<i32>).unwrap(),
+            to_grpc_value(&false).unwrap(),
+            to_grpc_value(&true).unwrap(),
+            double_value(f64::NAN),
+            double_value(f64::NEG_INFINITY),
+            int_value(-5),
+            double_value(0.5),
+            int_value(1),
+            double_value(f64::INFINITY),
+            Value {
+                value_type: Some(ValueType::TimestampValue(Timestamp {
+                    seconds: 0,
+                    nanos: 0,
+                })),
+            },
+            to_grpc_value(&"").unwrap(),
+            to_grpc_value(&"a").unwrap(),
+            to_grpc_value(&serde_bytes::Bytes::new(b"a")).unwrap(),
+            to_grpc_value(&reference).unwrap(),
+            to_grpc_value(&GeoPoint::new(0.0, 0.0)).unwrap(),
+            to_grpc_value(&Vec::<i32>::new()).unwrap(),
+            to_grpc_value(&vec![1]).unwrap(),
+            to_grpc_value(&map).unwrap(),
+        ];
+
+        for (i, a) in values.iter().enumerate() {
+            for (j, b) in values.iter().enumerate() {
+                assert_eq!(i.cmp(&j), compare(a, b), "{:?} vs {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(Ordering::Equal, compare(&int_value(1), &double_value(1.0)));
+        assert_eq!(Ordering::Equal, compare(&double_value(-0.0), &int_value(0)));
+        assert_eq!(Ordering::Less, compare(&int_value(1), &double_value(1.5)));
+        assert_eq!(Ordering::Greater, compare(&int_value(-1), &double_value(-1.5)));
+        assert_eq!(Ordering::Less, compare(&double_value(-0.5), &int_value(0)));
+
+        // Doubles can't represent i64::MAX exactly; it rounds up to 2^63.
+        assert_eq!(
+            Ordering::Less,
+            compare(&int_value(i64::MAX), &double_value(i64::MAX as f64))
+        );
+        assert_eq!(
+            Ordering::Equal,
+            compare(&int_value(i64::MIN), &double_value(i64::MIN as f64))
+        );
+
+        assert_eq!(
+            Ordering::Equal,
+            compare(&double_value(f64::NAN), &double_value(f64::NAN))
+        );
+        assert_eq!(
+            Ordering::Less,
+            compare(&double_value(f64::NAN), &int_value(i64::MIN))
+        );
+    }
+
+    #[test]
+    fn test_query_equals() {
+        assert!(query_equals(&int_value(1), &double_value(1.0)));
+        assert!(!query_equals(&int_value(1), &int_value(2)));
+        assert!(!query_equals(&double_value(f64::NAN), &double_value(f64::NAN)));
+        assert!(!query_equals(
+            &to_grpc_value(&vec![f64::NAN]).unwrap(),
+            &to_grpc_value(&vec![f64::NAN]).unwrap()
+        ));
+        assert!(query_equals(
+            &to_grpc_value(&vec![1.0]).unwrap(),
+            &to_grpc_value(&vec![1]).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_maps_and_arrays() {
+        let mut a = HashMap::new();
+        a.insert("a", 2);
+        let mut b = HashMap::new();
+        b.insert("a", 1);
+        b.insert("b", 1);
+
+        // Maps are compared key by key, in key order, before length.
+        assert_eq!(
+            Ordering::Greater,
+            compare(&to_grpc_value(&a).unwrap(), &to_grpc_value(&b).unwrap())
+        );
+
+        let mut c = HashMap::new();
+        c.insert("b", 0);
+        assert_eq!(
+            Ordering::Less,
+            compare(&to_grpc_value(&b).unwrap(), &to_grpc_value(&c).unwrap())
+        );
+
+        assert_eq!(
+            Ordering::Less,
+            compare(
+                &to_grpc_value(&vec![1, 2]).unwrap(),
+                &to_grpc_value(&vec![1, 2, 0]).unwrap()
+            )
+        );
+        assert_eq!(
+            Ordering::Greater,
+            compare(
+                &to_grpc_value(&vec![2]).unwrap(),
+                &to_grpc_value(&vec![1, 2, 0]).unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_references() {
+        let a = Value {
+            value_type: Some(ValueType::ReferenceValue(
+                "projects/p/databases/(default)/documents/a/b".to_string(),
+            )),
+        };
+        let b = Value {
+            value_type: Some(ValueType::ReferenceValue(
+                "projects/p/databases/(default)/documents/a-b/c".to_string(),
+            )),
+        };
+
+        // Compared by segment, so "a" sorts before "a-b" even though "/"
+        // sorts after "-".
+        assert_eq!(Ordering::Less, compare(&a, &b));
+    }
+
+    #[test]
+    fn test_sort() {
+        let values = [int_value(3), double_value(1.5), int_value(2)];
+        let mut sorted: Vec<OrderedValue> = values.iter().map(OrderedValue).collect();
+        sorted.sort();
+
+        assert_eq!(
+            vec![double_value(1.5), int_value(2), int_value(3)],
+            sorted.into_iter().map(|v| v.0.clone()).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,606 @@
+//! A builder for `StructuredQuery`s over documents of a given Rust type.
+//!
+//! Field paths are checked against the serde field names of the document
+//! type as far as it can describe them (i.e. through structs, newtypes and
+//! options), and operand and cursor values are serialized with
+//! `to_grpc_value`, so that misspelled fields and unserializable values are
+//! reported before the query is sent.
+
+use crate::field_path::InvalidFieldPath;
+use crate::firestore::{
+    structured_query::{
+        composite_filter, filter::FilterType, unary_filter::OperandType, CollectionSelector,
+        CompositeFilter, FieldFilter, FieldReference, Filter, Order, Projection, UnaryFilter,
+    },
+    value::ValueType,
+    ArrayValue, Cursor, StructuredQuery, Value,
+};
+use crate::serialize::SerializationError;
+use crate::{FieldPath, SerializerConfig, ValueSerializer};
+use serde::de::{
+    self, value::StrDeserializer, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess,
+    Visitor,
+};
+use serde::{forward_to_deserialize_any, Deserializer, Serialize};
+use std::fmt::Display;
+use std::marker::PhantomData;
+
+pub use crate::firestore::structured_query::field_filter::Operator as FieldOperator;
+pub use crate::firestore::structured_query::unary_filter::Operator as UnaryOperator;
+pub use crate::firestore::structured_query::Direction;
+
+/// The field path Firestore uses to refer to a document's name, e.g. to
+/// order by document ID.
+pub const DOCUMENT_NAME_FIELD: &str = "__name__";
+
+#[derive(Debug, PartialEq)]
+pub enum QueryError {
+    InvalidFieldPath(InvalidFieldPath),
+    /// A field path whose first segments do not name fields of the
+    /// document type.
+    UnknownField(FieldPath),
+    Serialization(SerializationError),
+    /// A cursor was given values which do not serialize to an array.
+    CursorNotAnArray,
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::InvalidFieldPath(e) => write!(f, "{}", e),
+            QueryError::UnknownField(path) => {
+                writeln!(f, "{} is not a field of the document type.", path)
+            }
+            QueryError::Serialization(e) => write!(f, "{}", e),
+            QueryError::CursorNotAnArray => writeln!(
+                f,
+                "Cursor values must serialize to an array, e.g. a tuple or Vec."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl From<InvalidFieldPath> for QueryError {
+    fn from(e: InvalidFieldPath) -> Self {
+        QueryError::InvalidFieldPath(e)
+    }
+}
+
+impl From<SerializationError> for QueryError {
+    fn from(e: SerializationError) -> Self {
+        QueryError::Serialization(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, QueryError>;
+
+/// Builds a `StructuredQuery` over a collection of documents which
+/// deserialize to `T`.
+///
+/// ```ignore
+/// let query = QueryBuilder::<User>::new("users")
+///     .where_field("age", FieldOperator::GreaterThanOrEqual, &18)?
+///     .order_by("age", Direction::Descending)?
+///     .limit(10)
+///     .build();
+/// ```
+pub struct QueryBuilder<T> {
+    query: StructuredQuery,
+    config: SerializerConfig,
+    document_type: PhantomData<fn() -> T>,
+}
+
+impl<T> QueryBuilder<T>
+where
+    T: DeserializeOwned,
+{
+    /// A query over the collection with the given ID, which is the last
+    /// segment of the collection's path.
+    pub fn new(collection_id: &str) -> Self {
+        QueryBuilder {
+            query: StructuredQuery {
+                from: vec![CollectionSelector {
+                    collection_id: collection_id.to_string(),
+                    all_descendants: false,
+                }],
+                ..StructuredQuery::default()
+            },
+            config: SerializerConfig::default(),
+            document_type: PhantomData,
+        }
+    }
+
+    /// Query every collection with the collection ID, rather than only the
+    /// one directly under the parent the query is run on.
+    pub fn all_descendants(mut self) -> Self {
+        for selector in &mut self.query.from {
+            selector.all_descendants = true;
+        }
+        self
+    }
+
+    /// The configuration used to serialize operand and cursor values. This
+    /// should match the one the documents were written with.
+    pub fn with_config(mut self, config: SerializerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Parses `path` and checks it against the fields of `T`.
+    pub fn field(&self, path: &str) -> Result<FieldReference> {
+        let field_path: FieldPath = path.parse()?;
+
+        if field_path.segments()[0] != DOCUMENT_NAME_FIELD
+            && !has_field::<T>(field_path.segments())
+        {
+            return Err(QueryError::UnknownField(field_path));
+        }
+
+        Ok(FieldReference {
+            field_path: field_path.to_string(),
+        })
+    }
+
+    /// A filter comparing the field at `path` to `value`.
+    pub fn field_filter<V>(&self, path: &str, op: FieldOperator, value: &V) -> Result<Filter>
+    where
+        V: Serialize + ?Sized,
+    {
+        Ok(Filter {
+            filter_type: Some(FilterType::FieldFilter(FieldFilter {
+                field: Some(self.field(path)?),
+                op: op as i32,
+                value: Some(value.serialize(ValueSerializer::with_config(self.config))?),
+            })),
+        })
+    }
+
+    /// A filter testing the field at `path` for null or NaN.
+    pub fn unary_filter(&self, path: &str, op: UnaryOperator) -> Result<Filter> {
+        Ok(Filter {
+            filter_type: Some(FilterType::UnaryFilter(UnaryFilter {
+                op: op as i32,
+                operand_type: Some(OperandType::Field(self.field(path)?)),
+            })),
+        })
+    }
+
+    /// Adds a filter to the query. If the query already has one, both must
+    /// match.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.query.r#where = Some(match self.query.r#where.take() {
+            Some(existing) => and(vec![existing, filter]),
+            None => filter,
+        });
+        self
+    }
+
+    pub fn where_field<V>(self, path: &str, op: FieldOperator, value: &V) -> Result<Self>
+    where
+        V: Serialize + ?Sized,
+    {
+        let filter = self.field_filter(path, op, value)?;
+        Ok(self.filter(filter))
+    }
+
+    pub fn where_unary(self, path: &str, op: UnaryOperator) -> Result<Self> {
+        let filter = self.unary_filter(path, op)?;
+        Ok(self.filter(filter))
+    }
+
+    /// Orders results by the field at `path`, after any previous orderings.
+    pub fn order_by(mut self, path: &str, direction: Direction) -> Result<Self> {
+        let field = self.field(path)?;
+        self.query.order_by.push(Order {
+            field: Some(field),
+            direction: direction as i32,
+        });
+        Ok(self)
+    }
+
+    /// Returns only the fields at the given paths.
+    pub fn select(mut self, paths: &[&str]) -> Result<Self> {
+        let fields = paths
+            .iter()
+            .map(|path| self.field(path))
+            .collect::<Result<Vec<_>>>()?;
+        self.query.select = Some(Projection { fields });
+        Ok(self)
+    }
+
+    /// Starts at the result whose ordered fields equal `values`, which must
+    /// serialize to an array with one element per `order_by` field.
+    pub fn start_at<V>(mut self, values: &V) -> Result<Self>
+    where
+        V: Serialize + ?Sized,
+    {
+        self.query.start_at = Some(self.cursor(values, true)?);
+        Ok(self)
+    }
+
+    /// Like `start_at`, but skips the result whose ordered fields equal
+    /// `values`.
+    pub fn start_after<V>(mut self, values: &V) -> Result<Self>
+    where
+        V: Serialize + ?Sized,
+    {
+        self.query.start_at = Some(self.cursor(values, false)?);
+        Ok(self)
+    }
+
+    /// Ends at (and includes) the result whose ordered fields equal
+    /// `values`.
+    pub fn end_at<V>(mut self, values: &V) -> Result<Self>
+    where
+        V: Serialize + ?Sized,
+    {
+        self.query.end_at = Some(self.cursor(values, false)?);
+        Ok(self)
+    }
+
+    /// Like `end_at`, but excludes the result whose ordered fields equal
+    /// `values`.
+    pub fn end_before<V>(mut self, values: &V) -> Result<Self>
+    where
+        V: Serialize + ?Sized,
+    {
+        self.query.end_at = Some(self.cursor(values, true)?);
+        Ok(self)
+    }
+
+    pub fn offset(mut self, offset: i32) -> Self {
+        self.query.offset = offset;
+        self
+    }
+
+    pub fn limit(mut self, limit: i32) -> Self {
+        self.query.limit = Some(limit);
+        self
+    }
+
+    pub fn build(self) -> StructuredQuery {
+        self.query
+    }
+
+    fn cursor<V>(&self, values: &V, before: bool) -> Result<Cursor>
+    where
+        V: Serialize + ?Sized,
+    {
+        if let Value {
+            value_type: Some(ValueType::ArrayValue(ArrayValue { values })),
+        } = values.serialize(ValueSerializer::with_config(self.config))?
+        {
+            Ok(Cursor { values, before })
+        } else {
+            Err(QueryError::CursorNotAnArray)
+        }
+    }
+}
+
+/// A filter which matches when all of `filters` match.
+pub fn and(filters: Vec<Filter>) -> Filter {
+    Filter {
+        filter_type: Some(FilterType::CompositeFilter(CompositeFilter {
+            op: composite_filter::Operator::And as i32,
+            filters,
+        })),
+    }
+}
+
+/// Whether `T` has a field at the given path. Only structs list their
+/// fields, so a path which passes through any other type (e.g. a map or a
+/// flattened struct) is assumed to exist.
+fn has_field<T: DeserializeOwned>(path: &[String]) -> bool {
+    !matches!(
+        T::deserialize(FieldProbe { path }),
+        Err(ProbeError::UnknownField)
+    )
+}
+
+/// The outcome of probing a type for a field. Probing always ends in an
+/// error, since no value is actually deserialized.
+#[derive(Debug)]
+enum ProbeError {
+    /// The end of the path was reached.
+    Found,
+    UnknownField,
+    /// The path passes through a type which doesn't list its fields.
+    Unchecked,
+    Message(String),
+}
+
+impl Display for ProbeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeError::Found => write!(f, "Reached the end of the field path."),
+            ProbeError::UnknownField => write!(f, "The field path names an unknown field."),
+            ProbeError::Unchecked => {
+                write!(
+                    f,
+                    "The field path passes through a type without named fields."
+                )
+            }
+            ProbeError::Message(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+impl de::Error for ProbeError {
+    fn custom<T>(msg: T) -> Self
+    where
+        T: Display,
+    {
+        ProbeError::Message(msg.to_string())
+    }
+}
+
+/// A deserializer which follows `path` through the fields of the type
+/// being deserialized.
+struct FieldProbe<'a> {
+    path: &'a [String],
+}
+
+impl<'de, 'a> Deserializer<'de> for FieldProbe<'a> {
+    type Error = ProbeError;
+
+    fn deserialize_any<V>(self, _visitor: V) -> std::result::Result<V::Value, ProbeError>
+    where
+        V: Visitor<'de>,
+    {
+        if self.path.is_empty() {
+            Err(ProbeError::Found)
+        } else {
+            Err(ProbeError::Unchecked)
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> std::result::Result<V::Value, ProbeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> std::result::Result<V::Value, ProbeError>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> std::result::Result<V::Value, ProbeError>
+    where
+        V: Visitor<'de>,
+    {
+        match self.path.split_first() {
+            None => Err(ProbeError::Found),
+            Some((first, _)) if !fields.contains(&first.as_str()) => {
+                Err(ProbeError::UnknownField)
+            }
+            Some((first, rest)) => visitor.visit_map(FieldProbeMap {
+                key: Some(first),
+                rest,
+            }),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map enum
+        identifier ignored_any
+    }
+}
+
+/// A map with the single entry `key`, whose value is probed for `rest`.
+struct FieldProbeMap<'a> {
+    key: Option<&'a str>,
+    rest: &'a [String],
+}
+
+impl<'de, 'a> MapAccess<'de> for FieldProbeMap<'a> {
+    type Error = ProbeError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> std::result::Result<Option<K::Value>, ProbeError>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.key {
+            Some(key) => {
+                let key: StrDeserializer<ProbeError> = key.into_deserializer();
+                seed.deserialize(key).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> std::result::Result<V::Value, ProbeError>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.key = None;
+        seed.deserialize(FieldProbe { path: self.rest })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Address {
+        city: String,
+        #[serde(rename = "zip code")]
+        zip: String,
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct User {
+        name: String,
+        age: u32,
+        address: Option<Address>,
+        tags: Vec<String>,
+        extra: HashMap<String, String>,
+    }
+
+    fn string_value(v: &str) -> Value {
+        Value {
+            value_type: Some(ValueType::StringValue(v.to_string())),
+        }
+    }
+
+    fn int_value(v: i64) -> Value {
+        Value {
+            value_type: Some(ValueType::IntegerValue(v)),
+        }
+    }
+
+    #[test]
+    fn test_field_validation() {
+        let query = QueryBuilder::<User>::new("users");
+
+        assert_eq!("age", query.field("age").unwrap().field_path);
+        assert_eq!(
+            "address.`zip code`",
+            query.field("address.`zip code`").unwrap().field_path
+        );
+        assert_eq!("extra.anything", query.field("extra.anything").unwrap().field_path);
+        assert_eq!("__name__", query.field("__name__").unwrap().field_path);
+
+        assert_eq!(
+            Err(QueryError::UnknownField("agee".parse().unwrap())),
+            query.field("agee")
+        );
+        assert_eq!(
+            Err(QueryError::UnknownField("address.town".parse().unwrap())),
+            query.field("address.town")
+        );
+        assert_eq!(
+            Err(QueryError::InvalidFieldPath(InvalidFieldPath(
+                "address.".to_string()
+            ))),
+            query.field("address.")
+        );
+    }
+
+    #[test]
+    fn test_build_query() {
+        let query = QueryBuilder::<User>::new("users")
+            .all_descendants()
+            .where_field("age", FieldOperator::GreaterThanOrEqual, &18)
+            .unwrap()
+            .where_unary("address", UnaryOperator::IsNotNull)
+            .unwrap()
+            .order_by("age", Direction::Descending)
+            .unwrap()
+            .select(&["name", "age"])
+            .unwrap()
+            .start_after(&(30,))
+            .unwrap()
+            .offset(5)
+            .limit(10)
+            .build();
+
+        let expected = StructuredQuery {
+            select: Some(Projection {
+                fields: vec![
+                    FieldReference {
+                        field_path: "name".to_string(),
+                    },
+                    FieldReference {
+                        field_path: "age".to_string(),
+                    },
+                ],
+            }),
+            from: vec![CollectionSelector {
+                collection_id: "users".to_string(),
+                all_descendants: true,
+            }],
+            r#where: Some(and(vec![
+                Filter {
+                    filter_type: Some(FilterType::FieldFilter(FieldFilter {
+                        field: Some(FieldReference {
+                            field_path: "age".to_string(),
+                        }),
+                        op: FieldOperator::GreaterThanOrEqual as i32,
+                        value: Some(int_value(18)),
+                    })),
+                },
+                Filter {
+                    filter_type: Some(FilterType::UnaryFilter(UnaryFilter {
+                        op: UnaryOperator::IsNotNull as i32,
+                        operand_type: Some(OperandType::Field(FieldReference {
+                            field_path: "address".to_string(),
+                        })),
+                    })),
+                },
+            ])),
+            order_by: vec![Order {
+                field: Some(FieldReference {
+                    field_path: "age".to_string(),
+                }),
+                direction: Direction::Descending as i32,
+            }],
+            start_at: Some(Cursor {
+                values: vec![int_value(30)],
+                before: false,
+            }),
+            end_at: None,
+            offset: 5,
+            limit: Some(10),
+        };
+
+        assert_eq!(expected, query);
+    }
+
+    #[test]
+    fn test_array_operands() {
+        let query = QueryBuilder::<User>::new("users")
+            .where_field("tags", FieldOperator::ArrayContainsAny, &["a", "b"])
+            .unwrap()
+            .build();
+
+        assert_eq!(
+            Some(Filter {
+                filter_type: Some(FilterType::FieldFilter(FieldFilter {
+                    field: Some(FieldReference {
+                        field_path: "tags".to_string(),
+                    }),
+                    op: FieldOperator::ArrayContainsAny as i32,
+                    value: Some(Value {
+                        value_type: Some(ValueType::ArrayValue(ArrayValue {
+                            values: vec![string_value("a"), string_value("b")],
+                        })),
+                    }),
+                })),
+            }),
+            query.r#where
+        );
+    }
+
+    #[test]
+    fn test_cursor_not_an_array() {
+        let result = QueryBuilder::<User>::new("users")
+            .order_by("age", Direction::Ascending)
+            .unwrap()
+            .end_at(&30);
+
+        assert_eq!(Some(QueryError::CursorNotAnArray), result.err());
+    }
+}
@@ -0,0 +1,252 @@
+use crate::REFERENCE_MAGIC;
+use serde::{
+    de::{Error, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{fmt::Display, str::FromStr};
+
+pub const DEFAULT_DATABASE: &str = "(default)";
+
+/// A reference to another document, stored in Firestore as a `ReferenceValue`.
+///
+/// References are formatted as Firestore resource names, i.e.
+/// `projects/{project_id}/databases/{database_id}/documents/{document_path}`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DocumentReference {
+    pub project_id: String,
+    pub database_id: String,
+    /// Alternating collection and document IDs leading to the collection that
+    /// contains the document, e.g. `["users", "alice", "posts"]`.
+    pub collection_path: Vec<String>,
+    pub document_id: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidReference(pub String);
+
+impl Display for InvalidReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:?} is not a valid document reference.", self.0)
+    }
+}
+
+impl std::error::Error for InvalidReference {}
+
+impl DocumentReference {
+    pub fn new(
+        project_id: &str,
+        database_id: &str,
+        collection_path: &[&str],
+        document_id: &str,
+    ) -> Self {
+        DocumentReference {
+            project_id: project_id.to_string(),
+            database_id: database_id.to_string(),
+            collection_path: collection_path.iter().map(|s| s.to_string()).collect(),
+            document_id: document_id.to_string(),
+        }
+    }
+
+    /// Parses a slash-separated document path (e.g. `users/alice`) relative to
+    /// the given database.
+    pub fn from_path(
+        project_id: &str,
+        database_id: &str,
+        path: &str,
+    ) -> Result<Self, InvalidReference> {
+        let segments: Vec<&str> = path.split('/').collect();
+
+        if segments.len() % 2 != 0 || segments.iter().any(|s| s.is_empty()) {
+            return Err(InvalidReference(path.to_string()));
+        }
+
+        let (document_id, collection_path) = segments
+            .split_last()
+            .expect("Already checked that path has an even, non-zero number of segments.");
+
+        Ok(DocumentReference::new(
+            project_id,
+            database_id,
+            collection_path,
+            document_id,
+        ))
+    }
+
+    /// The resource name of the database, e.g. `projects/p/databases/(default)`.
+    pub fn database_name(&self) -> String {
+        format!(
+            "projects/{}/databases/{}",
+            self.project_id, self.database_id
+        )
+    }
+
+    /// The path of the document relative to the database, e.g. `users/alice`.
+    pub fn document_path(&self) -> String {
+        let mut segments: Vec<&str> = self.collection_path.iter().map(String::as_str).collect();
+        segments.push(&self.document_id);
+        segments.join("/")
+    }
+
+    /// The ID of the collection which directly contains the document.
+    pub fn collection_id(&self) -> &str {
+        self.collection_path
+            .last()
+            .expect("A document reference always has a collection.")
+    }
+}
+
+impl Display for DocumentReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/documents/{}",
+            self.database_name(),
+            self.document_path()
+        )
+    }
+}
+
+impl FromStr for DocumentReference {
+    type Err = InvalidReference;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidReference(name.to_string());
+
+        let mut parts = name.splitn(6, '/');
+
+        if parts.next() != Some("projects") {
+            return Err(invalid());
+        }
+        let project_id = parts.next().filter(|s| !s.is_empty()).ok_or_else(invalid)?;
+        if parts.next() != Some("databases") {
+            return Err(invalid());
+        }
+        let database_id = parts.next().filter(|s| !s.is_empty()).ok_or_else(invalid)?;
+        if parts.next() != Some("documents") {
+            return Err(invalid());
+        }
+        let path = parts.next().ok_or_else(invalid)?;
+
+        DocumentReference::from_path(project_id, database_id, path).map_err(|_| invalid())
+    }
+}
+
+impl Serialize for DocumentReference {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(REFERENCE_MAGIC, &self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for DocumentReference {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(REFERENCE_MAGIC, DocumentReferenceVisitor)
+    }
+}
+
+struct DocumentReferenceVisitor;
+
+impl<'de> Visitor<'de> for DocumentReferenceVisitor {
+    type Value = DocumentReference;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a document reference")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<DocumentReference, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+
+        name.parse().map_err(D::Error::custom)
+    }
+}
+
+#[cfg(all(test, feature = "googapis"))]
+mod test {
+    use super::{DocumentReference, InvalidReference, DEFAULT_DATABASE};
+    use crate::deserialize::DeserializationError;
+    use crate::firestore::{value::ValueType, Value};
+    use crate::{from_grpc_value, to_grpc_value};
+
+    #[test]
+    fn test_parse_reference() {
+        let reference: DocumentReference =
+            "projects/my-project/databases/(default)/documents/users/alice/posts/p1"
+                .parse()
+                .unwrap();
+
+        assert_eq!(
+            DocumentReference::new(
+                "my-project",
+                DEFAULT_DATABASE,
+                &["users", "alice", "posts"],
+                "p1"
+            ),
+            reference
+        );
+
+        assert_eq!("posts", reference.collection_id());
+        assert_eq!("users/alice/posts/p1", reference.document_path());
+        assert_eq!(
+            "projects/my-project/databases/(default)/documents/users/alice/posts/p1",
+            reference.to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_reference() {
+        for name in [
+            "",
+            "projects/p/databases/d/documents",
+            "projects/p/databases/d/documents/users",
+            "projects/p/databases/d/documents/users//posts/p1",
+            "projects//databases/d/documents/users/alice",
+            "project/p/databases/d/documents/users/alice",
+        ] {
+            assert_eq!(
+                Err(InvalidReference(name.to_string())),
+                name.parse::<DocumentReference>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_serialize_reference() {
+        let reference =
+            DocumentReference::from_path("my-project", DEFAULT_DATABASE, "users/alice").unwrap();
+
+        let result = to_grpc_value(&reference).unwrap();
+
+        assert_eq!(
+            Value {
+                value_type: Some(ValueType::ReferenceValue(
+                    "projects/my-project/databases/(default)/documents/users/alice".to_string()
+                ))
+            },
+            result
+        );
+
+        assert_eq!(reference, from_grpc_value(&result).unwrap());
+    }
+
+    #[test]
+    fn test_reference_wrong_type() {
+        let value = Value {
+            value_type: Some(ValueType::StringValue(
+                "projects/my-project/databases/(default)/documents/users/alice".to_string(),
+            )),
+        };
+
+        assert_eq!(
+            DeserializationError::WrongType("reference", "string"),
+            from_grpc_value::<DocumentReference>(&value).unwrap_err()
+        );
+    }
+}
@@ -0,0 +1,673 @@
+//! The JSON representation of values and documents used by Firestore's REST
+//! API and by event payloads (e.g. Cloud Functions triggers), such as
+//! `{"fields": {"count": {"integerValue": "5"}}}`.
+//!
+//! `RestValue` and `RestDocument` wrap the gRPC types and implement
+//! `Serialize` and `Deserialize` in that representation, so a document
+//! received as JSON can be passed to `from_document`, and the result of
+//! `to_document` can be sent as JSON:
+//!
+//! ```rust,ignore
+//! let RestDocument(document) = serde_json::from_str(&body)?;
+//! let user: User = firestore_serde::from_document(document)?;
+//! ```
+
+use crate::firestore::{value::ValueType, ArrayValue, Document, MapValue, Value};
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use googapis::google::r#type::LatLng;
+use prost_types::Timestamp;
+use serde::{
+    de::{self, MapAccess, Visitor},
+    ser::{self, SerializeMap},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// A `Value` which serializes to and from the REST JSON representation.
+///
+/// Integers are represented as decimal strings, bytes as base64, timestamps
+/// as RFC 3339 strings and non-finite doubles as `"NaN"`, `"Infinity"` and
+/// `"-Infinity"`. A value with no type is represented as `{}`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RestValue(pub Value);
+
+/// A `Document` which serializes to and from the REST JSON representation,
+/// i.e. an object with `name`, `fields`, `createTime` and `updateTime`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RestDocument(pub Document);
+
+impl From<Value> for RestValue {
+    fn from(value: Value) -> Self {
+        RestValue(value)
+    }
+}
+
+impl From<RestValue> for Value {
+    fn from(value: RestValue) -> Self {
+        value.0
+    }
+}
+
+impl From<Document> for RestDocument {
+    fn from(document: Document) -> Self {
+        RestDocument(document)
+    }
+}
+
+impl From<RestDocument> for Document {
+    fn from(document: RestDocument) -> Self {
+        document.0
+    }
+}
+
+/// Serializes a borrowed value in the REST representation.
+struct ValueRef<'a>(&'a Value);
+
+/// Serializes a borrowed map of fields in the REST representation.
+struct FieldsRef<'a>(&'a HashMap<String, Value>);
+
+#[derive(Serialize)]
+struct ArrayRef<'a> {
+    values: Vec<ValueRef<'a>>,
+}
+
+#[derive(Serialize)]
+struct MapRef<'a> {
+    fields: FieldsRef<'a>,
+}
+
+/// Formats `timestamp` as RFC 3339, or returns `None` if its nanoseconds
+/// aren't in `0..1_000_000_000` or it is too far from the epoch to format.
+pub(crate) fn format_timestamp(timestamp: &Timestamp) -> Option<String> {
+    let nanos = u32::try_from(timestamp.nanos)
+        .ok()
+        .filter(|nanos| *nanos < 1_000_000_000)?;
+
+    Utc.timestamp_opt(timestamp.seconds, nanos)
+        .single()
+        .map(|datetime| datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+fn timestamp_string<E: ser::Error>(timestamp: &Timestamp) -> Result<String, E> {
+    format_timestamp(timestamp).ok_or_else(|| {
+        E::custom(format!(
+            "timestamp {}s {}ns is outside the range of RFC 3339",
+            timestamp.seconds, timestamp.nanos
+        ))
+    })
+}
+
+fn parse_timestamp<E: de::Error>(timestamp: &str) -> Result<Timestamp, E> {
+    let datetime = DateTime::parse_from_rfc3339(timestamp)
+        .map_err(|_| E::invalid_value(de::Unexpected::Str(timestamp), &"an RFC 3339 timestamp"))?;
+
+    #[allow(clippy::cast_possible_wrap)]
+    Ok(Timestamp {
+        seconds: datetime.timestamp(),
+        nanos: datetime.timestamp_subsec_nanos() as i32,
+    })
+}
+
+impl<'a> Serialize for FieldsRef<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.iter().map(|(k, v)| (k, ValueRef(v))))
+    }
+}
+
+impl<'a> Serialize for ValueRef<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let value_type = match &self.0.value_type {
+            Some(value_type) => value_type,
+            None => return serializer.serialize_map(Some(0))?.end(),
+        };
+
+        let mut map = serializer.serialize_map(Some(1))?;
+
+        match value_type {
+            ValueType::NullValue(_) => map.serialize_entry("nullValue", &())?,
+            ValueType::BooleanValue(v) => map.serialize_entry("booleanValue", v)?,
+            ValueType::IntegerValue(v) => map.serialize_entry("integerValue", &v.to_string())?,
+            ValueType::DoubleValue(v) if v.is_nan() => map.serialize_entry("doubleValue", "NaN")?,
+            ValueType::DoubleValue(v) if v.is_infinite() => map.serialize_entry(
+                "doubleValue",
+                if *v > 0. { "Infinity" } else { "-Infinity" },
+            )?,
+            ValueType::DoubleValue(v) => map.serialize_entry("doubleValue", v)?,
+            ValueType::TimestampValue(v) => {
+                map.serialize_entry("timestampValue", &timestamp_string::<S::Error>(v)?)?
+            }
+            ValueType::StringValue(v) => map.serialize_entry("stringValue", v)?,
+            ValueType::BytesValue(v) => map.serialize_entry("bytesValue", &base64::encode(v))?,
+            ValueType::ReferenceValue(v) => map.serialize_entry("referenceValue", v)?,
+            ValueType::GeoPointValue(v) => map.serialize_entry(
+                "geoPointValue",
+                &GeoPointRepr {
+                    latitude: v.latitude,
+                    longitude: v.longitude,
+                },
+            )?,
+            ValueType::ArrayValue(ArrayValue { values }) => map.serialize_entry(
+                "arrayValue",
+                &ArrayRef {
+                    values: values.iter().map(ValueRef).collect(),
+                },
+            )?,
+            ValueType::MapValue(MapValue { fields }) => map.serialize_entry(
+                "mapValue",
+                &MapRef {
+                    fields: FieldsRef(fields),
+                },
+            )?,
+        }
+
+        map.end()
+    }
+}
+
+impl Serialize for RestValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        ValueRef(&self.0).serialize(serializer)
+    }
+}
+
+impl Serialize for RestDocument {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let document = &self.0;
+        let mut map = serializer.serialize_map(None)?;
+
+        if !document.name.is_empty() {
+            map.serialize_entry("name", &document.name)?;
+        }
+
+        map.serialize_entry("fields", &FieldsRef(&document.fields))?;
+
+        if let Some(create_time) = &document.create_time {
+            map.serialize_entry("createTime", &timestamp_string::<S::Error>(create_time)?)?;
+        }
+
+        if let Some(update_time) = &document.update_time {
+            map.serialize_entry("updateTime", &timestamp_string::<S::Error>(update_time)?)?;
+        }
+
+        map.end()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct GeoPointRepr {
+    #[serde(default)]
+    latitude: f64,
+    #[serde(default)]
+    longitude: f64,
+}
+
+#[derive(Deserialize)]
+struct ArrayRepr {
+    #[serde(default)]
+    values: Vec<RestValue>,
+}
+
+#[derive(Deserialize)]
+struct MapRepr {
+    #[serde(default)]
+    fields: HashMap<String, RestValue>,
+}
+
+/// An integer, which the REST API represents as a string but which is also
+/// accepted as a JSON number.
+struct IntegerRepr(i64);
+
+impl<'de> Deserialize<'de> for IntegerRepr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct IntegerVisitor;
+
+        impl<'de> Visitor<'de> for IntegerVisitor {
+            type Value = IntegerRepr;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an integer or a string containing an integer")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<IntegerRepr, E> {
+                Ok(IntegerRepr(v))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<IntegerRepr, E> {
+                i64::try_from(v)
+                    .map(IntegerRepr)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<IntegerRepr, E> {
+                v.parse()
+                    .map(IntegerRepr)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+            }
+        }
+
+        deserializer.deserialize_any(IntegerVisitor)
+    }
+}
+
+/// A double, which the REST API represents as a JSON number, or as a string
+/// if it is not finite.
+struct DoubleRepr(f64);
+
+impl<'de> Deserialize<'de> for DoubleRepr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DoubleVisitor;
+
+        impl<'de> Visitor<'de> for DoubleVisitor {
+            type Value = DoubleRepr;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a number, \"NaN\", \"Infinity\" or \"-Infinity\"")
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<DoubleRepr, E> {
+                Ok(DoubleRepr(v))
+            }
+
+            #[allow(clippy::cast_precision_loss)]
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<DoubleRepr, E> {
+                Ok(DoubleRepr(v as f64))
+            }
+
+            #[allow(clippy::cast_precision_loss)]
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<DoubleRepr, E> {
+                Ok(DoubleRepr(v as f64))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<DoubleRepr, E> {
+                match v {
+                    "NaN" => Ok(DoubleRepr(f64::NAN)),
+                    "Infinity" => Ok(DoubleRepr(f64::INFINITY)),
+                    "-Infinity" => Ok(DoubleRepr(f64::NEG_INFINITY)),
+                    _ => v
+                        .parse()
+                        .map(DoubleRepr)
+                        .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(DoubleVisitor)
+    }
+}
+
+const VALUE_TYPES: &[&str] = &[
+    "nullValue",
+    "booleanValue",
+    "integerValue",
+    "doubleValue",
+    "timestampValue",
+    "stringValue",
+    "bytesValue",
+    "referenceValue",
+    "geoPointValue",
+    "arrayValue",
+    "mapValue",
+];
+
+impl<'de> Deserialize<'de> for RestValue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ValueVisitor;
+
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = RestValue;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a Firestore value object")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<RestValue, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let key: String = match map.next_key()? {
+                    Some(key) => key,
+                    None => return Ok(RestValue(Value { value_type: None })),
+                };
+
+                let value_type = match key.as_str() {
+                    "nullValue" => {
+                        map.next_value::<de::IgnoredAny>()?;
+                        ValueType::NullValue(0)
+                    }
+                    "booleanValue" => ValueType::BooleanValue(map.next_value()?),
+                    "integerValue" => ValueType::IntegerValue(map.next_value::<IntegerRepr>()?.0),
+                    "doubleValue" => ValueType::DoubleValue(map.next_value::<DoubleRepr>()?.0),
+                    "timestampValue" => {
+                        ValueType::TimestampValue(parse_timestamp(&map.next_value::<String>()?)?)
+                    }
+                    "stringValue" => ValueType::StringValue(map.next_value()?),
+                    "bytesValue" => {
+                        let encoded: String = map.next_value()?;
+                        let bytes = base64::decode(&encoded).map_err(|_| {
+                            de::Error::invalid_value(
+                                de::Unexpected::Str(&encoded),
+                                &"a base64 string",
+                            )
+                        })?;
+                        ValueType::BytesValue(bytes)
+                    }
+                    "referenceValue" => ValueType::ReferenceValue(map.next_value()?),
+                    "geoPointValue" => {
+                        let GeoPointRepr {
+                            latitude,
+                            longitude,
+                        } = map.next_value()?;
+                        ValueType::GeoPointValue(LatLng {
+                            latitude,
+                            longitude,
+                        })
+                    }
+                    "arrayValue" => {
+                        let ArrayRepr { values } = map.next_value()?;
+                        ValueType::ArrayValue(ArrayValue {
+                            values: values.into_iter().map(Value::from).collect(),
+                        })
+                    }
+                    "mapValue" => {
+                        let MapRepr { fields } = map.next_value()?;
+                        ValueType::MapValue(MapValue {
+                            fields: fields.into_iter().map(|(k, v)| (k, v.0)).collect(),
+                        })
+                    }
+                    _ => return Err(de::Error::unknown_field(&key, VALUE_TYPES)),
+                };
+
+                if let Some(key) = map.next_key::<String>()? {
+                    return Err(de::Error::custom(format!(
+                        "Firestore value has more than one type, found both {} and {}",
+                        value_type_name(&value_type),
+                        key
+                    )));
+                }
+
+                Ok(RestValue(Value {
+                    value_type: Some(value_type),
+                }))
+            }
+        }
+
+        deserializer.deserialize_map(ValueVisitor)
+    }
+}
+
+/// The REST field name of a value's type.
+fn value_type_name(value_type: &ValueType) -> &'static str {
+    match value_type {
+        ValueType::NullValue(_) => "nullValue",
+        ValueType::BooleanValue(_) => "booleanValue",
+        ValueType::IntegerValue(_) => "integerValue",
+        ValueType::DoubleValue(_) => "doubleValue",
+        ValueType::TimestampValue(_) => "timestampValue",
+        ValueType::StringValue(_) => "stringValue",
+        ValueType::BytesValue(_) => "bytesValue",
+        ValueType::ReferenceValue(_) => "referenceValue",
+        ValueType::GeoPointValue(_) => "geoPointValue",
+        ValueType::ArrayValue(_) => "arrayValue",
+        ValueType::MapValue(_) => "mapValue",
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DocumentRepr {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    fields: HashMap<String, RestValue>,
+    create_time: Option<String>,
+    update_time: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for RestDocument {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let DocumentRepr {
+            name,
+            fields,
+            create_time,
+            update_time,
+        } = DocumentRepr::deserialize(deserializer)?;
+
+        Ok(RestDocument(Document {
+            name,
+            fields: fields.into_iter().map(|(k, v)| (k, v.0)).collect(),
+            create_time: create_time.as_deref().map(parse_timestamp).transpose()?,
+            update_time: update_time.as_deref().map(parse_timestamp).transpose()?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RestDocument, RestValue};
+    use crate::firestore::{value::ValueType, Document, MapValue, Value};
+    use crate::{from_document, to_document, to_grpc_value, DocumentReference, GeoPoint};
+    use prost_types::Timestamp;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct AnEvent {
+        count: i64,
+        ratio: f64,
+        name: String,
+        #[serde(with = "serde_bytes")]
+        payload: Vec<u8>,
+        location: GeoPoint,
+        owner: DocumentReference,
+        tags: Vec<String>,
+        extra: Option<bool>,
+    }
+
+    fn event_json() -> serde_json::Value {
+        json!({
+            "name": "projects/p/databases/(default)/documents/events/e1",
+            "fields": {
+                "count": {"integerValue": "5"},
+                "ratio": {"doubleValue": 0.5},
+                "name": {"stringValue": "launch"},
+                "payload": {"bytesValue": "AQID"},
+                "location": {"geoPointValue": {"latitude": 1.5, "longitude": -2.5}},
+                "owner": {"referenceValue": "projects/p/databases/(default)/documents/users/u"},
+                "tags": {"arrayValue": {"values": [{"stringValue": "a"}, {"stringValue": "b"}]}},
+                "extra": {"nullValue": null},
+            },
+            "createTime": "2022-02-03T04:05:07Z",
+            "updateTime": "2022-02-03T04:05:08.000001Z",
+        })
+    }
+
+    fn event() -> AnEvent {
+        AnEvent {
+            count: 5,
+            ratio: 0.5,
+            name: "launch".to_string(),
+            payload: vec![1, 2, 3],
+            location: GeoPoint::new(1.5, -2.5),
+            owner: DocumentReference::new("p", "(default)", &["users"], "u"),
+            tags: vec!["a".to_string(), "b".to_string()],
+            extra: None,
+        }
+    }
+
+    #[test]
+    fn test_decode_document() {
+        let RestDocument(document) = serde_json::from_value(event_json()).unwrap();
+
+        assert_eq!(
+            "projects/p/databases/(default)/documents/events/e1",
+            document.name
+        );
+        assert_eq!(
+            1_643_861_107,
+            document.create_time.as_ref().unwrap().seconds
+        );
+        assert_eq!(1000, document.update_time.as_ref().unwrap().nanos);
+        assert_eq!(event(), from_document(document).unwrap());
+    }
+
+    #[test]
+    fn test_encode_document() {
+        let RestDocument(mut received) = serde_json::from_value(event_json()).unwrap();
+        let document = to_document(&event()).unwrap();
+        received.name = String::new();
+        received.create_time = None;
+        received.update_time = None;
+        assert_eq!(received, document);
+
+        let mut expected = event_json();
+        let expected = expected.as_object_mut().unwrap();
+        expected.remove("name");
+        expected.remove("createTime");
+        expected.remove("updateTime");
+
+        assert_eq!(
+            serde_json::Value::Object(expected.clone()),
+            serde_json::to_value(RestDocument(document)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_value_roundtrip() {
+        let values = vec![
+            to_grpc_value(&vec![vec![1, 2], vec![]]).unwrap(),
+            to_grpc_value(&f64::NAN).unwrap(),
+            to_grpc_value(&f64::NEG_INFINITY).unwrap(),
+            to_grpc_value(&i64::MIN).unwrap(),
+            Value { value_type: None },
+        ];
+
+        for value in values {
+            let json = serde_json::to_string(&RestValue(value.clone())).unwrap();
+            let RestValue(decoded) = serde_json::from_str(&json).unwrap();
+
+            // NaN is not equal to itself, so compare the encodings instead.
+            assert_eq!(
+                json,
+                serde_json::to_string(&RestValue(decoded)).unwrap(),
+                "{:?}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_lenient_numbers() {
+        let RestValue(value) = serde_json::from_value(json!({"integerValue": 7})).unwrap();
+        assert_eq!(Some(ValueType::IntegerValue(7)), value.value_type);
+
+        let RestValue(value) = serde_json::from_value(json!({"doubleValue": 7})).unwrap();
+        assert_eq!(Some(ValueType::DoubleValue(7.)), value.value_type);
+
+        let RestValue(value) = serde_json::from_value(json!({"doubleValue": "Infinity"})).unwrap();
+        assert_eq!(
+            Some(ValueType::DoubleValue(f64::INFINITY)),
+            value.value_type
+        );
+
+        let RestValue(value) = serde_json::from_value(json!({"mapValue": {}})).unwrap();
+        assert_eq!(
+            Some(ValueType::MapValue(MapValue::default())),
+            value.value_type
+        );
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let RestValue(value) =
+            serde_json::from_value(json!({"timestampValue": "2022-02-03T05:05:06.789+01:00"}))
+                .unwrap();
+        let timestamp = Timestamp {
+            seconds: 1_643_861_106,
+            nanos: 789_000_000,
+        };
+        assert_eq!(
+            Some(ValueType::TimestampValue(timestamp.clone())),
+            value.value_type
+        );
+
+        assert_eq!(
+            json!({"timestampValue": "2022-02-03T04:05:06.789Z"}),
+            serde_json::to_value(RestValue(Value {
+                value_type: Some(ValueType::TimestampValue(timestamp))
+            }))
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unformattable_timestamps() {
+        for timestamp in &[
+            Timestamp {
+                seconds: 0,
+                nanos: -1,
+            },
+            Timestamp {
+                seconds: 0,
+                nanos: 1_000_000_000,
+            },
+            Timestamp {
+                seconds: i64::MAX,
+                nanos: 0,
+            },
+        ] {
+            let value = RestValue(Value {
+                value_type: Some(ValueType::TimestampValue(timestamp.clone())),
+            });
+            assert!(serde_json::to_value(value).is_err(), "{:?}", timestamp);
+
+            let document = RestDocument(Document {
+                update_time: Some(timestamp.clone()),
+                ..Document::default()
+            });
+            assert!(serde_json::to_value(document).is_err(), "{:?}", timestamp);
+        }
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        for json in &[
+            json!({"integerValue": "five"}),
+            json!({"bytesValue": "not base64!"}),
+            json!({"timestampValue": "yesterday"}),
+            json!({"stringValue": "a", "integerValue": "1"}),
+            json!({"unknownValue": 1}),
+        ] {
+            assert!(
+                serde_json::from_value::<RestValue>(json.clone()).is_err(),
+                "{}",
+                json
+            );
+        }
+    }
+}
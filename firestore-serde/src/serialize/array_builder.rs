@@ -1,26 +1,46 @@
 use super::error::{Result, SerializationError};
-use crate::firestore::{value::ValueType, ArrayValue, Value};
-use crate::ValueSerializer;
+use crate::value_path::PathSegment;
+use crate::{FirestoreValue, ValueSerializer};
 use serde::{
     ser::{SerializeSeq, SerializeTuple, SerializeTupleStruct},
     Serialize,
 };
 
-#[derive(Default)]
-pub struct ArrayBuilder {
-    values: Vec<Value>,
+pub struct ArrayBuilder<V> {
+    serializer: ValueSerializer<V>,
+    values: Vec<V>,
 }
 
-impl ArrayBuilder {
-    pub fn with_capacity(capacity: usize) -> Self {
+impl<V: FirestoreValue> ArrayBuilder<V> {
+    pub fn with_capacity(serializer: ValueSerializer<V>, capacity: usize) -> Self {
         ArrayBuilder {
+            serializer,
             values: Vec::with_capacity(capacity),
         }
     }
+
+    fn push<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let since = self.serializer.transforms.pending();
+        let value = value
+            .serialize(self.serializer.clone())
+            .and_then(|value| {
+                self.serializer
+                    .transforms
+                    .forbid(since, "an array")
+                    .map(|_| value)
+            })
+            .map_err(|e| e.at(PathSegment::Index(self.values.len())))?;
+        self.values.push(value);
+
+        Ok(())
+    }
 }
 
-impl<'a> SerializeTuple for ArrayBuilder {
-    type Ok = Value;
+impl<V: FirestoreValue> SerializeTuple for ArrayBuilder<V> {
+    type Ok = V;
 
     type Error = SerializationError;
 
@@ -28,22 +48,16 @@ impl<'a> SerializeTuple for ArrayBuilder {
     where
         T: Serialize,
     {
-        self.values.push(value.serialize(ValueSerializer)?);
-
-        Ok(())
+        self.push(value)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        Ok(Value {
-            value_type: Some(ValueType::ArrayValue(ArrayValue {
-                values: self.values,
-            })),
-        })
+        Ok(V::array(self.values))
     }
 }
 
-impl<'a> SerializeTupleStruct for ArrayBuilder {
-    type Ok = Value;
+impl<V: FirestoreValue> SerializeTupleStruct for ArrayBuilder<V> {
+    type Ok = V;
 
     type Error = SerializationError;
 
@@ -51,22 +65,16 @@ impl<'a> SerializeTupleStruct for ArrayBuilder {
     where
         T: Serialize,
     {
-        self.values.push(value.serialize(ValueSerializer)?);
-
-        Ok(())
+        self.push(value)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        Ok(Value {
-            value_type: Some(ValueType::ArrayValue(ArrayValue {
-                values: self.values,
-            })),
-        })
+        Ok(V::array(self.values))
     }
 }
 
-impl<'a> SerializeSeq for ArrayBuilder {
-    type Ok = Value;
+impl<V: FirestoreValue> SerializeSeq for ArrayBuilder<V> {
+    type Ok = V;
 
     type Error = SerializationError;
 
@@ -74,16 +82,10 @@ impl<'a> SerializeSeq for ArrayBuilder {
     where
         T: Serialize,
     {
-        self.values.push(value.serialize(ValueSerializer)?);
-
-        Ok(())
+        self.push(value)
     }
 
     fn end(self) -> Result<Self::Ok> {
-        Ok(Value {
-            value_type: Some(ValueType::ArrayValue(ArrayValue {
-                values: self.values,
-            })),
-        })
+        Ok(V::array(self.values))
     }
 }
@@ -0,0 +1,375 @@
+use super::error::{Result, SerializationError};
+use super::{
+    kv_map_builder::KVMapBuilder, map_builder::MapBuilder, named_array_builder::NamedArrayBuilder,
+    named_map_builder::NamedMapBuilder,
+};
+use crate::firestore::{value::ValueType, Document, MapValue, Value};
+use crate::transform::{is_transform_magic, Transforms};
+use crate::{
+    SerializerConfig, ValueSerializer, DATE_MAGIC, GEOPOINT_MAGIC, METADATA_MAGIC,
+    REFERENCE_MAGIC,
+};
+use serde::ser::{
+    Impossible, SerializeMap, SerializeStruct, SerializeStructVariant, SerializeTupleVariant,
+};
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+
+/// Serializes a struct or map directly into the fields of a `Document`.
+/// Values which are not stored as a map (such as numbers and sequences) are
+/// rejected with `SerializationError::NotAMap` before any of their contents
+/// are serialized.
+#[derive(Clone, Default)]
+pub struct DocumentSerializer {
+    serializer: ValueSerializer<Value>,
+    name: String,
+}
+
+impl DocumentSerializer {
+    pub fn new() -> Self {
+        DocumentSerializer::default()
+    }
+
+    pub fn with_config(config: SerializerConfig) -> Self {
+        DocumentSerializer {
+            serializer: ValueSerializer::with_config(config),
+            name: String::new(),
+        }
+    }
+
+    /// Collects field transform markers into `transforms` instead of failing.
+    pub(crate) fn with_transforms(mut self, transforms: Transforms) -> Self {
+        self.serializer = self.serializer.with_transforms(transforms);
+        self
+    }
+
+    /// Sets the name of the resulting document to `document_id` inside the
+    /// collection with the given full path, e.g.
+    /// `projects/{project_id}/databases/{database_id}/documents/users`.
+    pub fn with_name(mut self, collection_path: &str, document_id: &str) -> Self {
+        self.name = format!("{}/{}", collection_path.trim_end_matches('/'), document_id);
+        self
+    }
+
+    fn document(self, fields: HashMap<String, Value>) -> Document {
+        Document {
+            name: self.name,
+            fields,
+            ..Document::default()
+        }
+    }
+
+    /// Wraps the result of serializing an enum variant, which may or may
+    /// not be a map depending on the enum representation.
+    fn variant_document(self, value: Value) -> Result<Document> {
+        if let Value {
+            value_type: Some(ValueType::MapValue(MapValue { fields })),
+        } = value
+        {
+            Ok(self.document(fields))
+        } else {
+            Err(SerializationError::NotAMap)
+        }
+    }
+}
+
+/// Collects the fields of a document with one of the `ValueSerializer`'s
+/// map builders.
+pub struct DocumentBuilder<B> {
+    builder: B,
+    document: DocumentSerializer,
+}
+
+impl Serializer for DocumentSerializer {
+    type Ok = Document;
+
+    type Error = SerializationError;
+
+    type SerializeMap = DocumentBuilder<KVMapBuilder<Value>>;
+    type SerializeSeq = Impossible<Document, SerializationError>;
+    type SerializeStruct = DocumentBuilder<MapBuilder<Value>>;
+    type SerializeStructVariant = DocumentBuilder<NamedMapBuilder<Value>>;
+    type SerializeTuple = Impossible<Document, SerializationError>;
+    type SerializeTupleStruct = Impossible<Document, SerializationError>;
+    type SerializeTupleVariant = DocumentBuilder<NamedArrayBuilder<Value>>;
+
+    fn serialize_bool(self, _v: bool) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_i128(self, _v: i128) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_u128(self, _v: u128) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_char(self, _v: char) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_none(self) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Document>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Document> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Document> {
+        let value = self
+            .serializer
+            .clone()
+            .serialize_unit_variant(name, variant_index, variant)?;
+        self.variant_document(value)
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Document>
+    where
+        T: ?Sized + Serialize,
+    {
+        // Timestamps, geopoints, references, metadata and transforms are
+        // never maps.
+        if name == DATE_MAGIC
+            || name == GEOPOINT_MAGIC
+            || name == REFERENCE_MAGIC
+            || name == METADATA_MAGIC
+            || is_transform_magic(name)
+        {
+            Err(SerializationError::NotAMap)
+        } else {
+            value.serialize(self)
+        }
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Document>
+    where
+        T: ?Sized + Serialize,
+    {
+        let value = self.serializer.clone().serialize_newtype_variant(
+            name,
+            variant_index,
+            variant,
+            value,
+        )?;
+        self.variant_document(value)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(SerializationError::NotAMap)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<DocumentBuilder<NamedArrayBuilder<Value>>> {
+        Ok(DocumentBuilder {
+            builder: self.serializer.clone().serialize_tuple_variant(
+                name,
+                variant_index,
+                variant,
+                len,
+            )?,
+            document: self,
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<DocumentBuilder<KVMapBuilder<Value>>> {
+        Ok(DocumentBuilder {
+            builder: self.serializer.clone().serialize_map(len)?,
+            document: self,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<DocumentBuilder<MapBuilder<Value>>> {
+        Ok(DocumentBuilder {
+            builder: self.serializer.clone().serialize_struct(name, len)?,
+            document: self,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<DocumentBuilder<NamedMapBuilder<Value>>> {
+        Ok(DocumentBuilder {
+            builder: self.serializer.clone().serialize_struct_variant(
+                name,
+                variant_index,
+                variant,
+                len,
+            )?,
+            document: self,
+        })
+    }
+}
+
+impl SerializeStruct for DocumentBuilder<MapBuilder<Value>> {
+    type Ok = Document;
+
+    type Error = SerializationError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.builder.serialize_field(key, value)
+    }
+
+    fn end(self) -> Result<Document> {
+        Ok(self.document.document(self.builder.into_fields()))
+    }
+}
+
+impl SerializeMap for DocumentBuilder<KVMapBuilder<Value>> {
+    type Ok = Document;
+
+    type Error = SerializationError;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.builder.serialize_key(key)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.builder.serialize_value(value)
+    }
+
+    fn end(self) -> Result<Document> {
+        Ok(self.document.document(self.builder.into_fields()))
+    }
+}
+
+impl SerializeStructVariant for DocumentBuilder<NamedMapBuilder<Value>> {
+    type Ok = Document;
+
+    type Error = SerializationError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.builder.serialize_field(key, value)
+    }
+
+    fn end(self) -> Result<Document> {
+        let value = self.builder.end()?;
+        self.document.variant_document(value)
+    }
+}
+
+impl SerializeTupleVariant for DocumentBuilder<NamedArrayBuilder<Value>> {
+    type Ok = Document;
+
+    type Error = SerializationError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.builder.serialize_field(value)
+    }
+
+    fn end(self) -> Result<Document> {
+        let value = self.builder.end()?;
+        self.document.variant_document(value)
+    }
+}
@@ -1,3 +1,6 @@
+#[cfg(feature = "googapis")]
+use crate::validate::Violation;
+use crate::value_path::{PathSegment, ValuePath};
 use serde::ser;
 use std::fmt::Display;
 
@@ -5,9 +8,40 @@ use std::fmt::Display;
 pub enum SerializationError {
     Message(String),
     OutsideIntRange(u64),
+    /// A `u64` which `U64Overflow::Double` can't store exactly.
+    InexactDouble(u64),
     Unrepresentable(String),
     NotAMap,
     NonStringKey,
+    LatitudeOutOfRange(f64),
+    LongitudeOutOfRange(f64),
+    /// A document which violates Firestore's limits, when validation is
+    /// enabled.
+    #[cfg(feature = "googapis")]
+    InvalidDocument(Vec<Violation>),
+    /// An error which occurred at the given location inside the value.
+    AtPath(ValuePath, Box<SerializationError>),
+}
+
+impl SerializationError {
+    /// The location of the value which caused the error, if it was nested
+    /// inside the value being serialized.
+    pub fn path(&self) -> Option<&ValuePath> {
+        match self {
+            SerializationError::AtPath(path, _) => Some(path),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn at(self, segment: PathSegment) -> Self {
+        match self {
+            SerializationError::AtPath(mut path, error) => {
+                path.prepend(segment);
+                SerializationError::AtPath(path, error)
+            }
+            error => SerializationError::AtPath(ValuePath::new(segment), Box::new(error)),
+        }
+    }
 }
 
 impl Display for SerializationError {
@@ -15,9 +49,21 @@ impl Display for SerializationError {
         match &self {
             Self::Message(s) => writeln!(f, "{}", s),
             Self::OutsideIntRange(v) => writeln!(f, "Attempted to convert a u64 ({}) that falls outside of the i64 representable range.", v),
+            Self::InexactDouble(v) => writeln!(f, "Attempted to store a u64 ({}) as a double, which can't represent it exactly.", v),
             Self::Unrepresentable(t) => writeln!(f, "Attempted to convert an unrepresentable type: {}", t),
             Self::NonStringKey => writeln!(f, "Attempted to use a non-string key in a map."),
             Self::NotAMap => writeln!(f, "Only types that convert to a map can be stored in a Document."),
+            Self::LatitudeOutOfRange(v) => writeln!(f, "Latitude {} falls outside of the range [-90, 90].", v),
+            Self::LongitudeOutOfRange(v) => writeln!(f, "Longitude {} falls outside of the range [-180, 180].", v),
+            #[cfg(feature = "googapis")]
+            Self::InvalidDocument(violations) => {
+                writeln!(f, "The document violates Firestore's limits:")?;
+                for violation in violations {
+                    writeln!(f, "{}", violation)?;
+                }
+                Ok(())
+            }
+            Self::AtPath(path, error) => write!(f, "{}: {}", path, error),
         }
     }
 }
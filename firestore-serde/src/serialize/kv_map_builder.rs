@@ -1,26 +1,33 @@
 use super::error::{Result, SerializationError};
-use crate::firestore::{value::ValueType, MapValue, Value};
-use crate::ValueSerializer;
+use crate::value_path::PathSegment;
+use crate::{FirestoreValue, ValueKind, ValueSerializer};
 use serde::{ser::SerializeMap, Serialize};
 use std::collections::HashMap;
 
-#[derive(Default)]
-pub struct KVMapBuilder {
+pub struct KVMapBuilder<V> {
+    serializer: ValueSerializer<V>,
     key: Option<String>,
-    fields: HashMap<String, Value>,
+    fields: HashMap<String, V>,
 }
 
-impl KVMapBuilder {
-    pub fn with_capacity(capacity: usize) -> Self {
+impl<V> KVMapBuilder<V> {
+    pub fn with_capacity(serializer: ValueSerializer<V>, capacity: usize) -> Self {
         KVMapBuilder {
+            serializer,
             key: None,
             fields: HashMap::with_capacity(capacity),
         }
     }
+
+    /// The fields serialized so far, for builders which don't need them
+    /// wrapped in a `Value`.
+    pub fn into_fields(self) -> HashMap<String, V> {
+        self.fields
+    }
 }
 
-impl<'a> SerializeMap for KVMapBuilder {
-    type Ok = Value;
+impl<V: FirestoreValue> SerializeMap for KVMapBuilder<V> {
+    type Ok = V;
 
     type Error = SerializationError;
 
@@ -28,13 +35,10 @@ impl<'a> SerializeMap for KVMapBuilder {
     where
         T: Serialize,
     {
-        let result = key.serialize(ValueSerializer)?;
+        let result: V = key.serialize(self.serializer.clone())?;
 
-        if let Value {
-            value_type: Some(ValueType::StringValue(v)),
-        } = result
-        {
-            self.key = Some(v);
+        if matches!(result.kind(), ValueKind::String(_)) {
+            self.key = result.into_string();
         } else {
             return Err(SerializationError::NonStringKey);
         }
@@ -51,16 +55,20 @@ impl<'a> SerializeMap for KVMapBuilder {
             .take()
             .expect("Should never attempt to serialize a value without having seen a key.");
 
-        self.fields.insert(key, value.serialize(ValueSerializer)?);
+        let since = self.serializer.transforms.pending();
+        let value = match value.serialize(self.serializer.field_value()) {
+            Ok(value) => value,
+            Err(e) => return Err(e.at(PathSegment::Key(key))),
+        };
+
+        if self.serializer.transforms.store_field(since, &key, &value) {
+            self.fields.insert(key, value);
+        }
 
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok> {
-        Ok(Value {
-            value_type: Some(ValueType::MapValue(MapValue {
-                fields: self.fields,
-            })),
-        })
+        Ok(V::map(self.fields))
     }
 }
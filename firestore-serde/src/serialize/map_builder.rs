@@ -1,23 +1,31 @@
 use super::error::{Result, SerializationError};
-use crate::firestore::{value::ValueType, MapValue, Value};
-use crate::ValueSerializer;
+use crate::value_path::PathSegment;
+use crate::{FirestoreValue, ValueSerializer};
 use serde::{ser::SerializeStruct, Serialize};
 use std::collections::HashMap;
 
-pub struct MapBuilder {
-    fields: HashMap<String, Value>,
+pub struct MapBuilder<V> {
+    serializer: ValueSerializer<V>,
+    fields: HashMap<String, V>,
 }
 
-impl MapBuilder {
-    pub fn with_capacity(capacity: usize) -> Self {
+impl<V> MapBuilder<V> {
+    pub fn with_capacity(serializer: ValueSerializer<V>, capacity: usize) -> Self {
         MapBuilder {
+            serializer,
             fields: HashMap::with_capacity(capacity),
         }
     }
+
+    /// The fields serialized so far, for builders which don't need them
+    /// wrapped in a `Value`.
+    pub fn into_fields(self) -> HashMap<String, V> {
+        self.fields
+    }
 }
 
-impl<'a> SerializeStruct for MapBuilder {
-    type Ok = Value;
+impl<V: FirestoreValue> SerializeStruct for MapBuilder<V> {
+    type Ok = V;
 
     type Error = SerializationError;
 
@@ -25,22 +33,19 @@ impl<'a> SerializeStruct for MapBuilder {
     where
         T: Serialize,
     {
-        self.fields
-            .insert(key.to_string(), value.serialize(ValueSerializer)?);
+        let since = self.serializer.transforms.pending();
+        let value = value
+            .serialize(self.serializer.field_value())
+            .map_err(|e| e.at(PathSegment::Key(key.to_string())))?;
+
+        if self.serializer.transforms.store_field(since, key, &value) {
+            self.fields.insert(key.to_string(), value);
+        }
 
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok> {
-        Ok(Value {
-            value_type: Some(ValueType::MapValue(MapValue {
-                fields: self.fields,
-            })),
-        })
-    }
-
-    fn skip_field(&mut self, key: &'static str) -> Result<()> {
-        let _ = key;
-        Ok(())
+        Ok(V::map(self.fields))
     }
 }
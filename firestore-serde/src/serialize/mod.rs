@@ -1,144 +1,313 @@
+#[cfg(feature = "googapis")]
+pub use self::document_serializer::DocumentSerializer;
 pub use self::error::{Result, SerializationError};
+use self::geopoint_serializer::GeoPointSerializer;
 use self::timestamp_serializer::TimestampSerializer;
+#[cfg(feature = "googapis")]
+pub(crate) use self::wire_serializer::WireSerializer;
 use self::{
     array_builder::ArrayBuilder, kv_map_builder::KVMapBuilder, map_builder::MapBuilder,
     named_array_builder::NamedArrayBuilder, named_map_builder::NamedMapBuilder,
 };
-use crate::firestore::{value::ValueType, MapValue, Value};
-use crate::{DATE_MAGIC, TYPE, VALUE};
+use crate::transform::{is_transform_magic, Transforms};
+use crate::value_path::PathSegment;
+use crate::{
+    EnumRepresentation, FirestoreValue, SerializerConfig, ValueKind, DATE_MAGIC, GEOPOINT_MAGIC,
+    METADATA_MAGIC, REFERENCE_MAGIC,
+};
 use serde::{Serialize, Serializer};
 use std::convert::TryFrom;
+use std::marker::PhantomData;
 
 mod array_builder;
+#[cfg(feature = "googapis")]
+mod document_serializer;
 mod error;
+mod geopoint_serializer;
 mod kv_map_builder;
 mod map_builder;
 mod named_array_builder;
 mod named_map_builder;
 mod timestamp_serializer;
+#[cfg(feature = "googapis")]
+mod wire_serializer;
+
+/// Serializes Rust values into any implementation of `FirestoreValue`.
+pub struct ValueSerializer<V> {
+    config: SerializerConfig,
+    /// Whether the value being serialized is directly the value of a map
+    /// field, in which case `None` may be omitted rather than stored as null.
+    field: bool,
+    /// Where field transform markers are collected, if they are allowed.
+    transforms: Transforms,
+    value: PhantomData<fn() -> V>,
+}
+
+// Implemented by hand, since deriving would require `V` to implement the
+// trait as well.
+impl<V> Clone for ValueSerializer<V> {
+    fn clone(&self) -> Self {
+        ValueSerializer {
+            config: self.config,
+            field: self.field,
+            transforms: self.transforms.clone(),
+            value: PhantomData,
+        }
+    }
+}
+
+impl<V> Default for ValueSerializer<V> {
+    fn default() -> Self {
+        ValueSerializer::with_config(SerializerConfig::default())
+    }
+}
+
+impl<V: FirestoreValue> ValueSerializer<V> {
+    pub fn new() -> Self {
+        ValueSerializer::default()
+    }
+}
+
+impl<V> ValueSerializer<V> {
+    pub fn with_config(config: SerializerConfig) -> Self {
+        ValueSerializer {
+            config,
+            field: false,
+            transforms: Transforms::default(),
+            value: PhantomData,
+        }
+    }
+
+    /// Collects field transform markers into `transforms` instead of failing.
+    #[cfg(feature = "googapis")]
+    pub(crate) fn with_transforms(self, transforms: Transforms) -> Self {
+        ValueSerializer { transforms, ..self }
+    }
 
-pub struct ValueSerializer;
+    fn field_value(&self) -> Self {
+        ValueSerializer {
+            field: true,
+            ..self.clone()
+        }
+    }
+
+    fn nested(&self) -> Self {
+        ValueSerializer {
+            field: false,
+            ..self.clone()
+        }
+    }
 
-impl Serializer for ValueSerializer {
-    type Ok = Value;
+    /// The key which `variant_value` stores the contents of an enum variant
+    /// under, if they aren't merged into the enclosing map.
+    fn variant_key<'a>(&self, variant: &'a str, newtype: bool) -> Option<&'a str> {
+        match self.config.enum_representation {
+            EnumRepresentation::TypeValue {
+                value_key,
+                values_key,
+                ..
+            } => Some(if newtype { value_key } else { values_key }),
+            EnumRepresentation::ExternallyTagged => Some(variant),
+            EnumRepresentation::InternallyTagged { .. } => None,
+        }
+    }
+
+    /// Attributes an error in the contents of an enum variant to the key
+    /// which `variant_value` stores them under.
+    fn variant_error(
+        &self,
+        variant: &str,
+        newtype: bool,
+        error: SerializationError,
+    ) -> SerializationError {
+        match self.variant_key(variant, newtype) {
+            Some(key) => error.at(PathSegment::Key(key.to_string())),
+            None => error,
+        }
+    }
+
+    /// Places the field transforms found since `since` in the contents of
+    /// an enum variant under the key which `variant_value` stores them
+    /// under.
+    fn variant_transforms(&self, since: usize, variant: &str, newtype: bool) {
+        if let Some(key) = self.variant_key(variant, newtype) {
+            self.transforms.nest(since, key);
+        }
+    }
+}
+
+impl<V: FirestoreValue> ValueSerializer<V> {
+    /// Wraps the serialized contents of an enum variant according to the
+    /// enum representation. `newtype` distinguishes newtype variants from
+    /// tuple and struct variants.
+    fn variant_value(&self, variant: &str, contents: V, newtype: bool) -> Result<V> {
+        // Contents with no type (such as a field transform) are left out.
+        let contents = if matches!(contents.kind(), ValueKind::Unset) {
+            None
+        } else {
+            Some(contents)
+        };
+
+        let fields = match self.config.enum_representation {
+            EnumRepresentation::TypeValue {
+                type_key,
+                value_key,
+                values_key,
+            } => vec![(type_key.to_string(), variant.serialize(self.nested())?)]
+                .into_iter()
+                .chain(contents.map(|contents| {
+                    (
+                        if newtype { value_key } else { values_key }.to_string(),
+                        contents,
+                    )
+                }))
+                .collect(),
+            EnumRepresentation::ExternallyTagged => contents
+                .map(|contents| (variant.to_string(), contents))
+                .into_iter()
+                .collect(),
+            EnumRepresentation::InternallyTagged { tag_key } => {
+                if let Some(mut fields) = contents.and_then(V::into_map) {
+                    if fields.contains_key(tag_key) {
+                        return Err(SerializationError::Unrepresentable(format!(
+                            "field {} which conflicts with the enum tag",
+                            tag_key
+                        )));
+                    }
+
+                    fields.insert(tag_key.to_string(), variant.serialize(self.nested())?);
+                    fields
+                } else {
+                    return Err(SerializationError::Unrepresentable(
+                        "non-map variant of internally tagged enum".to_string(),
+                    ));
+                }
+            }
+        };
+
+        Ok(V::map(fields))
+    }
+}
+
+impl<V: FirestoreValue> Serializer for ValueSerializer<V> {
+    type Ok = V;
 
     type Error = SerializationError;
 
-    type SerializeMap = KVMapBuilder;
-    type SerializeSeq = ArrayBuilder;
-    type SerializeStruct = MapBuilder;
-    type SerializeStructVariant = NamedMapBuilder;
-    type SerializeTuple = ArrayBuilder;
-    type SerializeTupleStruct = ArrayBuilder;
-    type SerializeTupleVariant = NamedArrayBuilder;
-
-    fn serialize_bool(self, v: bool) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::BooleanValue(v)),
-        })
+    type SerializeMap = KVMapBuilder<V>;
+    type SerializeSeq = ArrayBuilder<V>;
+    type SerializeStruct = MapBuilder<V>;
+    type SerializeStructVariant = NamedMapBuilder<V>;
+    type SerializeTuple = ArrayBuilder<V>;
+    type SerializeTupleStruct = ArrayBuilder<V>;
+    type SerializeTupleVariant = NamedArrayBuilder<V>;
+
+    fn serialize_bool(self, v: bool) -> Result<V> {
+        Ok(V::boolean(v))
     }
 
-    fn serialize_i8(self, v: i8) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::IntegerValue(i64::from(v))),
-        })
+    fn serialize_i8(self, v: i8) -> Result<V> {
+        Ok(V::integer(i64::from(v)))
     }
 
-    fn serialize_i16(self, v: i16) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::IntegerValue(i64::from(v))),
-        })
+    fn serialize_i16(self, v: i16) -> Result<V> {
+        Ok(V::integer(i64::from(v)))
     }
 
-    fn serialize_i32(self, v: i32) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::IntegerValue(i64::from(v))),
-        })
+    fn serialize_i32(self, v: i32) -> Result<V> {
+        Ok(V::integer(i64::from(v)))
     }
 
-    fn serialize_i64(self, v: i64) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::IntegerValue(v)),
-        })
+    fn serialize_i64(self, v: i64) -> Result<V> {
+        Ok(V::integer(v))
     }
 
-    fn serialize_u8(self, v: u8) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::IntegerValue(i64::from(v))),
+    fn serialize_i128(self, v: i128) -> Result<V> {
+        Ok(match i64::try_from(v) {
+            Ok(v) => V::integer(v),
+            Err(_) => self.config.i128_fallback.store_i128(v),
         })
     }
 
-    fn serialize_u16(self, v: u16) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::IntegerValue(i64::from(v))),
-        })
+    fn serialize_u8(self, v: u8) -> Result<V> {
+        Ok(V::integer(i64::from(v)))
     }
 
-    fn serialize_u32(self, v: u32) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::IntegerValue(i64::from(v))),
-        })
+    fn serialize_u16(self, v: u16) -> Result<V> {
+        Ok(V::integer(i64::from(v)))
     }
 
-    fn serialize_u64(self, v: u64) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::IntegerValue(
-                i64::try_from(v).map_err(|_| SerializationError::OutsideIntRange(v))?,
-            )),
-        })
+    fn serialize_u32(self, v: u32) -> Result<V> {
+        Ok(V::integer(i64::from(v)))
     }
 
-    fn serialize_f32(self, v: f32) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::DoubleValue(f64::from(v))),
-        })
+    fn serialize_u64(self, v: u64) -> Result<V> {
+        match i64::try_from(v) {
+            Ok(v) => Ok(V::integer(v)),
+            Err(_) => self.config.u64_overflow.store(v),
+        }
     }
 
-    fn serialize_f64(self, v: f64) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::DoubleValue(v)),
+    fn serialize_u128(self, v: u128) -> Result<V> {
+        Ok(match i64::try_from(v) {
+            Ok(v) => V::integer(v),
+            Err(_) => self.config.i128_fallback.store_u128(v),
         })
     }
 
-    fn serialize_char(self, v: char) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::StringValue(v.to_string())),
-        })
+    fn serialize_f32(self, v: f32) -> Result<V> {
+        Ok(V::double(f64::from(v)))
     }
 
-    fn serialize_str(self, v: &str) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::StringValue(v.to_string())),
-        })
+    fn serialize_f64(self, v: f64) -> Result<V> {
+        Ok(V::double(v))
     }
 
-    fn serialize_bytes(self, v: &[u8]) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::BytesValue(v.into())),
-        })
+    fn serialize_char(self, v: char) -> Result<V> {
+        Ok(V::string(v.to_string()))
     }
 
-    fn serialize_none(self) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::NullValue(0)),
-        })
+    fn serialize_str(self, v: &str) -> Result<V> {
+        Ok(V::string(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<V> {
+        Ok(V::bytes(v.into()))
+    }
+
+    fn serialize_none(self) -> Result<V> {
+        if self.field && self.config.skip_none {
+            // A value with no type tells the enclosing map builder to omit
+            // the field entirely.
+            Ok(V::unset())
+        } else {
+            Ok(V::null())
+        }
     }
 
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Value>
+    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<V>
     where
         T: Serialize,
     {
         value.serialize(self)
     }
 
-    fn serialize_unit(self) -> Result<Value> {
-        Err(SerializationError::Unrepresentable("()".to_string()))
+    fn serialize_unit(self) -> Result<V> {
+        if self.config.unit_as_null {
+            self.nested().serialize_none()
+        } else {
+            Err(SerializationError::Unrepresentable("()".to_string()))
+        }
     }
 
-    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value> {
-        Err(SerializationError::Unrepresentable(
-            "unit_struct".to_string(),
-        ))
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<V> {
+        if self.config.unit_as_null {
+            self.nested().serialize_none()
+        } else {
+            Err(SerializationError::Unrepresentable(
+                "unit_struct".to_string(),
+            ))
+        }
     }
 
     fn serialize_unit_variant(
@@ -146,18 +315,58 @@ impl Serializer for ValueSerializer {
         _name: &'static str,
         _variant_index: u32,
         variant: &'static str,
-    ) -> Result<Value> {
-        Ok(Value {
-            value_type: Some(ValueType::StringValue(variant.to_string())),
-        })
+    ) -> Result<V> {
+        if let EnumRepresentation::InternallyTagged { .. } = self.config.enum_representation {
+            self.variant_value(variant, V::map(Default::default()), false)
+        } else {
+            Ok(V::string(variant.to_string()))
+        }
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(self, name: &'static str, value: &T) -> Result<Value>
+    fn serialize_newtype_struct<T: ?Sized>(self, name: &'static str, value: &T) -> Result<V>
     where
         T: Serialize,
     {
         if name == DATE_MAGIC {
-            value.serialize(TimestampSerializer)
+            let timestamp = value.serialize(TimestampSerializer)?;
+            Ok(V::timestamp(timestamp.seconds, timestamp.nanos))
+        } else if name == GEOPOINT_MAGIC {
+            Ok(V::geo_point(value.serialize(GeoPointSerializer)?))
+        } else if is_transform_magic(name) {
+            self.transforms.check_collecting(name)?;
+
+            let since = self.transforms.pending();
+            let operand = value.serialize(self.nested())?;
+            self.transforms.forbid(since, "a field transform")?;
+            self.transforms.record(name, &operand)?;
+
+            // The transform is sent separately, so the map builders omit its
+            // field.
+            Ok(V::unset())
+        } else if name == METADATA_MAGIC {
+            // Document metadata isn't stored as a field, so the map builders
+            // omit it. Anywhere else, it would leave a value with no type.
+            if self.field {
+                Ok(V::unset())
+            } else {
+                Err(SerializationError::Unrepresentable(
+                    "document metadata outside of a map field".to_string(),
+                ))
+            }
+        } else if name == REFERENCE_MAGIC {
+            let value = value.serialize(self)?;
+
+            if matches!(value.kind(), ValueKind::String(_)) {
+                Ok(V::reference(
+                    value
+                        .into_string()
+                        .expect("Already checked that the value is a string."),
+                ))
+            } else {
+                Err(SerializationError::Unrepresentable(
+                    "non-string reference".to_string(),
+                ))
+            }
         } else {
             value.serialize(self)
         }
@@ -169,38 +378,32 @@ impl Serializer for ValueSerializer {
         _variant_index: u32,
         variant: &'static str,
         value: &T,
-    ) -> Result<Value>
+    ) -> Result<V>
     where
         T: Serialize,
     {
-        Ok(Value {
-            value_type: Some(ValueType::MapValue(MapValue {
-                fields: vec![
-                    (TYPE.to_string(), variant.serialize(ValueSerializer)?),
-                    (VALUE.to_string(), value.serialize(ValueSerializer)?),
-                ]
-                .into_iter()
-                .collect(),
-            })),
-        })
-    }
+        let since = self.transforms.pending();
+        let contents = value
+            .serialize(self.nested())
+            .map_err(|e| self.variant_error(variant, true, e))?;
+        self.variant_transforms(since, variant, true);
 
-    fn serialize_seq(self, len: Option<usize>) -> Result<ArrayBuilder> {
-        let builder = if let Some(len) = len {
-            ArrayBuilder::with_capacity(len)
-        } else {
-            ArrayBuilder::default()
-        };
+        self.variant_value(variant, contents, true)
+    }
 
-        Ok(builder)
+    fn serialize_seq(self, len: Option<usize>) -> Result<ArrayBuilder<V>> {
+        Ok(ArrayBuilder::with_capacity(
+            self.nested(),
+            len.unwrap_or_default(),
+        ))
     }
 
-    fn serialize_tuple(self, len: usize) -> Result<ArrayBuilder> {
-        Ok(ArrayBuilder::with_capacity(len))
+    fn serialize_tuple(self, len: usize) -> Result<ArrayBuilder<V>> {
+        Ok(ArrayBuilder::with_capacity(self.nested(), len))
     }
 
-    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<ArrayBuilder> {
-        Ok(ArrayBuilder::with_capacity(len))
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<ArrayBuilder<V>> {
+        Ok(ArrayBuilder::with_capacity(self.nested(), len))
     }
 
     fn serialize_tuple_variant(
@@ -209,20 +412,23 @@ impl Serializer for ValueSerializer {
         _variant_index: u32,
         variant: &'static str,
         len: usize,
-    ) -> Result<NamedArrayBuilder> {
-        Ok(NamedArrayBuilder::with_capacity(variant.to_string(), len))
+    ) -> Result<NamedArrayBuilder<V>> {
+        Ok(NamedArrayBuilder::with_capacity(
+            self.nested(),
+            variant,
+            len,
+        ))
     }
 
-    fn serialize_map(self, len: Option<usize>) -> Result<KVMapBuilder> {
-        if let Some(len) = len {
-            Ok(KVMapBuilder::with_capacity(len))
-        } else {
-            Ok(KVMapBuilder::default())
-        }
+    fn serialize_map(self, len: Option<usize>) -> Result<KVMapBuilder<V>> {
+        Ok(KVMapBuilder::with_capacity(
+            self.nested(),
+            len.unwrap_or_default(),
+        ))
     }
 
-    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapBuilder> {
-        Ok(MapBuilder::with_capacity(len))
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapBuilder<V>> {
+        Ok(MapBuilder::with_capacity(self.nested(), len))
     }
 
     fn serialize_struct_variant(
@@ -231,7 +437,7 @@ impl Serializer for ValueSerializer {
         _variant_index: u32,
         variant: &'static str,
         len: usize,
-    ) -> Result<NamedMapBuilder> {
-        Ok(NamedMapBuilder::with_capacity(variant.to_string(), len))
+    ) -> Result<NamedMapBuilder<V>> {
+        Ok(NamedMapBuilder::with_capacity(self.nested(), variant, len))
     }
 }
@@ -1,27 +1,32 @@
-use crate::firestore::{value::ValueType, ArrayValue, MapValue, Value};
 use serde::{ser::SerializeTupleVariant, Serialize};
 
-use crate::ValueSerializer;
+use crate::{FirestoreValue, ValueSerializer};
 
 use super::error::{Result, SerializationError};
+use crate::value_path::PathSegment;
 
-use crate::{TYPE, VALUES};
-pub struct NamedArrayBuilder {
-    name: String,
-    values: Vec<Value>,
+pub struct NamedArrayBuilder<V> {
+    serializer: ValueSerializer<V>,
+    name: &'static str,
+    values: Vec<V>,
 }
 
-impl NamedArrayBuilder {
-    pub fn with_capacity(name: String, capacity: usize) -> Self {
+impl<V> NamedArrayBuilder<V> {
+    pub fn with_capacity(
+        serializer: ValueSerializer<V>,
+        name: &'static str,
+        capacity: usize,
+    ) -> Self {
         NamedArrayBuilder {
+            serializer,
             name,
             values: Vec::with_capacity(capacity),
         }
     }
 }
 
-impl<'a> SerializeTupleVariant for NamedArrayBuilder {
-    type Ok = Value;
+impl<V: FirestoreValue> SerializeTupleVariant for NamedArrayBuilder<V> {
+    type Ok = V;
 
     type Error = SerializationError;
 
@@ -29,28 +34,26 @@ impl<'a> SerializeTupleVariant for NamedArrayBuilder {
     where
         T: Serialize,
     {
-        self.values.push(value.serialize(ValueSerializer)?);
+        let since = self.serializer.transforms.pending();
+        let value = value
+            .serialize(self.serializer.clone())
+            .and_then(|value| {
+                self.serializer
+                    .transforms
+                    .forbid(since, "an array")
+                    .map(|_| value)
+            })
+            .map_err(|e| {
+                let e = e.at(PathSegment::Index(self.values.len()));
+                self.serializer.variant_error(self.name, false, e)
+            })?;
+        self.values.push(value);
 
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok> {
-        Ok(Value {
-            value_type: Some(ValueType::MapValue(MapValue {
-                fields: vec![
-                    (TYPE.to_string(), self.name.serialize(ValueSerializer)?),
-                    (
-                        VALUES.to_string(),
-                        Value {
-                            value_type: Some(ValueType::ArrayValue(ArrayValue {
-                                values: self.values,
-                            })),
-                        },
-                    ),
-                ]
-                .into_iter()
-                .collect(),
-            })),
-        })
+        self.serializer
+            .variant_value(self.name, V::array(self.values), false)
     }
 }
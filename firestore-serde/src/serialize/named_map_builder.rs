@@ -1,26 +1,34 @@
 use super::error::{Result, SerializationError};
-use crate::firestore::{value::ValueType, MapValue, Value};
-use crate::ValueSerializer;
-use crate::{TYPE, VALUES};
+use crate::value_path::PathSegment;
+use crate::{FirestoreValue, ValueSerializer};
 use serde::{ser::SerializeStructVariant, Serialize};
 use std::collections::HashMap;
 
-pub struct NamedMapBuilder {
-    name: String,
-    fields: HashMap<String, Value>,
+pub struct NamedMapBuilder<V> {
+    serializer: ValueSerializer<V>,
+    name: &'static str,
+    fields: HashMap<String, V>,
+    /// The number of field transforms found before the variant's fields.
+    since: usize,
 }
 
-impl NamedMapBuilder {
-    pub fn with_capacity(name: String, capacity: usize) -> Self {
+impl<V> NamedMapBuilder<V> {
+    pub fn with_capacity(
+        serializer: ValueSerializer<V>,
+        name: &'static str,
+        capacity: usize,
+    ) -> Self {
         NamedMapBuilder {
+            since: serializer.transforms.pending(),
+            serializer,
             name,
             fields: HashMap::with_capacity(capacity),
         }
     }
 }
 
-impl<'a> SerializeStructVariant for NamedMapBuilder {
-    type Ok = Value;
+impl<V: FirestoreValue> SerializeStructVariant for NamedMapBuilder<V> {
+    type Ok = V;
 
     type Error = SerializationError;
 
@@ -28,34 +36,26 @@ impl<'a> SerializeStructVariant for NamedMapBuilder {
     where
         T: Serialize,
     {
-        self.fields
-            .insert(key.to_string(), value.serialize(ValueSerializer)?);
+        let since = self.serializer.transforms.pending();
+        let value = value
+            .serialize(self.serializer.field_value())
+            .map_err(|e| {
+                let e = e.at(PathSegment::Key(key.to_string()));
+                self.serializer.variant_error(self.name, false, e)
+            })?;
+
+        if self.serializer.transforms.store_field(since, key, &value) {
+            self.fields.insert(key.to_string(), value);
+        }
 
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok> {
-        Ok(Value {
-            value_type: Some(ValueType::MapValue(MapValue {
-                fields: vec![
-                    (TYPE.to_string(), self.name.serialize(ValueSerializer)?),
-                    (
-                        VALUES.to_string(),
-                        Value {
-                            value_type: Some(ValueType::MapValue(MapValue {
-                                fields: self.fields,
-                            })),
-                        },
-                    ),
-                ]
-                .into_iter()
-                .collect(),
-            })),
-        })
-    }
+        self.serializer
+            .variant_transforms(self.since, self.name, false);
 
-    fn skip_field(&mut self, key: &'static str) -> Result<()> {
-        let _ = key;
-        Ok(())
+        self.serializer
+            .variant_value(self.name, V::map(self.fields), false)
     }
 }
@@ -1,6 +1,5 @@
 use super::SerializationError;
 use crate::serialize::Result;
-use crate::firestore::{value::ValueType, Value};
 use prost::Message;
 use prost_types::Timestamp;
 use serde::{Serialize, Serializer};
@@ -10,17 +9,17 @@ pub struct TimestampSerializer;
 const PANIC_MESSAGE: &str = "TimestampSerializer should never be called with anything but bytes.";
 
 impl Serializer for TimestampSerializer {
-    type Ok = Value;
+    type Ok = Timestamp;
 
     type Error = SerializationError;
 
-    type SerializeMap = serde::ser::Impossible<Value, SerializationError>;
-    type SerializeSeq = serde::ser::Impossible<Value, SerializationError>;
-    type SerializeStruct = serde::ser::Impossible<Value, SerializationError>;
-    type SerializeStructVariant = serde::ser::Impossible<Value, SerializationError>;
-    type SerializeTuple = serde::ser::Impossible<Value, SerializationError>;
-    type SerializeTupleStruct = serde::ser::Impossible<Value, SerializationError>;
-    type SerializeTupleVariant = serde::ser::Impossible<Value, SerializationError>;
+    type SerializeMap = serde::ser::Impossible<Timestamp, SerializationError>;
+    type SerializeSeq = serde::ser::Impossible<Timestamp, SerializationError>;
+    type SerializeStruct = serde::ser::Impossible<Timestamp, SerializationError>;
+    type SerializeStructVariant = serde::ser::Impossible<Timestamp, SerializationError>;
+    type SerializeTuple = serde::ser::Impossible<Timestamp, SerializationError>;
+    type SerializeTupleStruct = serde::ser::Impossible<Timestamp, SerializationError>;
+    type SerializeTupleVariant = serde::ser::Impossible<Timestamp, SerializationError>;
 
     fn serialize_bool(self, _v: bool) -> Result<Self::Ok> {
         panic!("{}", PANIC_MESSAGE);
@@ -75,13 +74,9 @@ impl Serializer for TimestampSerializer {
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok> {
-        let timestamp = Timestamp::decode(v).expect(
+        Ok(Timestamp::decode(v).expect(
             "Should always be able to decode timestamp, since we encoded it immediately before.",
-        );
-
-        Ok(Value {
-            value_type: Some(ValueType::TimestampValue(timestamp)),
-        })
+        ))
     }
 
     fn serialize_none(self) -> Result<Self::Ok> {
@@ -0,0 +1,729 @@
+use super::error::{Result, SerializationError};
+use super::{named_array_builder::NamedArrayBuilder, named_map_builder::NamedMapBuilder};
+use crate::firestore::{value::ValueType, Document, MapValue, Value};
+use crate::transform::is_transform_magic;
+use crate::value_path::PathSegment;
+use crate::wire::{
+    ARRAY_VALUES, DOCUMENT_FIELDS, ENTRY_KEY, ENTRY_VALUE, MAP_FIELDS, VALUE_ARRAY,
+    VALUE_BOOLEAN, VALUE_BYTES, VALUE_DOUBLE, VALUE_INTEGER, VALUE_MAP, VALUE_NULL, VALUE_STRING,
+};
+use crate::{ValueSerializer, DATE_MAGIC, GEOPOINT_MAGIC, METADATA_MAGIC, REFERENCE_MAGIC};
+use bytes::{BufMut, BytesMut};
+use prost::encoding::{encode_key, encode_varint, encoded_len_varint, WireType};
+use prost::Message;
+use serde::ser::{
+    SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant,
+};
+use serde::{Serialize, Serializer};
+use std::convert::TryFrom;
+
+/// A buffer which messages are written to before their lengths are known.
+/// The lengths are inserted in front of the messages once the value is
+/// complete, so that each byte is moved once rather than once for every
+/// message around it.
+pub(crate) struct WireBuf {
+    buf: BytesMut,
+    /// Where the length of each finished message is to be inserted, and the
+    /// length, in the order the messages were finished.
+    lengths: Vec<(usize, usize)>,
+    /// Where each unfinished message starts, and how many bytes the lengths
+    /// of the messages finished inside it will take up.
+    open: Vec<(usize, usize)>,
+}
+
+impl WireBuf {
+    fn new(buf: BytesMut) -> Self {
+        WireBuf {
+            buf,
+            lengths: Vec::new(),
+            open: Vec::new(),
+        }
+    }
+
+    /// Writes the key of a message field, returning where the message's
+    /// contents start.
+    fn begin_message(&mut self, tag: u32) -> usize {
+        encode_key(tag, WireType::LengthDelimited, &mut self.buf);
+
+        let start = self.buf.len();
+        self.open.push((start, 0));
+        start
+    }
+
+    /// Finishes the most recently begun message, recording its length.
+    fn end_message(&mut self) {
+        let (start, nested) = self
+            .open
+            .pop()
+            .expect("Should only end a message which has begun.");
+        let len = self.buf.len() - start + nested;
+        self.lengths.push((start, len));
+
+        if let Some((_, parent)) = self.open.last_mut() {
+            *parent += nested + encoded_len_varint(len as u64);
+        }
+    }
+
+    /// Removes everything written after `len`, including any messages begun
+    /// there.
+    fn truncate(&mut self, len: usize) {
+        self.buf.truncate(len);
+
+        while matches!(self.open.last(), Some(&(start, _)) if start > len) {
+            self.open.pop();
+        }
+
+        // Messages are finished inside out, so those which started after
+        // `len` are the last ones.
+        while matches!(self.lengths.last(), Some(&(start, _)) if start > len) {
+            self.lengths.pop();
+        }
+    }
+
+    /// Inserts the length of each message in front of it, moving the bytes
+    /// from the back of the buffer to the front.
+    fn finish(mut self) -> BytesMut {
+        self.lengths.sort_unstable_by_key(|&(start, _)| start);
+
+        let extra: usize = self
+            .lengths
+            .iter()
+            .map(|&(_, len)| encoded_len_varint(len as u64))
+            .sum();
+
+        let mut end = self.buf.len();
+        let mut dest = end + extra;
+        self.buf.resize(dest, 0);
+
+        for &(start, len) in self.lengths.iter().rev() {
+            dest -= end - start;
+            self.buf.copy_within(start..end, dest);
+
+            let prefix = encoded_len_varint(len as u64);
+            dest -= prefix;
+            let mut header = &mut self.buf[dest..dest + prefix];
+            encode_varint(len as u64, &mut header);
+
+            end = start;
+        }
+
+        self.buf
+    }
+}
+
+/// Writes the protobuf encoding of a `Value` (or, for a document, of the
+/// fields of a `Document`) to a buffer, without building the `Value`.
+///
+/// The result is whether anything was written: like a `Value` with no type
+/// from the `ValueSerializer`, `false` tells the enclosing map to omit the
+/// field.
+pub(crate) struct WireSerializer<'a> {
+    buf: &'a mut WireBuf,
+    serializer: ValueSerializer<Value>,
+    /// Whether the value is the root of a document, in which case it must be
+    /// a map and its entries are written as `Document.fields`.
+    document: bool,
+}
+
+impl<'a> WireSerializer<'a> {
+    /// Appends the encoding of `value` to `buf`, as a `Value` or, if
+    /// `document` is set, as the fields of a `Document`. On error, `buf` is
+    /// left as it was.
+    pub fn write<T>(
+        value: &T,
+        serializer: ValueSerializer<Value>,
+        document: bool,
+        buf: &mut BytesMut,
+    ) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let start = buf.len();
+        let mut wire = WireBuf::new(std::mem::take(buf));
+
+        let result = value.serialize(WireSerializer {
+            buf: &mut wire,
+            serializer,
+            document,
+        });
+
+        *buf = if result.is_ok() {
+            wire.finish()
+        } else {
+            wire.truncate(start);
+            wire.buf
+        };
+
+        result.map(|_| ())
+    }
+
+    fn new(buf: &'a mut WireBuf, serializer: ValueSerializer<Value>) -> Self {
+        WireSerializer {
+            buf,
+            serializer,
+            document: false,
+        }
+    }
+
+    /// Writes a scalar value, which can't be stored as a document.
+    fn scalar(self, write: impl FnOnce(&mut BytesMut)) -> Result<bool> {
+        if self.document {
+            return Err(SerializationError::NotAMap);
+        }
+
+        write(&mut self.buf.buf);
+        Ok(true)
+    }
+
+    /// Writes a value built by the `ValueSerializer`, for the rarer types
+    /// (enums, timestamps, geopoints, etc.) which aren't written directly.
+    fn fallback(self, value: Result<Value>) -> Result<bool> {
+        write_built(&mut self.buf.buf, value?, self.document)
+    }
+}
+
+/// Writes a value built by the `ValueSerializer`.
+fn write_built(buf: &mut BytesMut, value: Value, document: bool) -> Result<bool> {
+    if document {
+        if let Value {
+            value_type: Some(ValueType::MapValue(MapValue { fields })),
+        } = value
+        {
+            encode_message(
+                buf,
+                &Document {
+                    fields,
+                    ..Document::default()
+                },
+            );
+            Ok(true)
+        } else {
+            Err(SerializationError::NotAMap)
+        }
+    } else if value.value_type.is_some() {
+        encode_message(buf, &value);
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+fn encode_message(buf: &mut BytesMut, message: &impl Message) {
+    message
+        .encode(buf)
+        .expect("A BytesMut grows to fit the message.");
+}
+
+fn write_varint(buf: &mut BytesMut, tag: u32, v: u64) {
+    encode_key(tag, WireType::Varint, buf);
+    encode_varint(v, buf);
+}
+
+fn write_bytes(buf: &mut BytesMut, tag: u32, v: &[u8]) {
+    encode_key(tag, WireType::LengthDelimited, buf);
+    encode_varint(v.len() as u64, buf);
+    buf.put_slice(v);
+}
+
+impl<'a> Serializer for WireSerializer<'a> {
+    type Ok = bool;
+
+    type Error = SerializationError;
+
+    type SerializeMap = WireMapBuilder<'a>;
+    type SerializeSeq = WireArrayBuilder<'a>;
+    type SerializeStruct = WireMapBuilder<'a>;
+    type SerializeStructVariant = WireVariantBuilder<'a, NamedMapBuilder<Value>>;
+    type SerializeTuple = WireArrayBuilder<'a>;
+    type SerializeTupleStruct = WireArrayBuilder<'a>;
+    type SerializeTupleVariant = WireVariantBuilder<'a, NamedArrayBuilder<Value>>;
+
+    fn serialize_bool(self, v: bool) -> Result<bool> {
+        self.scalar(|buf| write_varint(buf, VALUE_BOOLEAN, u64::from(v)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<bool> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<bool> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<bool> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<bool> {
+        // Negative integers are encoded as their two's complement, like
+        // protobuf's int64.
+        self.scalar(|buf| write_varint(buf, VALUE_INTEGER, v as u64))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<bool> {
+        if let Ok(v) = i64::try_from(v) {
+            self.serialize_i64(v)
+        } else if self.document {
+            Err(SerializationError::NotAMap)
+        } else {
+            let value = self.serializer.clone().serialize_i128(v);
+            self.fallback(value)
+        }
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<bool> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<bool> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<bool> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<bool> {
+        if let Ok(v) = i64::try_from(v) {
+            self.serialize_i64(v)
+        } else if self.document {
+            Err(SerializationError::NotAMap)
+        } else {
+            let value = self.serializer.clone().serialize_u64(v);
+            self.fallback(value)
+        }
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<bool> {
+        if let Ok(v) = i64::try_from(v) {
+            self.serialize_i64(v)
+        } else if self.document {
+            Err(SerializationError::NotAMap)
+        } else {
+            let value = self.serializer.clone().serialize_u128(v);
+            self.fallback(value)
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<bool> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<bool> {
+        self.scalar(|buf| {
+            encode_key(VALUE_DOUBLE, WireType::SixtyFourBit, buf);
+            buf.put_f64_le(v);
+        })
+    }
+
+    fn serialize_char(self, v: char) -> Result<bool> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<bool> {
+        self.scalar(|buf| write_bytes(buf, VALUE_STRING, v.as_bytes()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<bool> {
+        self.scalar(|buf| write_bytes(buf, VALUE_BYTES, v))
+    }
+
+    fn serialize_none(self) -> Result<bool> {
+        if self.serializer.field && self.serializer.config.skip_none {
+            Ok(false)
+        } else {
+            self.scalar(|buf| write_varint(buf, VALUE_NULL, 0))
+        }
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<bool>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<bool> {
+        if self.document {
+            return Err(SerializationError::NotAMap);
+        }
+
+        let value = self.serializer.clone().serialize_unit();
+        self.fallback(value)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<bool> {
+        if self.document {
+            return Err(SerializationError::NotAMap);
+        }
+
+        let value = self.serializer.clone().serialize_unit_struct(name);
+        self.fallback(value)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<bool> {
+        let value = self
+            .serializer
+            .clone()
+            .serialize_unit_variant(name, variant_index, variant);
+        self.fallback(value)
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<bool>
+    where
+        T: ?Sized + Serialize,
+    {
+        if name == DATE_MAGIC
+            || name == GEOPOINT_MAGIC
+            || name == REFERENCE_MAGIC
+            || name == METADATA_MAGIC
+            || is_transform_magic(name)
+        {
+            if self.document {
+                return Err(SerializationError::NotAMap);
+            }
+
+            let value = self
+                .serializer
+                .clone()
+                .serialize_newtype_struct(name, value);
+            self.fallback(value)
+        } else {
+            value.serialize(self)
+        }
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<bool>
+    where
+        T: ?Sized + Serialize,
+    {
+        let value =
+            self.serializer
+                .clone()
+                .serialize_newtype_variant(name, variant_index, variant, value);
+        self.fallback(value)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<WireArrayBuilder<'a>> {
+        if self.document {
+            return Err(SerializationError::NotAMap);
+        }
+
+        self.buf.begin_message(VALUE_ARRAY);
+
+        Ok(WireArrayBuilder {
+            buf: self.buf,
+            serializer: self.serializer.nested(),
+            index: 0,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<WireArrayBuilder<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<WireArrayBuilder<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<WireVariantBuilder<'a, NamedArrayBuilder<Value>>> {
+        Ok(WireVariantBuilder {
+            builder: self
+                .serializer
+                .serialize_tuple_variant(name, variant_index, variant, len)?,
+            buf: self.buf,
+            document: self.document,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<WireMapBuilder<'a>> {
+        Ok(WireMapBuilder::new(self))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<WireMapBuilder<'a>> {
+        Ok(WireMapBuilder::new(self))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<WireVariantBuilder<'a, NamedMapBuilder<Value>>> {
+        Ok(WireVariantBuilder {
+            builder: self
+                .serializer
+                .serialize_struct_variant(name, variant_index, variant, len)?,
+            buf: self.buf,
+            document: self.document,
+        })
+    }
+}
+
+pub(crate) struct WireArrayBuilder<'a> {
+    buf: &'a mut WireBuf,
+    serializer: ValueSerializer<Value>,
+    index: usize,
+}
+
+impl<'a> WireArrayBuilder<'a> {
+    fn write_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        // An element which writes nothing (i.e. metadata) is left as an
+        // empty message, like a `Value` with no type.
+        self.buf.begin_message(ARRAY_VALUES);
+        value
+            .serialize(WireSerializer::new(self.buf, self.serializer.clone()))
+            .map_err(|e| e.at(PathSegment::Index(self.index)))?;
+        self.buf.end_message();
+
+        self.index += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Result<bool> {
+        self.buf.end_message();
+        Ok(true)
+    }
+}
+
+impl<'a> SerializeSeq for WireArrayBuilder<'a> {
+    type Ok = bool;
+
+    type Error = SerializationError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.write_element(value)
+    }
+
+    fn end(self) -> Result<bool> {
+        self.finish()
+    }
+}
+
+impl<'a> SerializeTuple for WireArrayBuilder<'a> {
+    type Ok = bool;
+
+    type Error = SerializationError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.write_element(value)
+    }
+
+    fn end(self) -> Result<bool> {
+        self.finish()
+    }
+}
+
+impl<'a> SerializeTupleStruct for WireArrayBuilder<'a> {
+    type Ok = bool;
+
+    type Error = SerializationError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.write_element(value)
+    }
+
+    fn end(self) -> Result<bool> {
+        self.finish()
+    }
+}
+
+pub(crate) struct WireMapBuilder<'a> {
+    buf: &'a mut WireBuf,
+    serializer: ValueSerializer<Value>,
+    /// The field number of each entry: `MapValue.fields` or
+    /// `Document.fields`.
+    entry_tag: u32,
+    /// Whether the entries are in a `MapValue`, rather than being the fields
+    /// of a document.
+    map_value: bool,
+    key: Option<String>,
+}
+
+impl<'a> WireMapBuilder<'a> {
+    fn new(serializer: WireSerializer<'a>) -> Self {
+        let entry_tag = if serializer.document {
+            DOCUMENT_FIELDS
+        } else {
+            serializer.buf.begin_message(VALUE_MAP);
+            MAP_FIELDS
+        };
+
+        WireMapBuilder {
+            buf: serializer.buf,
+            serializer: serializer.serializer.nested(),
+            entry_tag,
+            map_value: !serializer.document,
+            key: None,
+        }
+    }
+
+    fn write_entry<T>(&mut self, key: &str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let before = self.buf.buf.len();
+        self.buf.begin_message(self.entry_tag);
+
+        // Like prost, leave out the key when it is empty.
+        if !key.is_empty() {
+            write_bytes(&mut self.buf.buf, ENTRY_KEY, key.as_bytes());
+        }
+
+        self.buf.begin_message(ENTRY_VALUE);
+        let written = value
+            .serialize(WireSerializer::new(
+                self.buf,
+                self.serializer.field_value(),
+            ))
+            .map_err(|e| e.at(PathSegment::Key(key.to_string())))?;
+
+        if written {
+            self.buf.end_message();
+            self.buf.end_message();
+        } else {
+            self.buf.truncate(before);
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<bool> {
+        if self.map_value {
+            self.buf.end_message();
+        }
+
+        Ok(true)
+    }
+}
+
+impl<'a> SerializeStruct for WireMapBuilder<'a> {
+    type Ok = bool;
+
+    type Error = SerializationError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.write_entry(key, value)
+    }
+
+    fn end(self) -> Result<bool> {
+        self.finish()
+    }
+}
+
+impl<'a> SerializeMap for WireMapBuilder<'a> {
+    type Ok = bool;
+
+    type Error = SerializationError;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        if let Value {
+            value_type: Some(ValueType::StringValue(v)),
+        } = key.serialize(self.serializer.clone())?
+        {
+            self.key = Some(v);
+            Ok(())
+        } else {
+            Err(SerializationError::NonStringKey)
+        }
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let key = self
+            .key
+            .take()
+            .expect("Should never attempt to serialize a value without having seen a key.");
+
+        self.write_entry(&key, value)
+    }
+
+    fn end(self) -> Result<bool> {
+        self.finish()
+    }
+}
+
+/// Builds an enum variant with one of the `ValueSerializer`'s builders, and
+/// writes the result once it is complete.
+pub(crate) struct WireVariantBuilder<'a, B> {
+    buf: &'a mut WireBuf,
+    builder: B,
+    document: bool,
+}
+
+impl<'a> SerializeTupleVariant for WireVariantBuilder<'a, NamedArrayBuilder<Value>> {
+    type Ok = bool;
+
+    type Error = SerializationError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.builder.serialize_field(value)
+    }
+
+    fn end(self) -> Result<bool> {
+        write_built(&mut self.buf.buf, self.builder.end()?, self.document)
+    }
+}
+
+impl<'a> SerializeStructVariant for WireVariantBuilder<'a, NamedMapBuilder<Value>> {
+    type Ok = bool;
+
+    type Error = SerializationError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.builder.serialize_field(key, value)
+    }
+
+    fn end(self) -> Result<bool> {
+        write_built(&mut self.buf.buf, self.builder.end()?, self.document)
+    }
+}
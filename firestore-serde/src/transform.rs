@@ -0,0 +1,517 @@
+#[cfg(feature = "googapis")]
+use crate::field_path::format_segments;
+#[cfg(feature = "googapis")]
+use crate::firestore::{
+    document_transform::{field_transform::TransformType, FieldTransform},
+    value::ValueType,
+    Value,
+};
+use crate::serialize::{Result, SerializationError};
+use crate::{FirestoreValue, ValueKind};
+use serde::{Serialize, Serializer};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+pub(crate) const SERVER_TIMESTAMP_MAGIC: &str = "$ServerTimestamp";
+pub(crate) const INCREMENT_MAGIC: &str = "$Increment";
+pub(crate) const MAXIMUM_MAGIC: &str = "$Maximum";
+pub(crate) const MINIMUM_MAGIC: &str = "$Minimum";
+pub(crate) const ARRAY_UNION_MAGIC: &str = "$ArrayUnion";
+pub(crate) const ARRAY_REMOVE_MAGIC: &str = "$ArrayRemove";
+
+/// `ServerValue::RequestTime`, the value which `ServerTimestamp` sets.
+const REQUEST_TIME: i32 = 1;
+
+/// Sets the field to the time at which the server processed the write.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ServerTimestamp;
+
+/// Adds the given value to the field's current value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Increment<T>(pub T);
+
+/// Sets the field to the maximum of its current value and the given value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Maximum<T>(pub T);
+
+/// Sets the field to the minimum of its current value and the given value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Minimum<T>(pub T);
+
+/// Appends each of the given elements which is not already in the array.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArrayUnion<T>(pub Vec<T>);
+
+/// Removes every instance of each of the given elements from the array.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArrayRemove<T>(pub Vec<T>);
+
+impl Serialize for ServerTimestamp {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(SERVER_TIMESTAMP_MAGIC, &REQUEST_TIME)
+    }
+}
+
+impl<T: Serialize> Serialize for Increment<T> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(INCREMENT_MAGIC, &self.0)
+    }
+}
+
+impl<T: Serialize> Serialize for Maximum<T> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(MAXIMUM_MAGIC, &self.0)
+    }
+}
+
+impl<T: Serialize> Serialize for Minimum<T> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(MINIMUM_MAGIC, &self.0)
+    }
+}
+
+impl<T: Serialize> Serialize for ArrayUnion<T> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(ARRAY_UNION_MAGIC, &self.0)
+    }
+}
+
+impl<T: Serialize> Serialize for ArrayRemove<T> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(ARRAY_REMOVE_MAGIC, &self.0)
+    }
+}
+
+pub(crate) fn is_transform_magic(name: &str) -> bool {
+    matches!(
+        name,
+        SERVER_TIMESTAMP_MAGIC
+            | INCREMENT_MAGIC
+            | MAXIMUM_MAGIC
+            | MINIMUM_MAGIC
+            | ARRAY_UNION_MAGIC
+            | ARRAY_REMOVE_MAGIC
+    )
+}
+
+/// A transform found during serialization. `keys` leads to its field, but
+/// is stored innermost first, since keys are added as serialization passes
+/// back out of each map.
+struct PendingTransform {
+    keys: Vec<String>,
+    #[cfg(feature = "googapis")]
+    transform_type: TransformType,
+}
+
+/// The field transforms found while serializing a document. The
+/// `ValueSerializer` carries it into every builder and nested serializer,
+/// and its clones all add to the same list. The default collects nothing,
+/// so transform markers fail outside of `to_document_with_transforms`.
+#[derive(Clone, Default)]
+pub(crate) struct Transforms(Option<Arc<Mutex<Vec<PendingTransform>>>>);
+
+impl Transforms {
+    /// Transforms which accept transform markers and collect them.
+    #[cfg(feature = "googapis")]
+    pub fn collecting() -> Self {
+        Transforms(Some(Arc::default()))
+    }
+
+    /// The transforms found so far, or `None` if they aren't collected.
+    fn collected(&self) -> Option<MutexGuard<'_, Vec<PendingTransform>>> {
+        // A panic while a transform was recorded can't leave the list
+        // inconsistent, so a poisoned lock is still usable.
+        self.0
+            .as_ref()
+            .map(|collected| collected.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Takes the `FieldTransform`s found so far, with their field paths
+    /// filled in.
+    #[cfg(feature = "googapis")]
+    pub fn take(&self) -> Vec<FieldTransform> {
+        let pending = self
+            .collected()
+            .map(|mut collected| std::mem::take(&mut *collected))
+            .unwrap_or_default();
+
+        pending
+            .into_iter()
+            .map(
+                |PendingTransform {
+                     mut keys,
+                     transform_type,
+                 }| {
+                    keys.reverse();
+
+                    FieldTransform {
+                        field_path: format_segments(&keys),
+                        transform_type: Some(transform_type),
+                    }
+                },
+            )
+            .collect()
+    }
+
+    /// The number of transforms found so far. Builders compare it before
+    /// and after serializing a value to find the transforms inside that
+    /// value.
+    pub fn pending(&self) -> usize {
+        self.collected().map_or(0, |collected| collected.len())
+    }
+
+    /// Fails unless transform markers are being collected.
+    pub fn check_collecting(&self, name: &str) -> Result<()> {
+        if self.0.is_some() {
+            Ok(())
+        } else {
+            Err(SerializationError::Unrepresentable(format!(
+                "{} outside of to_document_with_transforms",
+                name
+            )))
+        }
+    }
+
+    /// Records the transform for a marker, given its magic name and
+    /// serialized operand. The marker's field is omitted from the value.
+    #[cfg(feature = "googapis")]
+    pub fn record<V: FirestoreValue>(&self, name: &str, operand: &V) -> Result<()> {
+        let operand: Value = crate::value_model::convert(operand);
+
+        let transform_type = match (name, operand.value_type) {
+            (SERVER_TIMESTAMP_MAGIC, Some(ValueType::IntegerValue(v))) => {
+                TransformType::SetToServerValue(v as i32)
+            }
+            (INCREMENT_MAGIC, value_type) => TransformType::Increment(Value { value_type }),
+            (MAXIMUM_MAGIC, value_type) => TransformType::Maximum(Value { value_type }),
+            (MINIMUM_MAGIC, value_type) => TransformType::Minimum(Value { value_type }),
+            (ARRAY_UNION_MAGIC, Some(ValueType::ArrayValue(array))) => {
+                TransformType::AppendMissingElements(array)
+            }
+            (ARRAY_REMOVE_MAGIC, Some(ValueType::ArrayValue(array))) => {
+                TransformType::RemoveAllFromArray(array)
+            }
+            _ => return Err(SerializationError::Unrepresentable(name.to_string())),
+        };
+
+        if let Some(mut collected) = self.collected() {
+            collected.push(PendingTransform {
+                keys: Vec::new(),
+                transform_type,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Without `googapis`, nothing collects transforms, so
+    /// `check_collecting` always fails before a transform is recorded.
+    #[cfg(not(feature = "googapis"))]
+    pub fn record<V: FirestoreValue>(&self, name: &str, _operand: &V) -> Result<()> {
+        self.check_collecting(name)
+    }
+
+    /// Places the transforms found since `since` under `key`.
+    pub fn nest(&self, since: usize, key: &str) {
+        if let Some(mut collected) = self.collected() {
+            for pending in collected.iter_mut().skip(since) {
+                pending.keys.push(key.to_string());
+            }
+        }
+    }
+
+    /// Places the transforms found since `since` under `key`, and returns
+    /// whether `value` should be stored in the map as that key's value.
+    /// Unset values are omitted, as are maps left empty because all of
+    /// their fields were transforms.
+    pub fn store_field<V: FirestoreValue>(&self, since: usize, key: &str, value: &V) -> bool {
+        self.nest(since, key);
+
+        match value.kind() {
+            ValueKind::Unset => false,
+            ValueKind::Map(mut fields) => fields.next().is_some() || self.pending() == since,
+            _ => true,
+        }
+    }
+
+    /// Fails if any transforms were found since `since`, which were inside
+    /// of `container` rather than directly in a map field.
+    pub fn forbid(&self, since: usize, container: &str) -> Result<()> {
+        if self.pending() == since {
+            Ok(())
+        } else {
+            Err(SerializationError::Unrepresentable(format!(
+                "field transform inside {}",
+                container
+            )))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "googapis"))]
+mod test {
+    use super::*;
+    use crate::firestore::document_transform::field_transform::ServerValue;
+    use crate::firestore::{ArrayValue, MapValue};
+    use crate::{to_document, to_document_with_transforms, DocumentSerializer, SerializerConfig};
+    use std::collections::HashMap;
+
+    #[derive(Serialize)]
+    struct Counters {
+        visits: Increment<i64>,
+        #[serde(rename = "last-seen")]
+        last_seen: ServerTimestamp,
+    }
+
+    #[derive(Serialize)]
+    struct Page {
+        title: String,
+        tags: ArrayUnion<String>,
+        counters: Counters,
+    }
+
+    fn transform_map(transforms: Vec<FieldTransform>) -> HashMap<String, TransformType> {
+        transforms
+            .into_iter()
+            .map(|t| (t.field_path, t.transform_type.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_extract_transforms() {
+        let page = Page {
+            title: "Home".to_string(),
+            tags: ArrayUnion(vec!["new".to_string()]),
+            counters: Counters {
+                visits: Increment(1),
+                last_seen: ServerTimestamp,
+            },
+        };
+
+        let (document, transforms) =
+            to_document_with_transforms(&page, SerializerConfig::new()).unwrap();
+
+        // `counters` only contained transforms, so it is left out rather
+        // than overwritten with an empty map.
+        assert_eq!(
+            vec!["title"],
+            document.fields.keys().collect::<Vec<&String>>()
+        );
+
+        let transforms = transform_map(transforms);
+        assert_eq!(3, transforms.len());
+        assert_eq!(
+            TransformType::Increment(Value {
+                value_type: Some(ValueType::IntegerValue(1))
+            }),
+            transforms["counters.visits"]
+        );
+        assert_eq!(
+            TransformType::SetToServerValue(ServerValue::RequestTime as i32),
+            transforms["counters.`last-seen`"]
+        );
+        assert_eq!(
+            TransformType::AppendMissingElements(ArrayValue {
+                values: vec![Value {
+                    value_type: Some(ValueType::StringValue("new".to_string()))
+                }]
+            }),
+            transforms["tags"]
+        );
+    }
+
+    #[test]
+    fn test_transform_in_array() {
+        let value: HashMap<&str, Vec<HashMap<&str, Maximum<i64>>>> = vec![(
+            "scores",
+            vec![vec![("high", Maximum(3))].into_iter().collect()],
+        )]
+        .into_iter()
+        .collect();
+
+        let error = to_document_with_transforms(&value, SerializerConfig::new()).unwrap_err();
+
+        assert_eq!("scores.0", error.path().unwrap().to_string());
+        assert!(matches!(
+            error,
+            SerializationError::AtPath(_, inner)
+                if *inner == SerializationError::Unrepresentable(
+                    "field transform inside an array".to_string()
+                )
+        ));
+    }
+
+    #[test]
+    fn test_transforms_in_variants() {
+        #[derive(Serialize)]
+        enum Change {
+            Set(i64),
+            Add(Increment<i64>),
+            Touch { at: ServerTimestamp, by: String },
+        }
+
+        let changes: HashMap<&str, Change> = vec![
+            ("a", Change::Set(1)),
+            ("b", Change::Add(Increment(2))),
+            (
+                "c",
+                Change::Touch {
+                    at: ServerTimestamp,
+                    by: "me".to_string(),
+                },
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let (document, transforms) =
+            to_document_with_transforms(&changes, SerializerConfig::new()).unwrap();
+
+        // The variant's tag is kept, without a value.
+        assert_eq!(
+            Some(&Value {
+                value_type: Some(ValueType::MapValue(MapValue {
+                    fields: vec![(
+                        "type".to_string(),
+                        Value {
+                            value_type: Some(ValueType::StringValue("Add".to_string()))
+                        }
+                    )]
+                    .into_iter()
+                    .collect()
+                }))
+            }),
+            document.fields.get("b")
+        );
+
+        let transforms = transform_map(transforms);
+        assert_eq!(2, transforms.len());
+        assert!(transforms.contains_key("b.value"));
+        assert!(transforms.contains_key("c.values.at"));
+    }
+
+    #[test]
+    fn test_user_data_is_not_a_transform() {
+        // A map which looks like the placeholders of earlier versions is
+        // stored as it is.
+        let value: HashMap<&str, HashMap<&str, serde_bytes::ByteBuf>> = vec![(
+            "data",
+            vec![(
+                "$FieldTransform",
+                serde_bytes::ByteBuf::from(vec![0xff, 0x01]),
+            )]
+            .into_iter()
+            .collect(),
+        )]
+        .into_iter()
+        .collect();
+
+        let (document, transforms) =
+            to_document_with_transforms(&value, SerializerConfig::new()).unwrap();
+
+        assert!(transforms.is_empty());
+        assert_eq!(to_document(&value).unwrap(), document);
+    }
+
+    #[test]
+    fn test_empty_maps_are_kept() {
+        #[derive(Serialize)]
+        struct Stats {
+            tags: HashMap<String, String>,
+            visits: Increment<i64>,
+        }
+
+        let value: HashMap<&str, Stats> = vec![(
+            "stats",
+            Stats {
+                tags: HashMap::new(),
+                visits: Increment(1),
+            },
+        )]
+        .into_iter()
+        .collect();
+
+        let (document, transforms) =
+            to_document_with_transforms(&value, SerializerConfig::new()).unwrap();
+
+        assert_eq!(
+            Some(&Value {
+                value_type: Some(ValueType::MapValue(MapValue {
+                    fields: vec![(
+                        "tags".to_string(),
+                        Value {
+                            value_type: Some(ValueType::MapValue(MapValue::default()))
+                        }
+                    )]
+                    .into_iter()
+                    .collect()
+                }))
+            }),
+            document.fields.get("stats")
+        );
+        assert_eq!("stats.visits", transforms[0].field_path);
+    }
+
+    #[test]
+    fn test_transforms_follow_the_serializer() {
+        let transforms = Transforms::collecting();
+        let serializer = DocumentSerializer::with_config(SerializerConfig::new())
+            .with_transforms(transforms.clone());
+
+        // The transforms are collected by the serializer, not by the thread
+        // which serializes.
+        let document = std::thread::spawn(move || {
+            Counters {
+                visits: Increment(1),
+                last_seen: ServerTimestamp,
+            }
+            .serialize(serializer)
+        })
+        .join()
+        .unwrap()
+        .unwrap();
+
+        let mut paths: Vec<String> = transforms
+            .take()
+            .into_iter()
+            .map(|t| t.field_path)
+            .collect();
+        paths.sort();
+
+        assert!(document.fields.is_empty());
+        assert_eq!(vec!["`last-seen`", "visits"], paths);
+    }
+
+    #[test]
+    fn test_transform_without_collecting() {
+        let value: HashMap<&str, Minimum<i64>> = vec![("low", Minimum(3))].into_iter().collect();
+
+        let error = to_document(&value).unwrap_err();
+
+        assert_eq!("low", error.path().unwrap().to_string());
+        assert!(matches!(
+            error,
+            SerializationError::AtPath(_, inner)
+                if *inner == SerializationError::Unrepresentable(
+                    "$Minimum outside of to_document_with_transforms".to_string()
+                )
+        ));
+    }
+}
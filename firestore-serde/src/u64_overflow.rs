@@ -0,0 +1,178 @@
+use crate::serialize::SerializationError;
+use crate::{FirestoreValue, ValueKind};
+use std::convert::TryFrom;
+
+/// 2^64, the smallest double above every `u64`.
+const U64_LIMIT: f64 = 18_446_744_073_709_551_616.0;
+
+/// Determines how `u64` values above `i64::MAX`, which don't fit in
+/// Firestore's signed 64-bit integers, are stored. Smaller values are always
+/// stored as an `IntegerValue`.
+///
+/// The `DeserializerConfig` must use the same policy to read the values back
+/// into a `u64`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum U64Overflow {
+    /// Fail with `SerializationError::OutsideIntRange`. This is the default.
+    #[default]
+    Error,
+    /// Store a `StringValue` of the decimal digits.
+    String,
+    /// Store a `DoubleValue`, failing with `SerializationError::InexactDouble`
+    /// unless the double represents the value exactly. Doubles this large are
+    /// multiples of 2048.
+    Double,
+    /// Store an 8-byte, big-endian `BytesValue`.
+    Bytes,
+    /// Store the `IntegerValue` with the same bit pattern, i.e. wrap around
+    /// to negative numbers. Such values no longer sort correctly in queries.
+    Wrap,
+}
+
+impl U64Overflow {
+    /// Stores `v`, which is above `i64::MAX`.
+    pub(crate) fn store<V: FirestoreValue>(self, v: u64) -> Result<V, SerializationError> {
+        match self {
+            U64Overflow::Error => Err(SerializationError::OutsideIntRange(v)),
+            U64Overflow::String => Ok(V::string(v.to_string())),
+            U64Overflow::Double => {
+                let d = v as f64;
+
+                if d as u128 == u128::from(v) {
+                    Ok(V::double(d))
+                } else {
+                    Err(SerializationError::InexactDouble(v))
+                }
+            }
+            U64Overflow::Bytes => Ok(V::bytes(v.to_be_bytes().to_vec())),
+            U64Overflow::Wrap => Ok(V::integer(v as i64)),
+        }
+    }
+
+    /// Reads a value stored by `store`, or returns `None` if `value` isn't
+    /// in this policy's form. Integers are left to the caller.
+    pub(crate) fn load<V>(self, value: &ValueKind<'_, V>) -> Option<u64> {
+        match (self, value) {
+            (U64Overflow::String, ValueKind::String(s)) => s.parse().ok(),
+            (U64Overflow::Double, ValueKind::Double(d)) => {
+                if *d >= 0.0 && *d < U64_LIMIT && d.fract() == 0.0 {
+                    Some(*d as u64)
+                } else {
+                    None
+                }
+            }
+            (U64Overflow::Bytes, ValueKind::Bytes(bytes)) => {
+                <[u8; 8]>::try_from(*bytes).ok().map(u64::from_be_bytes)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "googapis"))]
+mod test {
+    use super::*;
+    use crate::deserialize::DeserializationError;
+    use crate::firestore::{value::ValueType, Value};
+    use crate::{from_grpc_value_with, to_grpc_value_with, DeserializerConfig, SerializerConfig};
+
+    fn round_trip(policy: U64Overflow, v: u64) -> (Value, u64) {
+        let value =
+            to_grpc_value_with(&v, SerializerConfig::new().with_u64_overflow(policy)).unwrap();
+        let result =
+            from_grpc_value_with(&value, DeserializerConfig::new().with_u64_overflow(policy))
+                .unwrap();
+
+        (value, result)
+    }
+
+    fn value(value_type: ValueType) -> Value {
+        Value {
+            value_type: Some(value_type),
+        }
+    }
+
+    #[test]
+    fn test_small_values_are_integers() {
+        for policy in [
+            U64Overflow::Error,
+            U64Overflow::String,
+            U64Overflow::Double,
+            U64Overflow::Bytes,
+            U64Overflow::Wrap,
+        ] {
+            assert_eq!(
+                (value(ValueType::IntegerValue(i64::MAX)), i64::MAX as u64),
+                round_trip(policy, i64::MAX as u64)
+            );
+        }
+    }
+
+    #[test]
+    fn test_policies() {
+        assert_eq!(
+            Err(SerializationError::OutsideIntRange(u64::MAX)),
+            to_grpc_value_with(&u64::MAX, SerializerConfig::new())
+        );
+
+        assert_eq!(
+            (
+                value(ValueType::StringValue("18446744073709551615".to_string())),
+                u64::MAX
+            ),
+            round_trip(U64Overflow::String, u64::MAX)
+        );
+
+        assert_eq!(
+            (
+                value(ValueType::DoubleValue(9_223_372_036_854_777_856.0)),
+                (1 << 63) + 2048
+            ),
+            round_trip(U64Overflow::Double, (1 << 63) + 2048)
+        );
+
+        assert_eq!(
+            (value(ValueType::BytesValue(vec![0xff; 8])), u64::MAX),
+            round_trip(U64Overflow::Bytes, u64::MAX)
+        );
+
+        assert_eq!(
+            (value(ValueType::IntegerValue(-1)), u64::MAX),
+            round_trip(U64Overflow::Wrap, u64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_inexact_double() {
+        assert_eq!(
+            Err(SerializationError::InexactDouble(u64::MAX)),
+            to_grpc_value_with(
+                &u64::MAX,
+                SerializerConfig::new().with_u64_overflow(U64Overflow::Double)
+            )
+        );
+    }
+
+    #[test]
+    fn test_wrong_form() {
+        let config = DeserializerConfig::new().with_u64_overflow(U64Overflow::Bytes);
+
+        assert_eq!(
+            Err(DeserializationError::WrongType("u64", "bytes")),
+            from_grpc_value_with::<u64>(&value(ValueType::BytesValue(vec![1, 2])), config)
+        );
+
+        // Without `Wrap`, negative integers are out of range.
+        assert_eq!(
+            Err(DeserializationError::IntRange("u64", "-1".to_string())),
+            from_grpc_value_with::<u64>(&value(ValueType::IntegerValue(-1)), config)
+        );
+
+        // Values are only read in the configured form.
+        assert!(from_grpc_value_with::<u64>(
+            &value(ValueType::StringValue("1".to_string())),
+            config
+        )
+        .is_err());
+    }
+}
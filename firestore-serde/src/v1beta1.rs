@@ -0,0 +1,442 @@
+//! Conversions to and from the `google.firestore.v1beta1` protocol buffers,
+//! available when both the `google-firestore-v1` and
+//! `google-firestore-v1beta1` features are enabled.
+//!
+//! In that case, the rest of the crate uses the `v1` types, and this module
+//! provides the same entry points for the `v1beta1` types. Values are
+//! serialized and deserialized directly, since the `v1beta1` `Value`
+//! implements `FirestoreValue`. Both versions define `Document`, `Value` and
+//! the field transforms identically, so documents are serialized as `v1` and
+//! then moved field by field into their `v1beta1` counterparts (or the
+//! reverse, when deserializing).
+//!
+//! The two versions also share their protobuf encoding, so `write_value`,
+//! `write_document`, `read_value` and `read_document` (and their `_with`
+//! counterparts) in the crate root work unchanged on `v1beta1` messages.
+//! There is no `from_document_ref`: borrow from an encoded document with
+//! `read_document` instead.
+
+use crate::firestore;
+use crate::{DeserializerConfig, SerializerConfig};
+use googapis::google::firestore::v1beta1::{
+    document_transform::{field_transform::TransformType, FieldTransform},
+    value::ValueType,
+    ArrayValue, Document, DocumentMask, MapValue, Value,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+crate::value_model::impl_firestore_value!(googapis::google::firestore::v1beta1);
+
+pub fn to_grpc_value<T>(value: &T) -> crate::serialize::Result<Value>
+where
+    T: Serialize,
+{
+    to_grpc_value_with(value, SerializerConfig::default())
+}
+
+pub fn to_grpc_value_with<T>(value: &T, config: SerializerConfig) -> crate::serialize::Result<Value>
+where
+    T: Serialize,
+{
+    crate::value_model::to_value_with(value, config)
+}
+
+pub fn from_grpc_value<T>(value: &Value) -> crate::deserialize::Result<T>
+where
+    T: DeserializeOwned,
+{
+    from_grpc_value_with(value, DeserializerConfig::default())
+}
+
+pub fn from_grpc_value_with<T>(
+    value: &Value,
+    config: DeserializerConfig,
+) -> crate::deserialize::Result<T>
+where
+    T: DeserializeOwned,
+{
+    crate::value_model::from_value_with(value, config)
+}
+
+/// Like `from_grpc_value`, but allows `&str` and `&[u8]` fields of the result
+/// to borrow directly from `value`.
+pub fn from_grpc_value_ref<'de, T>(value: &'de Value) -> crate::deserialize::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    from_grpc_value_ref_with(value, DeserializerConfig::default())
+}
+
+pub fn from_grpc_value_ref_with<'de, T>(
+    value: &'de Value,
+    config: DeserializerConfig,
+) -> crate::deserialize::Result<T>
+where
+    T: Deserialize<'de>,
+{
+    crate::value_model::from_value_ref_with(value, config)
+}
+
+pub fn to_document<T>(value: &T) -> crate::serialize::Result<Document>
+where
+    T: Serialize,
+{
+    to_document_with(value, SerializerConfig::default())
+}
+
+pub fn to_document_with<T>(
+    value: &T,
+    config: SerializerConfig,
+) -> crate::serialize::Result<Document>
+where
+    T: Serialize,
+{
+    Ok(from_v1_document(crate::to_document_with(value, config)?))
+}
+
+/// See `crate::to_document_with_transforms`.
+pub fn to_document_with_transforms<T>(
+    value: &T,
+    config: SerializerConfig,
+) -> crate::serialize::Result<(Document, Vec<FieldTransform>)>
+where
+    T: Serialize,
+{
+    let (document, transforms) = crate::to_document_with_transforms(value, config)?;
+
+    Ok((
+        from_v1_document(document),
+        transforms
+            .into_iter()
+            .map(from_v1_field_transform)
+            .collect(),
+    ))
+}
+
+/// See `crate::to_document_mask`.
+pub fn to_document_mask<T>(value: &T, expand_maps: bool) -> crate::serialize::Result<DocumentMask>
+where
+    T: Serialize,
+{
+    to_document_mask_with(value, SerializerConfig::default(), expand_maps)
+}
+
+pub fn to_document_mask_with<T>(
+    value: &T,
+    config: SerializerConfig,
+    expand_maps: bool,
+) -> crate::serialize::Result<DocumentMask>
+where
+    T: Serialize,
+{
+    let mask = crate::to_document_mask_with(value, config, expand_maps)?;
+
+    Ok(DocumentMask {
+        field_paths: mask.field_paths,
+    })
+}
+
+pub fn from_document<T>(document: Document) -> crate::deserialize::Result<T>
+where
+    T: DeserializeOwned,
+{
+    from_document_with(document, DeserializerConfig::default())
+}
+
+pub fn from_document_with<T>(
+    document: Document,
+    config: DeserializerConfig,
+) -> crate::deserialize::Result<T>
+where
+    T: DeserializeOwned,
+{
+    crate::from_document_with(to_v1_document(document), config)
+}
+
+/// Converts a `v1` value to the equivalent `v1beta1` value.
+pub fn from_v1_value(value: firestore::Value) -> Value {
+    use firestore::value::ValueType as V1;
+
+    let value_type = value.value_type.map(|value_type| match value_type {
+        V1::NullValue(v) => ValueType::NullValue(v),
+        V1::BooleanValue(v) => ValueType::BooleanValue(v),
+        V1::IntegerValue(v) => ValueType::IntegerValue(v),
+        V1::DoubleValue(v) => ValueType::DoubleValue(v),
+        V1::TimestampValue(v) => ValueType::TimestampValue(v),
+        V1::StringValue(v) => ValueType::StringValue(v),
+        V1::BytesValue(v) => ValueType::BytesValue(v),
+        V1::ReferenceValue(v) => ValueType::ReferenceValue(v),
+        V1::GeoPointValue(v) => ValueType::GeoPointValue(v),
+        V1::ArrayValue(firestore::ArrayValue { values }) => ValueType::ArrayValue(ArrayValue {
+            values: values.into_iter().map(from_v1_value).collect(),
+        }),
+        V1::MapValue(firestore::MapValue { fields }) => ValueType::MapValue(MapValue {
+            fields: fields
+                .into_iter()
+                .map(|(k, v)| (k, from_v1_value(v)))
+                .collect(),
+        }),
+    });
+
+    Value { value_type }
+}
+
+/// Converts a `v1beta1` value to the equivalent `v1` value.
+pub fn to_v1_value(value: Value) -> firestore::Value {
+    use firestore::value::ValueType as V1;
+
+    let value_type = value.value_type.map(|value_type| match value_type {
+        ValueType::NullValue(v) => V1::NullValue(v),
+        ValueType::BooleanValue(v) => V1::BooleanValue(v),
+        ValueType::IntegerValue(v) => V1::IntegerValue(v),
+        ValueType::DoubleValue(v) => V1::DoubleValue(v),
+        ValueType::TimestampValue(v) => V1::TimestampValue(v),
+        ValueType::StringValue(v) => V1::StringValue(v),
+        ValueType::BytesValue(v) => V1::BytesValue(v),
+        ValueType::ReferenceValue(v) => V1::ReferenceValue(v),
+        ValueType::GeoPointValue(v) => V1::GeoPointValue(v),
+        ValueType::ArrayValue(ArrayValue { values }) => V1::ArrayValue(firestore::ArrayValue {
+            values: values.into_iter().map(to_v1_value).collect(),
+        }),
+        ValueType::MapValue(MapValue { fields }) => V1::MapValue(firestore::MapValue {
+            fields: fields
+                .into_iter()
+                .map(|(k, v)| (k, to_v1_value(v)))
+                .collect(),
+        }),
+    });
+
+    firestore::Value { value_type }
+}
+
+/// Converts a `v1` document to the equivalent `v1beta1` document.
+pub fn from_v1_document(document: firestore::Document) -> Document {
+    Document {
+        name: document.name,
+        fields: document
+            .fields
+            .into_iter()
+            .map(|(k, v)| (k, from_v1_value(v)))
+            .collect(),
+        create_time: document.create_time,
+        update_time: document.update_time,
+    }
+}
+
+/// Converts a `v1beta1` document to the equivalent `v1` document.
+pub fn to_v1_document(document: Document) -> firestore::Document {
+    firestore::Document {
+        name: document.name,
+        fields: document
+            .fields
+            .into_iter()
+            .map(|(k, v)| (k, to_v1_value(v)))
+            .collect(),
+        create_time: document.create_time,
+        update_time: document.update_time,
+    }
+}
+
+/// Converts a `v1` field transform to the equivalent `v1beta1` transform.
+pub fn from_v1_field_transform(
+    transform: firestore::document_transform::FieldTransform,
+) -> FieldTransform {
+    use firestore::document_transform::field_transform::TransformType as V1;
+
+    let array = |firestore::ArrayValue { values }| ArrayValue {
+        values: values.into_iter().map(from_v1_value).collect(),
+    };
+
+    let transform_type = transform
+        .transform_type
+        .map(|transform_type| match transform_type {
+            V1::SetToServerValue(v) => TransformType::SetToServerValue(v),
+            V1::Increment(v) => TransformType::Increment(from_v1_value(v)),
+            V1::Maximum(v) => TransformType::Maximum(from_v1_value(v)),
+            V1::Minimum(v) => TransformType::Minimum(from_v1_value(v)),
+            V1::AppendMissingElements(v) => TransformType::AppendMissingElements(array(v)),
+            V1::RemoveAllFromArray(v) => TransformType::RemoveAllFromArray(array(v)),
+        });
+
+    FieldTransform {
+        field_path: transform.field_path,
+        transform_type,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{GeoPoint, Increment, ServerTimestamp};
+    use bytes::BytesMut;
+    use googapis::google::firestore::v1beta1::document_transform::field_transform::ServerValue;
+    use prost::Message;
+    use prost_types::Timestamp;
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Inner {
+        tags: Vec<String>,
+        location: GeoPoint,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Outer {
+        name: String,
+        count: i64,
+        inner: Inner,
+    }
+
+    fn outer() -> Outer {
+        Outer {
+            name: "blah".to_string(),
+            count: 4,
+            inner: Inner {
+                tags: vec!["a".to_string(), "b".to_string()],
+                location: GeoPoint::new(1.5, -2.5),
+            },
+        }
+    }
+
+    #[test]
+    fn test_round_trip_value() {
+        let value = to_grpc_value(&outer()).unwrap();
+
+        assert_eq!(
+            from_v1_value(crate::to_grpc_value(&outer()).unwrap()),
+            value
+        );
+        assert_eq!(outer(), from_grpc_value::<Outer>(&value).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip_document() {
+        let mut document = to_document(&outer()).unwrap();
+        document.name = "projects/p/databases/(default)/documents/c/d".to_string();
+        document.update_time = Some(Timestamp {
+            seconds: 150,
+            nanos: 200,
+        });
+
+        let v1 = to_v1_document(document.clone());
+        assert_eq!(document.name, v1.name);
+        assert_eq!(document.update_time, v1.update_time);
+        assert_eq!(document, from_v1_document(v1));
+
+        assert_eq!(outer(), from_document::<Outer>(document).unwrap());
+    }
+
+    #[test]
+    fn test_convert_scalars() {
+        let mut fields = HashMap::new();
+        fields.insert(
+            "null".to_string(),
+            Value {
+                value_type: Some(ValueType::NullValue(0)),
+            },
+        );
+        fields.insert(
+            "bytes".to_string(),
+            Value {
+                value_type: Some(ValueType::BytesValue(vec![1, 2, 3])),
+            },
+        );
+        fields.insert(
+            "reference".to_string(),
+            Value {
+                value_type: Some(ValueType::ReferenceValue(
+                    "projects/p/databases/(default)/documents/c/d".to_string(),
+                )),
+            },
+        );
+        fields.insert(
+            "timestamp".to_string(),
+            Value {
+                value_type: Some(ValueType::TimestampValue(Timestamp {
+                    seconds: 1,
+                    nanos: 2,
+                })),
+            },
+        );
+        fields.insert("unset".to_string(), Value { value_type: None });
+        let value = Value {
+            value_type: Some(ValueType::MapValue(MapValue { fields })),
+        };
+
+        assert_eq!(value, from_v1_value(to_v1_value(value.clone())));
+    }
+
+    #[test]
+    fn test_from_grpc_value_ref_borrows() {
+        #[derive(Deserialize)]
+        struct Borrowed<'a> {
+            name: &'a str,
+        }
+
+        let value = to_grpc_value(&outer()).unwrap();
+        let result: Borrowed = from_grpc_value_ref(&value).unwrap();
+
+        assert_eq!("blah", result.name);
+    }
+
+    #[test]
+    fn test_transforms() {
+        #[derive(Serialize)]
+        struct Counters {
+            name: String,
+            visits: Increment<i64>,
+            updated: ServerTimestamp,
+        }
+
+        let counters = Counters {
+            name: "home".to_string(),
+            visits: Increment(2),
+            updated: ServerTimestamp,
+        };
+
+        let (document, mut transforms) =
+            to_document_with_transforms(&counters, SerializerConfig::default()).unwrap();
+        transforms.sort_by(|a, b| a.field_path.cmp(&b.field_path));
+
+        assert_eq!(vec!["name"], document.fields.keys().collect::<Vec<_>>());
+        assert_eq!(
+            vec![
+                FieldTransform {
+                    field_path: "updated".to_string(),
+                    transform_type: Some(TransformType::SetToServerValue(
+                        ServerValue::RequestTime as i32
+                    )),
+                },
+                FieldTransform {
+                    field_path: "visits".to_string(),
+                    transform_type: Some(TransformType::Increment(Value {
+                        value_type: Some(ValueType::IntegerValue(2)),
+                    })),
+                },
+            ],
+            transforms
+        );
+    }
+
+    #[test]
+    fn test_document_mask() {
+        let mut field_paths = to_document_mask(&outer(), false).unwrap().field_paths;
+        field_paths.sort();
+
+        assert_eq!(vec!["count", "inner", "name"], field_paths);
+    }
+
+    #[test]
+    fn test_wire_encoding_is_shared() {
+        let mut buf = BytesMut::new();
+        crate::write_document(&outer(), &mut buf).unwrap();
+        assert_eq!(
+            to_document(&outer()).unwrap(),
+            Document::decode(&buf[..]).unwrap()
+        );
+
+        let encoded = to_grpc_value(&outer()).unwrap().encode_to_vec();
+        assert_eq!(outer(), crate::read_value::<Outer>(&encoded).unwrap());
+    }
+}
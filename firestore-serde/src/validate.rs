@@ -0,0 +1,337 @@
+//! Checks a `Document` against the limits Firestore enforces on writes, so
+//! that a violation can be reported (with its location) before the write is
+//! sent.
+//!
+//! Sizes are computed per Firestore's
+//! [storage size rules](https://firebase.google.com/docs/firestore/storage-size).
+
+use crate::firestore::{value::ValueType, ArrayValue, Document, MapValue, Value};
+use crate::value_path::{PathSegment, ValuePath};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// The maximum storage size of a document, in bytes.
+pub const MAX_DOCUMENT_SIZE: usize = 1_048_576;
+
+/// The maximum depth of a value nested in maps and arrays, where top-level
+/// fields are at depth 1.
+pub const MAX_DEPTH: usize = 20;
+
+/// The maximum size of a field name, in bytes.
+pub const MAX_FIELD_NAME_SIZE: usize = 1500;
+
+/// Extra bytes counted towards the size of every document name.
+const DOCUMENT_NAME_OVERHEAD: usize = 16;
+
+/// Extra bytes counted towards the size of every document.
+const DOCUMENT_OVERHEAD: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Violation {
+    /// The document's storage size, which exceeds `MAX_DOCUMENT_SIZE`.
+    DocumentTooLarge(usize),
+    /// A value nested more than `MAX_DEPTH` levels deep.
+    TooDeep(ValuePath),
+    /// A field name matching `__.*__`, which Firestore reserves.
+    ReservedFieldName(ValuePath),
+    /// A field name longer than `MAX_FIELD_NAME_SIZE`.
+    FieldNameTooLong(ValuePath),
+    /// An array directly inside another array.
+    ArrayInArray(ValuePath),
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::DocumentTooLarge(size) => write!(
+                f,
+                "Document is {} bytes, which exceeds the limit of {} bytes.",
+                size, MAX_DOCUMENT_SIZE
+            ),
+            Violation::TooDeep(path) => write!(
+                f,
+                "{}: value is nested more than {} levels deep.",
+                path, MAX_DEPTH
+            ),
+            Violation::ReservedFieldName(path) => {
+                write!(f, "{}: field names matching __.*__ are reserved.", path)
+            }
+            Violation::FieldNameTooLong(path) => write!(
+                f,
+                "{}: field name exceeds the limit of {} bytes.",
+                path, MAX_FIELD_NAME_SIZE
+            ),
+            Violation::ArrayInArray(path) => {
+                write!(f, "{}: arrays cannot directly contain arrays.", path)
+            }
+        }
+    }
+}
+
+/// The storage size of a string: its UTF-8 encoding plus one byte.
+fn string_size(s: &str) -> usize {
+    s.len() + 1
+}
+
+/// The storage size of a document name, e.g.
+/// `projects/p/databases/(default)/documents/users/jeff`, which counts only
+/// the collection and document IDs. An empty name has size 0.
+pub fn document_name_size(name: &str) -> usize {
+    if name.is_empty() {
+        return 0;
+    }
+
+    let path = match name.find("/documents/") {
+        Some(index) => &name[index + "/documents/".len()..],
+        None => name,
+    };
+
+    path.split('/').map(string_size).sum::<usize>() + DOCUMENT_NAME_OVERHEAD
+}
+
+/// The storage size of a value.
+pub fn value_size(value: &Value) -> usize {
+    match &value.value_type {
+        None => 0,
+        Some(ValueType::NullValue(_)) | Some(ValueType::BooleanValue(_)) => 1,
+        Some(ValueType::IntegerValue(_))
+        | Some(ValueType::DoubleValue(_))
+        | Some(ValueType::TimestampValue(_)) => 8,
+        Some(ValueType::GeoPointValue(_)) => 16,
+        Some(ValueType::StringValue(v)) => string_size(v),
+        Some(ValueType::BytesValue(v)) => v.len(),
+        Some(ValueType::ReferenceValue(v)) => document_name_size(v),
+        Some(ValueType::ArrayValue(ArrayValue { values })) => values.iter().map(value_size).sum(),
+        Some(ValueType::MapValue(MapValue { fields })) => fields_size(fields),
+    }
+}
+
+fn fields_size(fields: &HashMap<String, Value>) -> usize {
+    fields
+        .iter()
+        .map(|(key, value)| string_size(key) + value_size(value))
+        .sum()
+}
+
+/// The storage size of a document. If the document has no name (e.g. it
+/// was produced by `to_document`), the size of its eventual name is not
+/// included.
+pub fn document_size(document: &Document) -> usize {
+    document_name_size(&document.name) + fields_size(&document.fields) + DOCUMENT_OVERHEAD
+}
+
+/// Checks `document` against Firestore's limits, returning every violation
+/// found. An empty result means the document is valid.
+pub fn validate_document(document: &Document) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    let size = document_size(document);
+    if size > MAX_DOCUMENT_SIZE {
+        violations.push(Violation::DocumentTooLarge(size));
+    }
+
+    validate_fields(&document.fields, &mut Vec::new(), &mut violations);
+
+    violations
+}
+
+fn path_to(segments: &[PathSegment]) -> ValuePath {
+    ValuePath::from_segments(segments.to_vec())
+}
+
+fn validate_fields(
+    fields: &HashMap<String, Value>,
+    path: &mut Vec<PathSegment>,
+    violations: &mut Vec<Violation>,
+) {
+    for (key, value) in fields {
+        path.push(PathSegment::Key(key.clone()));
+
+        if key.len() >= 4 && key.starts_with("__") && key.ends_with("__") {
+            violations.push(Violation::ReservedFieldName(path_to(path)));
+        }
+        if key.len() > MAX_FIELD_NAME_SIZE {
+            violations.push(Violation::FieldNameTooLong(path_to(path)));
+        }
+
+        validate_value(value, path, false, violations);
+        path.pop();
+    }
+}
+
+fn validate_value(
+    value: &Value,
+    path: &mut Vec<PathSegment>,
+    in_array: bool,
+    violations: &mut Vec<Violation>,
+) {
+    if path.len() > MAX_DEPTH {
+        violations.push(Violation::TooDeep(path_to(path)));
+        return;
+    }
+
+    match &value.value_type {
+        Some(ValueType::ArrayValue(ArrayValue { values })) => {
+            if in_array {
+                violations.push(Violation::ArrayInArray(path_to(path)));
+            }
+
+            for (index, value) in values.iter().enumerate() {
+                path.push(PathSegment::Index(index));
+                validate_value(value, path, true, violations);
+                path.pop();
+            }
+        }
+        Some(ValueType::MapValue(MapValue { fields })) => {
+            validate_fields(fields, path, violations)
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::to_document;
+    use serde::Serialize;
+
+    fn string_value(v: &str) -> Value {
+        Value {
+            value_type: Some(ValueType::StringValue(v.to_string())),
+        }
+    }
+
+    fn map_value(key: &str, value: Value) -> Value {
+        Value {
+            value_type: Some(ValueType::MapValue(MapValue {
+                fields: vec![(key.to_string(), value)].into_iter().collect(),
+            })),
+        }
+    }
+
+    #[test]
+    fn test_document_size() {
+        // The example from Firestore's documentation.
+        #[derive(Serialize)]
+        struct Task {
+            r#type: String,
+            done: bool,
+            priority: i64,
+            description: String,
+        }
+
+        let mut document = to_document(&Task {
+            r#type: "Personal".to_string(),
+            done: false,
+            priority: 1,
+            description: "Learn Cloud Firestore".to_string(),
+        })
+        .unwrap();
+        document.name = "projects/p/databases/(default)/documents/users/jeff/tasks/my_task_id"
+            .to_string();
+
+        assert_eq!(44, document_name_size(&document.name));
+        assert_eq!(147, document_size(&document));
+        assert!(validate_document(&document).is_empty());
+    }
+
+    #[test]
+    fn test_too_large() {
+        let mut document = Document::default();
+        document
+            .fields
+            .insert("a".to_string(), string_value(&"x".repeat(MAX_DOCUMENT_SIZE)));
+
+        let size = 2 + MAX_DOCUMENT_SIZE + 1 + DOCUMENT_OVERHEAD;
+        assert_eq!(
+            vec![Violation::DocumentTooLarge(size)],
+            validate_document(&document)
+        );
+    }
+
+    #[test]
+    fn test_field_names() {
+        let mut document = Document::default();
+        document.fields.insert(
+            "outer".to_string(),
+            map_value("__reserved__", string_value("a")),
+        );
+        document.fields.insert(
+            "x".repeat(MAX_FIELD_NAME_SIZE + 1),
+            string_value("a"),
+        );
+        document.fields.insert("__".to_string(), string_value("a"));
+
+        let mut violations: Vec<String> = validate_document(&document)
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        violations.sort();
+
+        assert_eq!(2, violations.len());
+        assert_eq!(
+            "outer.__reserved__: field names matching __.*__ are reserved.",
+            violations[0]
+        );
+        assert!(violations[1].ends_with("field name exceeds the limit of 1500 bytes."));
+    }
+
+    /// A document whose string leaf is `depth` maps deep.
+    fn nested_document(depth: usize) -> Document {
+        let mut value = string_value("deep");
+        for _ in 0..depth {
+            value = map_value("a", value);
+        }
+
+        match value.value_type {
+            Some(ValueType::MapValue(MapValue { fields })) => Document {
+                fields,
+                ..Document::default()
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_nesting() {
+        assert!(validate_document(&nested_document(MAX_DEPTH)).is_empty());
+
+        assert_eq!(
+            format!(
+                "{}: value is nested more than 20 levels deep.",
+                vec!["a"; MAX_DEPTH + 1].join(".")
+            ),
+            validate_document(&nested_document(MAX_DEPTH + 1))[0].to_string()
+        );
+    }
+
+    #[test]
+    fn test_array_in_array() {
+        #[derive(Serialize)]
+        struct Matrix {
+            rows: Vec<Vec<i64>>,
+        }
+
+        let document = to_document(&Matrix {
+            rows: vec![vec![1], vec![2]],
+        })
+        .unwrap();
+
+        let mut violations = validate_document(&document);
+        violations.sort_by_key(|v| v.to_string());
+
+        assert_eq!(
+            vec![
+                Violation::ArrayInArray(path_to(&[
+                    PathSegment::Key("rows".to_string()),
+                    PathSegment::Index(0)
+                ])),
+                Violation::ArrayInArray(path_to(&[
+                    PathSegment::Key("rows".to_string()),
+                    PathSegment::Index(1)
+                ])),
+            ],
+            violations
+        );
+    }
+}
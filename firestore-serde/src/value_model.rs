@@ -0,0 +1,609 @@
+//! A trait describing Firestore's value model, so that Rust values can be
+//! converted to and from `Value` types other than the `googapis` ones (for
+//! example, protos generated with a different version of `prost`).
+//!
+//! `ValueSerializer` and `ValueDeserializer` work on any implementation of
+//! `FirestoreValue`. The `googapis` `Value` implements it when the
+//! `google-firestore-v1` (default) or `google-firestore-v1beta1` feature is
+//! enabled.
+
+use crate::{DeserializerConfig, GeoPoint, SerializerConfig, ValueDeserializer, ValueSerializer};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The contents of a value, as returned by `FirestoreValue::kind`.
+pub enum ValueKind<'a, V> {
+    /// A value with no type set. Fields with such values are omitted from
+    /// maps when converting.
+    Unset,
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Double(f64),
+    Timestamp { seconds: i64, nanos: i32 },
+    String(&'a str),
+    Bytes(&'a [u8]),
+    Reference(&'a str),
+    GeoPoint(GeoPoint),
+    Array(&'a [V]),
+    Map(Box<dyn Iterator<Item = (&'a str, &'a V)> + 'a>),
+}
+
+impl<'a, V> ValueKind<'a, V> {
+    /// A short description of the kind, for error messages.
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ValueKind::Unset => "empty value",
+            ValueKind::Null => "null",
+            ValueKind::Boolean(_) => "boolean",
+            ValueKind::Integer(_) => "integer",
+            ValueKind::Double(_) => "double",
+            ValueKind::Timestamp { .. } => "timestamp",
+            ValueKind::String(_) => "string",
+            ValueKind::Bytes(_) => "bytes",
+            ValueKind::Reference(_) => "reference",
+            ValueKind::GeoPoint(_) => "geopoint",
+            ValueKind::Array(_) => "array",
+            ValueKind::Map(_) => "map",
+        }
+    }
+}
+
+/// A Firestore value, which can be constructed from and inspected as each
+/// of the types Firestore supports.
+pub trait FirestoreValue: Sized {
+    /// A value with no type set, whose `kind` is `ValueKind::Unset`. The
+    /// serializer returns it for map fields which are to be omitted, such as
+    /// `None` with `SerializerConfig::with_skip_none`.
+    fn unset() -> Self;
+
+    fn null() -> Self;
+
+    fn boolean(v: bool) -> Self;
+
+    fn integer(v: i64) -> Self;
+
+    fn double(v: f64) -> Self;
+
+    fn timestamp(seconds: i64, nanos: i32) -> Self;
+
+    fn string(v: String) -> Self;
+
+    fn bytes(v: Vec<u8>) -> Self;
+
+    /// A reference to the document with the given resource name.
+    fn reference(v: String) -> Self;
+
+    fn geo_point(v: GeoPoint) -> Self;
+
+    fn array(values: Vec<Self>) -> Self;
+
+    fn map(fields: HashMap<String, Self>) -> Self;
+
+    fn kind(&self) -> ValueKind<'_, Self>;
+
+    /// The contents of a string or reference value. The deserializer uses
+    /// this and the other `into_` methods to move the contents of values it
+    /// owns into the result. The default implementations copy them out of
+    /// `kind`.
+    fn into_string(self) -> Option<String> {
+        match self.kind() {
+            ValueKind::String(v) | ValueKind::Reference(v) => Some(v.to_string()),
+            _ => None,
+        }
+    }
+
+    /// The contents of a bytes value.
+    fn into_bytes(self) -> Option<Vec<u8>> {
+        match self.kind() {
+            ValueKind::Bytes(v) => Some(v.to_vec()),
+            _ => None,
+        }
+    }
+
+    /// The elements of an array value.
+    fn into_array(self) -> Option<Vec<Self>> {
+        match self.kind() {
+            ValueKind::Array(values) => Some(values.iter().map(convert).collect()),
+            _ => None,
+        }
+    }
+
+    /// The fields of a map value.
+    fn into_map(self) -> Option<HashMap<String, Self>> {
+        match self.kind() {
+            ValueKind::Map(fields) => {
+                Some(fields.map(|(k, v)| (k.to_string(), convert(v))).collect())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Implements `FirestoreValue` for the `Value` of a `googapis` Firestore
+/// module. Every API version defines `Value` identically, so the same
+/// implementation serves each of them.
+#[cfg(feature = "googapis")]
+macro_rules! impl_firestore_value {
+    ($($module:ident)::+) => {
+        const _: () = {
+            use $($module)::+::{value::ValueType, ArrayValue, MapValue, Value};
+            use googapis::google::r#type::LatLng;
+            use prost_types::Timestamp;
+            use std::collections::HashMap;
+            use $crate::{FirestoreValue, GeoPoint, ValueKind};
+
+            impl FirestoreValue for Value {
+                fn unset() -> Self {
+                    Value { value_type: None }
+                }
+
+                fn null() -> Self {
+                    Value {
+                        value_type: Some(ValueType::NullValue(0)),
+                    }
+                }
+
+                fn boolean(v: bool) -> Self {
+                    Value {
+                        value_type: Some(ValueType::BooleanValue(v)),
+                    }
+                }
+
+                fn integer(v: i64) -> Self {
+                    Value {
+                        value_type: Some(ValueType::IntegerValue(v)),
+                    }
+                }
+
+                fn double(v: f64) -> Self {
+                    Value {
+                        value_type: Some(ValueType::DoubleValue(v)),
+                    }
+                }
+
+                fn timestamp(seconds: i64, nanos: i32) -> Self {
+                    Value {
+                        value_type: Some(ValueType::TimestampValue(Timestamp {
+                            seconds,
+                            nanos,
+                        })),
+                    }
+                }
+
+                fn string(v: String) -> Self {
+                    Value {
+                        value_type: Some(ValueType::StringValue(v)),
+                    }
+                }
+
+                fn bytes(v: Vec<u8>) -> Self {
+                    Value {
+                        value_type: Some(ValueType::BytesValue(v)),
+                    }
+                }
+
+                fn reference(v: String) -> Self {
+                    Value {
+                        value_type: Some(ValueType::ReferenceValue(v)),
+                    }
+                }
+
+                fn geo_point(v: GeoPoint) -> Self {
+                    Value {
+                        value_type: Some(ValueType::GeoPointValue(LatLng::from(v))),
+                    }
+                }
+
+                fn array(values: Vec<Self>) -> Self {
+                    Value {
+                        value_type: Some(ValueType::ArrayValue(ArrayValue { values })),
+                    }
+                }
+
+                fn map(fields: HashMap<String, Self>) -> Self {
+                    Value {
+                        value_type: Some(ValueType::MapValue(MapValue { fields })),
+                    }
+                }
+
+                fn kind(&self) -> ValueKind<'_, Self> {
+                    match &self.value_type {
+                        None => ValueKind::Unset,
+                        Some(ValueType::NullValue(_)) => ValueKind::Null,
+                        Some(ValueType::BooleanValue(v)) => ValueKind::Boolean(*v),
+                        Some(ValueType::IntegerValue(v)) => ValueKind::Integer(*v),
+                        Some(ValueType::DoubleValue(v)) => ValueKind::Double(*v),
+                        Some(ValueType::TimestampValue(Timestamp { seconds, nanos })) => {
+                            ValueKind::Timestamp {
+                                seconds: *seconds,
+                                nanos: *nanos,
+                            }
+                        }
+                        Some(ValueType::StringValue(v)) => ValueKind::String(v),
+                        Some(ValueType::BytesValue(v)) => ValueKind::Bytes(v),
+                        Some(ValueType::ReferenceValue(v)) => ValueKind::Reference(v),
+                        Some(ValueType::GeoPointValue(v)) => {
+                            ValueKind::GeoPoint(GeoPoint::from(v.clone()))
+                        }
+                        Some(ValueType::ArrayValue(ArrayValue { values })) => {
+                            ValueKind::Array(values)
+                        }
+                        Some(ValueType::MapValue(MapValue { fields })) => ValueKind::Map(Box::new(
+                            fields.iter().map(|(k, v)| (k.as_str(), v)),
+                        )),
+                    }
+                }
+
+                fn into_string(self) -> Option<String> {
+                    match self.value_type {
+                        Some(ValueType::StringValue(v)) | Some(ValueType::ReferenceValue(v)) => {
+                            Some(v)
+                        }
+                        _ => None,
+                    }
+                }
+
+                fn into_bytes(self) -> Option<Vec<u8>> {
+                    match self.value_type {
+                        Some(ValueType::BytesValue(v)) => Some(v),
+                        _ => None,
+                    }
+                }
+
+                fn into_array(self) -> Option<Vec<Self>> {
+                    match self.value_type {
+                        Some(ValueType::ArrayValue(ArrayValue { values })) => Some(values),
+                        _ => None,
+                    }
+                }
+
+                fn into_map(self) -> Option<HashMap<String, Self>> {
+                    match self.value_type {
+                        Some(ValueType::MapValue(MapValue { fields })) => Some(fields),
+                        _ => None,
+                    }
+                }
+            }
+        };
+    };
+}
+
+#[cfg(all(feature = "google-firestore-v1", feature = "google-firestore-v1beta1"))]
+pub(crate) use impl_firestore_value;
+
+#[cfg(feature = "googapis")]
+impl_firestore_value!(crate::firestore);
+
+/// Converts a value from one implementation of `FirestoreValue` to another.
+/// An unset value becomes null, except as a map field, where it is omitted.
+pub fn convert<A, B>(value: &A) -> B
+where
+    A: FirestoreValue,
+    B: FirestoreValue,
+{
+    match value.kind() {
+        ValueKind::Unset | ValueKind::Null => B::null(),
+        ValueKind::Boolean(v) => B::boolean(v),
+        ValueKind::Integer(v) => B::integer(v),
+        ValueKind::Double(v) => B::double(v),
+        ValueKind::Timestamp { seconds, nanos } => B::timestamp(seconds, nanos),
+        ValueKind::String(v) => B::string(v.to_string()),
+        ValueKind::Bytes(v) => B::bytes(v.to_vec()),
+        ValueKind::Reference(v) => B::reference(v.to_string()),
+        ValueKind::GeoPoint(v) => B::geo_point(v),
+        ValueKind::Array(values) => B::array(values.iter().map(convert).collect()),
+        ValueKind::Map(fields) => B::map(
+            fields
+                .filter(|(_, v)| !matches!(v.kind(), ValueKind::Unset))
+                .map(|(k, v)| (k.to_string(), convert(v)))
+                .collect(),
+        ),
+    }
+}
+
+pub fn to_value<V, T>(value: &T) -> crate::serialize::Result<V>
+where
+    V: FirestoreValue,
+    T: Serialize,
+{
+    to_value_with(value, SerializerConfig::default())
+}
+
+pub fn to_value_with<V, T>(value: &T, config: SerializerConfig) -> crate::serialize::Result<V>
+where
+    V: FirestoreValue,
+    T: Serialize,
+{
+    value.serialize(ValueSerializer::with_config(config))
+}
+
+pub fn from_value<V, T>(value: &V) -> crate::deserialize::Result<T>
+where
+    V: FirestoreValue,
+    T: DeserializeOwned,
+{
+    from_value_with(value, DeserializerConfig::default())
+}
+
+pub fn from_value_with<V, T>(value: &V, config: DeserializerConfig) -> crate::deserialize::Result<T>
+where
+    V: FirestoreValue,
+    T: DeserializeOwned,
+{
+    T::deserialize(&mut ValueDeserializer::with_config(value, config))
+}
+
+/// Like `from_value`, but allows `&str` and `&[u8]` fields of the result to
+/// borrow directly from `value`.
+pub fn from_value_ref<'de, V, T>(value: &'de V) -> crate::deserialize::Result<T>
+where
+    V: FirestoreValue,
+    T: Deserialize<'de>,
+{
+    from_value_ref_with(value, DeserializerConfig::default())
+}
+
+pub fn from_value_ref_with<'de, V, T>(
+    value: &'de V,
+    config: DeserializerConfig,
+) -> crate::deserialize::Result<T>
+where
+    V: FirestoreValue,
+    T: Deserialize<'de>,
+{
+    T::deserialize(&mut ValueDeserializer::with_config(value, config))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// A value model unrelated to `googapis`, as a proto binding generated
+    /// by another tool might provide.
+    #[derive(Debug, PartialEq)]
+    enum OtherValue {
+        Unset,
+        Null,
+        Boolean(bool),
+        Integer(i64),
+        Double(f64),
+        Timestamp(i64, i32),
+        String(String),
+        Bytes(Vec<u8>),
+        Reference(String),
+        GeoPoint(f64, f64),
+        Array(Vec<OtherValue>),
+        Map(BTreeMap<String, OtherValue>),
+    }
+
+    impl FirestoreValue for OtherValue {
+        fn unset() -> Self {
+            OtherValue::Unset
+        }
+
+        fn null() -> Self {
+            OtherValue::Null
+        }
+
+        fn boolean(v: bool) -> Self {
+            OtherValue::Boolean(v)
+        }
+
+        fn integer(v: i64) -> Self {
+            OtherValue::Integer(v)
+        }
+
+        fn double(v: f64) -> Self {
+            OtherValue::Double(v)
+        }
+
+        fn timestamp(seconds: i64, nanos: i32) -> Self {
+            OtherValue::Timestamp(seconds, nanos)
+        }
+
+        fn string(v: String) -> Self {
+            OtherValue::String(v)
+        }
+
+        fn bytes(v: Vec<u8>) -> Self {
+            OtherValue::Bytes(v)
+        }
+
+        fn reference(v: String) -> Self {
+            OtherValue::Reference(v)
+        }
+
+        fn geo_point(v: GeoPoint) -> Self {
+            OtherValue::GeoPoint(v.latitude, v.longitude)
+        }
+
+        fn array(values: Vec<Self>) -> Self {
+            OtherValue::Array(values)
+        }
+
+        fn map(fields: HashMap<String, Self>) -> Self {
+            OtherValue::Map(fields.into_iter().collect())
+        }
+
+        fn kind(&self) -> ValueKind<'_, Self> {
+            match self {
+                OtherValue::Unset => ValueKind::Unset,
+                OtherValue::Null => ValueKind::Null,
+                OtherValue::Boolean(v) => ValueKind::Boolean(*v),
+                OtherValue::Integer(v) => ValueKind::Integer(*v),
+                OtherValue::Double(v) => ValueKind::Double(*v),
+                OtherValue::Timestamp(seconds, nanos) => ValueKind::Timestamp {
+                    seconds: *seconds,
+                    nanos: *nanos,
+                },
+                OtherValue::String(v) => ValueKind::String(v),
+                OtherValue::Bytes(v) => ValueKind::Bytes(v),
+                OtherValue::Reference(v) => ValueKind::Reference(v),
+                OtherValue::GeoPoint(latitude, longitude) => {
+                    ValueKind::GeoPoint(GeoPoint::new(*latitude, *longitude))
+                }
+                OtherValue::Array(values) => ValueKind::Array(values),
+                OtherValue::Map(fields) => {
+                    ValueKind::Map(Box::new(fields.iter().map(|(k, v)| (k.as_str(), v))))
+                }
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Place {
+        name: String,
+        visits: u32,
+        location: GeoPoint,
+        #[serde(with = "serde_bytes")]
+        photo: Vec<u8>,
+        tags: Vec<String>,
+        rating: Option<f64>,
+    }
+
+    #[test]
+    fn test_round_trip_other_value() {
+        let place = Place {
+            name: "home".to_string(),
+            visits: 3,
+            location: GeoPoint::new(1.5, -2.5),
+            photo: vec![1, 2, 3],
+            tags: vec!["a".to_string()],
+            rating: None,
+        };
+
+        let value: OtherValue = to_value(&place).unwrap();
+
+        let expected = OtherValue::Map(
+            vec![
+                ("name".to_string(), OtherValue::String("home".to_string())),
+                ("visits".to_string(), OtherValue::Integer(3)),
+                ("location".to_string(), OtherValue::GeoPoint(1.5, -2.5)),
+                ("photo".to_string(), OtherValue::Bytes(vec![1, 2, 3])),
+                (
+                    "tags".to_string(),
+                    OtherValue::Array(vec![OtherValue::String("a".to_string())]),
+                ),
+                ("rating".to_string(), OtherValue::Null),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        assert_eq!(expected, value);
+        assert_eq!(place, from_value::<_, Place>(&value).unwrap());
+    }
+
+    #[test]
+    fn test_skip_none_other_value() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Profile {
+            name: String,
+            nickname: Option<String>,
+        }
+
+        let profile = Profile {
+            name: "alice".to_string(),
+            nickname: None,
+        };
+
+        let value: OtherValue =
+            to_value_with(&profile, SerializerConfig::new().with_skip_none(true)).unwrap();
+
+        assert_eq!(
+            OtherValue::Map(
+                vec![("name".to_string(), OtherValue::String("alice".to_string()))]
+                    .into_iter()
+                    .collect()
+            ),
+            value
+        );
+        assert_eq!(profile, from_value::<_, Profile>(&value).unwrap());
+    }
+
+    #[test]
+    fn test_from_value_ref_borrows() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Borrowed<'a> {
+            name: &'a str,
+            #[serde(with = "serde_bytes")]
+            photo: &'a [u8],
+        }
+
+        let value = OtherValue::Map(
+            vec![
+                ("name".to_string(), OtherValue::String("home".to_string())),
+                ("photo".to_string(), OtherValue::Bytes(vec![1, 2, 3])),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        assert_eq!(
+            Borrowed {
+                name: "home",
+                photo: &[1, 2, 3],
+            },
+            from_value_ref(&value).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_wrong_type_other_value() {
+        assert_eq!(
+            crate::deserialize::DeserializationError::WrongType("string", "integer"),
+            from_value::<_, String>(&OtherValue::Integer(1)).unwrap_err()
+        );
+    }
+
+    #[cfg(feature = "googapis")]
+    #[test]
+    fn test_convert_skips_unset_fields() {
+        use crate::firestore::Value;
+
+        let map = |fields: Vec<(&str, Value)>| {
+            Value::map(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v))
+                    .collect(),
+            )
+        };
+
+        let value = map(vec![
+            ("a", Value::integer(1)),
+            ("b", Value::unset()),
+            ("c", Value::reference("projects/p".to_string())),
+            ("d", Value::timestamp(150, 200)),
+        ]);
+
+        let converted: OtherValue = convert(&value);
+
+        assert_eq!(
+            OtherValue::Map(
+                vec![
+                    ("a".to_string(), OtherValue::Integer(1)),
+                    (
+                        "c".to_string(),
+                        OtherValue::Reference("projects/p".to_string())
+                    ),
+                    ("d".to_string(), OtherValue::Timestamp(150, 200)),
+                ]
+                .into_iter()
+                .collect()
+            ),
+            converted
+        );
+        assert_eq!(
+            map(vec![
+                ("a", Value::integer(1)),
+                ("c", Value::reference("projects/p".to_string())),
+                ("d", Value::timestamp(150, 200)),
+            ]),
+            convert(&converted)
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use crate::field_path::escape_segment;
+use std::fmt::Display;
+
+/// One step into a `Value`: a map key or an array index.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// The location of a value within a document (or another value), as reported
+/// by errors.
+///
+/// Paths are formatted like field paths, with array indices as plain numbers,
+/// e.g. `users.3.address.zip`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ValuePath {
+    segments: Vec<PathSegment>,
+}
+
+impl ValuePath {
+    pub(crate) fn new(segment: PathSegment) -> Self {
+        ValuePath {
+            segments: vec![segment],
+        }
+    }
+
+    #[cfg(feature = "googapis")]
+    pub(crate) fn from_segments(segments: Vec<PathSegment>) -> Self {
+        ValuePath { segments }
+    }
+
+    /// The segments of the path, outermost first.
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// Adds a segment to the start of the path, as an error passes out of
+    /// the map or array that contains the value it refers to.
+    pub(crate) fn prepend(&mut self, segment: PathSegment) {
+        self.segments.insert(0, segment);
+    }
+}
+
+impl Display for ValuePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i > 0 {
+                write!(f, ".")?;
+            }
+
+            match segment {
+                PathSegment::Key(key) => write!(f, "{}", escape_segment(key))?,
+                PathSegment::Index(index) => write!(f, "{}", index)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PathSegment, ValuePath};
+
+    #[test]
+    fn test_format_path() {
+        let mut path = ValuePath::new(PathSegment::Key("zip code".to_string()));
+        path.prepend(PathSegment::Key("address".to_string()));
+        path.prepend(PathSegment::Index(3));
+        path.prepend(PathSegment::Key("users".to_string()));
+
+        assert_eq!("users.3.address.`zip code`", path.to_string());
+    }
+}
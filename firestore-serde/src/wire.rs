@@ -0,0 +1,327 @@
+//! Field numbers of the Firestore messages, for reading and writing their
+//! protobuf encoding directly (see `write_value` and `read_value`).
+
+// google.firestore.v1.Value
+pub(crate) const VALUE_BOOLEAN: u32 = 1;
+pub(crate) const VALUE_INTEGER: u32 = 2;
+pub(crate) const VALUE_DOUBLE: u32 = 3;
+pub(crate) const VALUE_REFERENCE: u32 = 5;
+pub(crate) const VALUE_MAP: u32 = 6;
+pub(crate) const VALUE_GEO_POINT: u32 = 8;
+pub(crate) const VALUE_ARRAY: u32 = 9;
+pub(crate) const VALUE_TIMESTAMP: u32 = 10;
+pub(crate) const VALUE_NULL: u32 = 11;
+pub(crate) const VALUE_STRING: u32 = 17;
+pub(crate) const VALUE_BYTES: u32 = 18;
+
+// google.firestore.v1.ArrayValue
+pub(crate) const ARRAY_VALUES: u32 = 1;
+
+// google.firestore.v1.MapValue, whose entries are encoded as messages with
+// a key and a value.
+pub(crate) const MAP_FIELDS: u32 = 1;
+pub(crate) const ENTRY_KEY: u32 = 1;
+pub(crate) const ENTRY_VALUE: u32 = 2;
+
+// google.firestore.v1.Document
+pub(crate) const DOCUMENT_NAME: u32 = 1;
+pub(crate) const DOCUMENT_FIELDS: u32 = 2;
+pub(crate) const DOCUMENT_CREATE_TIME: u32 = 3;
+pub(crate) const DOCUMENT_UPDATE_TIME: u32 = 4;
+
+#[cfg(test)]
+mod test {
+    use crate::deserialize::DeserializationError;
+    use crate::firestore::{Document, Value};
+    use crate::serialize::SerializationError;
+    use crate::{
+        from_document, from_grpc_value, read_document, read_document_with, read_value,
+        read_value_with, to_document, to_grpc_value, to_grpc_value_with, write_document,
+        write_document_with, write_value, write_value_with, DeserializerConfig, DocumentName,
+        DocumentReference, EnumRepresentation, GeoPoint, I128Fallback, SerializerConfig,
+    };
+    use bytes::BytesMut;
+    use prost::Message;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Circle(f64),
+        Square { side: f64 },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Place {
+        name: String,
+        visits: u32,
+        rating: Option<f64>,
+        open: bool,
+        tags: Vec<String>,
+        #[serde(with = "serde_bytes")]
+        thumbnail: Vec<u8>,
+        owner: DocumentReference,
+        location: GeoPoint,
+        shape: Shape,
+        hours: HashMap<String, (u8, u8)>,
+    }
+
+    fn place() -> Place {
+        Place {
+            name: "Café".to_string(),
+            visits: 12,
+            rating: None,
+            open: true,
+            tags: vec!["quiet".to_string(), "wifi".to_string()],
+            thumbnail: vec![0, 1, 255],
+            owner: DocumentReference::new("p", "(default)", &["users"], "alice"),
+            location: GeoPoint::new(48.85, 2.35),
+            shape: Shape::Square { side: 3.5 },
+            hours: vec![("mon".to_string(), (9, 17))].into_iter().collect(),
+        }
+    }
+
+    fn encode_value<T: Serialize>(value: &T) -> BytesMut {
+        let mut buf = BytesMut::new();
+        write_value(value, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_matches_prost_encoding() {
+        // Maps are encoded in an unspecified order, so only values with at
+        // most one entry per map are compared byte for byte.
+        assert_eq!(
+            to_grpc_value(&"blah").unwrap().encode_to_vec(),
+            encode_value(&"blah").to_vec()
+        );
+        assert_eq!(
+            to_grpc_value(&-5i64).unwrap().encode_to_vec(),
+            encode_value(&-5i64).to_vec()
+        );
+        assert_eq!(
+            to_grpc_value(&vec![Some(1.5), None]).unwrap().encode_to_vec(),
+            encode_value(&vec![Some(1.5), None]).to_vec()
+        );
+
+        let long = vec!["x".repeat(300)];
+        assert_eq!(
+            to_grpc_value(&long).unwrap().encode_to_vec(),
+            encode_value(&long).to_vec()
+        );
+
+        let location = GeoPoint::new(1.0, 2.0);
+        assert_eq!(
+            to_grpc_value(&location).unwrap().encode_to_vec(),
+            encode_value(&location).to_vec()
+        );
+
+        let empty: HashMap<String, u8> = vec![(String::new(), 0)].into_iter().collect();
+        assert_eq!(
+            to_grpc_value(&empty).unwrap().encode_to_vec(),
+            encode_value(&empty).to_vec()
+        );
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        // Long enough that the lengths of the outer messages take several
+        // bytes, and each map has one entry so that it matches prost.
+        let mut value = serde_json::json!("x".repeat(300));
+        for _ in 0..60 {
+            value = serde_json::json!({ "inner": [value, 1] });
+        }
+
+        let buf = encode_value(&value);
+
+        assert_eq!(to_grpc_value(&value).unwrap().encode_to_vec(), buf.to_vec());
+        assert_eq!(value, read_value::<serde_json::Value>(&buf).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip_value() {
+        let buf = encode_value(&place());
+
+        assert_eq!(
+            to_grpc_value(&place()).unwrap(),
+            Value::decode(&buf[..]).unwrap()
+        );
+        assert_eq!(place(), read_value::<Place>(&buf).unwrap());
+        assert_eq!(
+            place(),
+            from_grpc_value::<Place>(&Value::decode(&buf[..]).unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_borrowed_strings() {
+        #[derive(Deserialize)]
+        struct Borrowed<'a> {
+            name: &'a str,
+            #[serde(with = "serde_bytes")]
+            thumbnail: &'a [u8],
+        }
+
+        let buf = encode_value(&place());
+        let borrowed: Borrowed = read_value(&buf).unwrap();
+
+        assert_eq!("Café", borrowed.name);
+        assert_eq!(&[0, 1, 255], borrowed.thumbnail);
+    }
+
+    #[test]
+    fn test_round_trip_document() {
+        let mut buf = BytesMut::new();
+        write_document(&place(), &mut buf).unwrap();
+
+        assert_eq!(
+            to_document(&place()).unwrap(),
+            Document::decode(&buf[..]).unwrap()
+        );
+        assert_eq!(place(), read_document::<Place>(&buf).unwrap());
+
+        let mut buf = BytesMut::new();
+        assert_eq!(
+            Err(SerializationError::NotAMap),
+            write_document(&vec![1], &mut buf)
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_document_metadata() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Named {
+            #[serde(rename = "__name__")]
+            name: DocumentName,
+            visits: u32,
+        }
+
+        let mut document = to_document(&place()).unwrap();
+        document.name = "projects/p/databases/(default)/documents/places/cafe".to_string();
+        let buf = document.encode_to_vec();
+
+        assert_eq!(
+            from_document::<Named>(document).unwrap(),
+            read_document::<Named>(&buf).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut buf = BytesMut::from(&b"prefix"[..]);
+        let result = write_value(&vec![Some(u64::MAX)], &mut buf);
+
+        assert_eq!(
+            Some("0".to_string()),
+            result.unwrap_err().path().map(ToString::to_string)
+        );
+        assert_eq!(&b"prefix"[..], &buf[..]);
+
+        let buf = encode_value(&place());
+        let error = read_value::<HashMap<String, String>>(&buf).unwrap_err();
+        assert_eq!(
+            Some("visits".to_string()),
+            error.path().map(ToString::to_string)
+        );
+
+        assert!(matches!(
+            read_value::<Place>(&buf[..buf.len() - 1]),
+            Err(DeserializationError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_scalars() {
+        assert_eq!(Ok(-5i8), read_value(&encode_value(&-5i64)));
+        assert_eq!(Ok(300u16), read_value(&encode_value(&300i64)));
+        assert_eq!(Ok(1.5f32), read_value(&encode_value(&1.5f64)));
+        assert_eq!(Ok('x'), read_value(&encode_value(&"x")));
+        assert_eq!(Ok(u128::MAX), read_value(&encode_value(&u128::MAX)));
+        assert_eq!(
+            Err(DeserializationError::IntRange("u8", "-5".to_string())),
+            read_value::<u8>(&encode_value(&-5i64))
+        );
+        assert_eq!(
+            Err(DeserializationError::IntRange("u128", "-5".to_string())),
+            read_value::<u128>(&encode_value(&"-5"))
+        );
+        assert_eq!(
+            Err(DeserializationError::WrongType("i32", "map")),
+            read_value::<i32>(&encode_value(&place()))
+        );
+        assert_eq!(
+            Err(DeserializationError::WrongType("i128", "string")),
+            read_value_with::<i128>(
+                &encode_value(&"5"),
+                DeserializerConfig::new().with_i128_fallback(I128Fallback::Bytes)
+            )
+        );
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Labelled<'a> {
+        Named { name: &'a str },
+        Unit,
+    }
+
+    #[test]
+    fn test_borrowed_enums() {
+        for representation in [
+            EnumRepresentation::default(),
+            EnumRepresentation::ExternallyTagged,
+            EnumRepresentation::InternallyTagged { tag_key: "type" },
+        ] {
+            let serializer_config =
+                SerializerConfig::new().with_enum_representation(representation);
+            let deserializer_config =
+                DeserializerConfig::new().with_enum_representation(representation);
+
+            for v in [Labelled::Named { name: "alice" }, Labelled::Unit] {
+                let mut buf = BytesMut::new();
+                write_value_with(&v, serializer_config, &mut buf).unwrap();
+                assert_eq!(Ok(&v), read_value_with(&buf, deserializer_config).as_ref());
+
+                if v != Labelled::Unit {
+                    let mut buf = BytesMut::new();
+                    write_document_with(&v, serializer_config, &mut buf).unwrap();
+                    assert_eq!(
+                        Ok(&v),
+                        read_document_with(&buf, deserializer_config).as_ref()
+                    );
+                }
+            }
+        }
+
+        assert_eq!(
+            Err(DeserializationError::WrongType("enum", "integer")),
+            read_value::<Labelled>(&encode_value(&5i64))
+        );
+    }
+
+    #[test]
+    fn test_skip_none() {
+        #[derive(Serialize)]
+        struct Maybe {
+            a: Option<u8>,
+        }
+
+        let mut buf = BytesMut::new();
+        write_value_with(
+            &Maybe { a: None },
+            SerializerConfig::new().with_skip_none(true),
+            &mut buf,
+        )
+        .unwrap();
+
+        assert_eq!(
+            to_grpc_value_with(
+                &Maybe { a: None },
+                SerializerConfig::new().with_skip_none(true)
+            )
+            .unwrap()
+            .encode_to_vec(),
+            buf.to_vec()
+        );
+    }
+}